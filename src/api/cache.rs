@@ -1,14 +1,317 @@
-use std::{any::Any, collections::HashMap, time::Instant};
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use parking_lot::{const_mutex, Mutex, MutexGuard};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::structs::json::data::RelationshipKind;
+use super::request::Validators;
+use super::structs::{
+    ids::{HasId, Id},
+    json::data::RelationshipKind,
+    AtHomeServerChapter, Author, Chapter, CoverArt, Manga, Tag, Volume,
+};
+use crate::paths;
 
-/// Simple cache with support for directed relationships
+/// Memory budget of a cache in bytes, see set_memory_budget.
+static MEMORY_BUDGET: AtomicUsize = AtomicUsize::new(64 * 1024 * 1024);
+/// The disk cache can hold this many times the memory budget, it keeps the objects that were
+/// evicted from memory.
+const DISK_BUDGET_FACTOR: usize = 4;
+/// How often expired data is looked for.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the cache is saved to disk when it changed.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Kinds of links from an object of a type to the objects that are part of it: they can't be
+/// reached without it, so they are removed with it. The tags, authors, etc. of a manga are shared
+/// with other objects and are kept.
+fn owned_links(type_id: TypeId) -> &'static [RelationshipKind] {
+    if type_id == TypeId::of::<Manga>() {
+        &[RelationshipKind::Volume]
+    } else if type_id == TypeId::of::<Chapter>() {
+        &[RelationshipKind::AtHome]
+    } else {
+        &[]
+    }
+}
+
+/// Set the (rough) memory budget of the api caches, the least recently used data is removed when
+/// a cache goes over it. This also limits the size of the disk cache (see DISK_BUDGET_FACTOR).
+pub fn set_memory_budget(bytes: usize) {
+    MEMORY_BUDGET.store(bytes, Ordering::Relaxed);
+}
+
+/// What happened to an object of a cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Inserted,
+    /// Inserted again, with newer data.
+    Updated,
+    Removed,
+}
+
+/// A change of an object in a cache, with the new value (except when it is removed).
+#[derive(Clone)]
+pub struct CacheChange {
+    pub id: Uuid,
+    pub kind: ChangeKind,
+    type_id: TypeId,
+    value: Option<Arc<dyn Any + Send + Sync>>,
+}
+
+impl CacheChange {
+    pub fn is<T: HasId + Any>(&self) -> bool {
+        self.type_id == TypeId::of::<T>()
+    }
+    /// The id of the object, if it is a T.
+    pub fn id<T: HasId + Any>(&self) -> Option<T::Id> {
+        self.is::<T>().then(|| T::Id::from_uuid(self.id))
+    }
+    /// The new value of the object, if it is a T.
+    pub fn value<T: HasId + Any>(&self) -> Option<&T> {
+        self.value.as_ref()?.downcast_ref()
+    }
+}
+
+/// What a subscriber wants to be told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subscription {
+    Id(Uuid),
+    Type(TypeId),
+}
+
+impl Subscription {
+    pub fn id(id: impl Id) -> Self {
+        Self::Id(id.uuid())
+    }
+    pub fn of<T: HasId + Any>() -> Self {
+        Self::Type(TypeId::of::<T>())
+    }
+    fn matches(&self, id: &Uuid, type_id: &TypeId) -> bool {
+        match self {
+            Self::Id(i) => i == id,
+            Self::Type(t) => t == type_id,
+        }
+    }
+}
+
+// Subscribers don't belong to a cache, they get the changes made by any of them.
+static SUBSCRIBERS: Mutex<Vec<(Vec<Subscription>, Sender<CacheChange>)>> = const_mutex(Vec::new());
+
+// Apis are made for about every task, they all use the same cache instead of loading their own.
+static SHARED: Mutex<Option<Arc<Mutex<ApiCache>>>> = const_mutex(None);
+
+/// Get the changes (made by any cache) of the objects matching one of the subscriptions. The
+/// subscription ends when the receiver is dropped.
+pub fn subscribe(subscriptions: Vec<Subscription>) -> Receiver<CacheChange> {
+    let (sender, receiver) = mpsc::channel();
+    SUBSCRIBERS.lock().push((subscriptions, sender));
+    receiver
+}
+
+fn interested(subs: &[Subscription], change: &CacheChange) -> bool {
+    subs.iter().any(|s| s.matches(&change.id, &change.type_id))
+}
+
+/// Tell the subscribers about changes.
+fn notify(changes: Vec<CacheChange>) {
+    if changes.is_empty() {
+        return;
+    }
+    let mut subscribers = SUBSCRIBERS.lock();
+    for change in changes {
+        // drop the subscribers that are gone
+        subscribers.retain(|(subs, sender)| {
+            !interested(subs, &change) || sender.send(change.clone()).is_ok()
+        });
+    }
+}
+
+/// Save the shared cache if it was used and changed. Called before exiting.
+pub fn flush() {
+    let shared = SHARED.lock().clone();
+    if let Some(cache) = shared {
+        if let Err(e) = CacheGuard::lock(&cache).save() {
+            log::error!("Couldn't save the api cache ({e})");
+        }
+    }
+}
+
+/// A locked cache, the changes made through it are sent to the subscribers once it's unlocked.
+pub struct CacheGuard<'a> {
+    guard: Option<MutexGuard<'a, ApiCache>>,
+}
+
+impl<'a> CacheGuard<'a> {
+    pub fn lock(cache: &'a Mutex<ApiCache>) -> Self {
+        Self {
+            guard: Some(cache.lock()),
+        }
+    }
+}
+
+impl Deref for CacheGuard<'_> {
+    type Target = ApiCache;
+
+    fn deref(&self) -> &ApiCache {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for CacheGuard<'_> {
+    fn deref_mut(&mut self) -> &mut ApiCache {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl Drop for CacheGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mut guard) = self.guard.take() {
+            let changes = std::mem::take(&mut guard.changes);
+            drop(guard);
+            notify(changes);
+        }
+    }
+}
+
+/// Simple cache with support for directed relationships. Relationships are only stored once, and
+/// removing an element removes the relationships to and from it.
 pub struct ApiCache {
     data: HashMap<Uuid, Box<dyn Any + Send + Sync>>,
     relationships: HashMap<Uuid, Vec<(Uuid, RelationshipKind)>>,
+    /// Reverse of the relationships: the objects that have at least one link to an object, so
+    /// that removing an object doesn't need to go through every relationship.
+    incoming: HashMap<Uuid, HashSet<Uuid>>,
     expiration_dates: HashMap<Uuid, Instant>,
+    /// When the data was gotten from the api, to know if it's stale (see HasId::TTL).
+    fetched: HashMap<Uuid, DateTime<Utc>>,
+    /// Changed since it was loaded, so it needs to be saved.
+    dirty: bool,
+    /// Size of each object (see HasId::size) and of all of them.
+    sizes: HashMap<Uuid, usize>,
+    size: usize,
+    /// Last access of each object, objects from the disk cache that weren't used have none.
+    accessed: HashMap<Uuid, u64>,
+    clock: u64,
+    last_sweep: Instant,
+    last_save: Option<Instant>,
+    /// Validators of the last responses, by url.
+    validators: HashMap<String, Validators>,
+    /// Objects removed since the last save, they must not come back from the disk cache.
+    removed: HashSet<Uuid>,
+    /// Cleared since the last save, what is on disk is dropped.
+    cleared: bool,
+    /// Changes someone is interested in, sent when the cache is unlocked (see CacheGuard).
+    changes: Vec<CacheChange>,
+    /// Stale objects that are being fetched again in the background, by any Api.
+    refreshing: HashSet<Uuid>,
+}
+
+/// An object of the cache as saved on disk.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "T: Serialize + DeserializeOwned")]
+struct Entry<T: HasId> {
+    id: T::Id,
+    fetched: DateTime<Utc>,
+    value: T,
+}
+
+/// The cache as saved on disk, only mangadex objects are kept (at home servers don't live long
+/// enough to be worth it).
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct CacheFile {
+    mangas: Vec<Entry<Manga>>,
+    chapters: Vec<Entry<Chapter>>,
+    tags: Vec<Entry<Tag>>,
+    covers: Vec<Entry<CoverArt>>,
+    authors: Vec<Entry<Author>>,
+    volumes: Vec<Entry<Volume>>,
+    relationships: HashMap<Uuid, Vec<(Uuid, RelationshipKind)>>,
+    validators: HashMap<String, Validators>,
+}
+
+fn cache_path() -> PathBuf {
+    paths::cache_dir().join("api.json")
+}
+
+impl CacheFile {
+    /// Add the objects (and relationships) of another file, the ones fetched the longest ago are
+    /// dropped when it would go over the size.
+    fn append(&mut self, mut other: CacheFile, size: usize) {
+        fn sizes<T: HasId>(
+            entries: &[Entry<T>],
+        ) -> impl Iterator<Item = (DateTime<Utc>, Uuid, usize)> + '_ {
+            entries
+                .iter()
+                .map(|e| (e.fetched, e.id.uuid(), e.value.size()))
+        }
+        let mut used: usize = sizes(&self.mangas)
+            .chain(sizes(&self.chapters))
+            .chain(sizes(&self.tags))
+            .chain(sizes(&self.covers))
+            .chain(sizes(&self.authors))
+            .chain(sizes(&self.volumes))
+            .map(|(_, _, s)| s)
+            .sum();
+        let mut candidates: Vec<_> = sizes(&other.mangas)
+            .chain(sizes(&other.chapters))
+            .chain(sizes(&other.tags))
+            .chain(sizes(&other.covers))
+            .chain(sizes(&other.authors))
+            .chain(sizes(&other.volumes))
+            .collect();
+        // most recent first
+        candidates.sort_unstable_by(|a, b| b.cmp(a));
+        let mut dropped = HashSet::new();
+        for (_, uuid, s) in candidates {
+            if used + s > size {
+                dropped.insert(uuid);
+            } else {
+                used += s;
+            }
+        }
+        if !dropped.is_empty() {
+            log::debug!("Dropping {} objects from the disk cache", dropped.len());
+        }
+
+        fn keep<T: HasId>(entries: &mut Vec<Entry<T>>, dropped: &HashSet<Uuid>) {
+            entries.retain(|e| !dropped.contains(&e.id.uuid()));
+        }
+        keep(&mut other.mangas, &dropped);
+        keep(&mut other.chapters, &dropped);
+        keep(&mut other.tags, &dropped);
+        keep(&mut other.covers, &dropped);
+        keep(&mut other.authors, &dropped);
+        keep(&mut other.volumes, &dropped);
+
+        self.mangas.append(&mut other.mangas);
+        self.chapters.append(&mut other.chapters);
+        self.tags.append(&mut other.tags);
+        self.covers.append(&mut other.covers);
+        self.authors.append(&mut other.authors);
+        self.volumes.append(&mut other.volumes);
+        for (a, rels) in other.relationships {
+            if !dropped.contains(&a) {
+                self.relationships.entry(a).or_insert(rels);
+            }
+        }
+    }
 }
 
 impl ApiCache {
@@ -17,79 +320,823 @@ impl ApiCache {
         Self {
             data: HashMap::new(),
             relationships: HashMap::new(),
+            incoming: HashMap::new(),
             expiration_dates: HashMap::new(),
+            fetched: HashMap::new(),
+            dirty: false,
+            sizes: HashMap::new(),
+            size: 0,
+            accessed: HashMap::new(),
+            clock: 0,
+            last_sweep: Instant::now(),
+            last_save: None,
+            validators: HashMap::new(),
+            removed: HashSet::new(),
+            cleared: false,
+            changes: Vec::new(),
+            refreshing: HashSet::new(),
         }
     }
-    /// Add element in cache
-    pub fn insert<T: Any + Send + Sync>(&mut self, uuid: Uuid, value: T, expire: Option<Instant>) {
-        log::trace!("Add {uuid} to cache");
+    /// Load the cache saved on disk, a missing file means an empty cache.
+    pub fn load() -> Result<Self> {
+        Self::load_from(&cache_path())
+    }
+    fn load_from(path: &Path) -> Result<Self> {
+        let mut cache = Self::new();
+        if let Some(file) = read_cache_file(path)? {
+            cache.merge(file, true);
+        }
+        cache.evict_to(MEMORY_BUDGET.load(Ordering::Relaxed));
+        cache.dirty = false;
+        Ok(cache)
+    }
+    /// Load the cache saved on disk, starting empty if it can't be read.
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_else(|e| {
+            log::error!("Couldn't load the api cache ({e})");
+            Self::new()
+        })
+    }
+    /// The cache shared by every Api of this process, loaded from disk on first use.
+    pub fn shared() -> Arc<Mutex<Self>> {
+        SHARED
+            .lock()
+            .get_or_insert_with(|| {
+                let mut cache = Self::load_or_default();
+                // the api object holds no data, having it isn't a change worth saving
+                cache.insert(super::API_ID, ());
+                cache.dirty = false;
+                Arc::new(Mutex::new(cache))
+            })
+            .clone()
+    }
+    /// Save the cache to disk if it changed. Another process might have saved in the meantime, so
+    /// what is on disk is merged in first: newer versions of our objects replace ours, and the
+    /// objects we don't have (evicted, or from the other process) are written back as they were,
+    /// unless we removed them.
+    pub fn save(&mut self) -> Result<()> {
+        self.save_to(&cache_path())
+    }
+    fn save_to(&mut self, path: &Path) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let others = match read_cache_file(path) {
+            // everything on disk was cleared
+            Ok(_) if self.cleared => CacheFile::default(),
+            Ok(Some(file)) => self.merge(file, false),
+            Ok(None) => CacheFile::default(),
+            // don't keep a broken file around
+            Err(e) => {
+                log::warn!("Overwriting the api cache ({e})");
+                CacheFile::default()
+            }
+        };
+        self.sweep();
+        self.evict_to(MEMORY_BUDGET.load(Ordering::Relaxed));
 
-        self.data.insert(uuid, Box::new(value));
+        let mut snapshot = self.snapshot();
+        snapshot.append(
+            others,
+            MEMORY_BUDGET.load(Ordering::Relaxed) * DISK_BUDGET_FACTOR,
+        );
 
-        self.relationships.insert(uuid, Vec::new());
+        paths::write_atomic(path, &serde_json::to_vec(&snapshot)?)?;
+        self.removed.clear();
+        self.cleared = false;
+        self.dirty = false;
+        self.last_save = Some(Instant::now());
+        Ok(())
+    }
+    /// Save if something changed and the last save is old enough, the app calls it from a timer.
+    pub fn save_if_due(&mut self) {
+        if self.dirty && self.last_save.is_none_or(|t| t.elapsed() > SAVE_INTERVAL) {
+            if let Err(e) = self.save() {
+                log::error!("Couldn't save the api cache ({e})");
+            }
+        }
+    }
+    /// Add what's in the file when it is newer than what we have, objects we don't have at all
+    /// are only added when loading, else they are returned with their relationships. Removed
+    /// objects are skipped.
+    fn merge(&mut self, file: CacheFile, load: bool) -> CacheFile {
+        let mut newer = HashSet::new();
+        let mut others = CacheFile {
+            mangas: self.merge_entries(file.mangas, load, &mut newer),
+            chapters: self.merge_entries(file.chapters, load, &mut newer),
+            tags: self.merge_entries(file.tags, load, &mut newer),
+            covers: self.merge_entries(file.covers, load, &mut newer),
+            authors: self.merge_entries(file.authors, load, &mut newer),
+            volumes: self.merge_entries(file.volumes, load, &mut newer),
+            ..Default::default()
+        };
+
+        for (url, v) in file.validators {
+            self.validators.entry(url).or_insert(v);
+        }
+        for (a, rels) in file.relationships {
+            if self.removed.contains(&a) {
+                continue;
+            }
+            let rels = rels.into_iter().filter(|(b, _)| !self.removed.contains(b));
+            // links of objects we have the newest version of are ours, the ones of objects we
+            // don't have are kept aside, the others (from the api object, authors, groups...)
+            // are added
+            if self.data.contains_key(&a) && !newer.contains(&a) {
+                continue;
+            }
+            if load || newer.contains(&a) || self.relationships.contains_key(&a) {
+                let rels: Vec<_> = rels.collect();
+                for rel in rels {
+                    self.add_link(a, rel);
+                }
+            } else {
+                others.relationships.insert(a, rels.collect());
+            }
+        }
+        others
+    }
+    /// Add the entries that are newer than ours (their ids are added to newer), the ones we don't
+    /// have are returned, or added if add_missing is true.
+    fn merge_entries<T: HasId + Any + Send + Sync>(
+        &mut self,
+        entries: Vec<Entry<T>>,
+        add_missing: bool,
+        newer: &mut HashSet<Uuid>,
+    ) -> Vec<Entry<T>> {
+        let mut missing = Vec::new();
+        for e in entries {
+            let uuid = e.id.uuid();
+            if self.removed.contains(&uuid) {
+                continue;
+            }
+            if !self.data.contains_key(&uuid) && !add_missing {
+                missing.push(e);
+                continue;
+            }
+            if self.data.contains_key(&uuid) && !self.fetched_before(&uuid, &e.fetched) {
+                continue;
+            }
+            // the file is newer, its relationships replace ours
+            self.retain_links(uuid, |_| false);
+            self.put(uuid, e.value, e.fetched);
+            newer.insert(uuid);
+        }
+        missing
+    }
+    /// If our version of an object was fetched before a date.
+    fn fetched_before(&self, uuid: &Uuid, date: &DateTime<Utc>) -> bool {
+        self.fetched.get(uuid).is_some_and(|ours| ours < date)
+    }
+    fn entries<T: HasId + Any + Clone>(&self) -> Vec<Entry<T>> {
+        self.data
+            .iter()
+            .filter_map(|(uuid, b)| {
+                Some(Entry {
+                    id: T::Id::from_uuid(*uuid),
+                    fetched: *self.fetched.get(uuid)?,
+                    value: b.downcast_ref::<T>()?.clone(),
+                })
+            })
+            .collect()
+    }
+    fn snapshot(&self) -> CacheFile {
+        let relationships = self
+            .relationships
+            .iter()
+            .filter(|(a, _)| !matches!(self.data.get(a), Some(b) if b.is::<AtHomeServerChapter>()))
+            .map(|(a, rels)| {
+                let rels = rels
+                    .iter()
+                    .filter(|(_, k)| *k != RelationshipKind::AtHome)
+                    .cloned()
+                    .collect();
+                (*a, rels)
+            })
+            .collect();
 
-        if let Some(instant) = expire {
-            self.expiration_dates.insert(uuid, instant);
+        CacheFile {
+            mangas: self.entries(),
+            chapters: self.entries(),
+            tags: self.entries(),
+            covers: self.entries(),
+            authors: self.entries(),
+            volumes: self.entries(),
+            relationships,
+            validators: self.validators.clone(),
+        }
+    }
+    /// If the data was fetched longer ago than its type's TTL. Data that isn't in the cache isn't
+    /// stale.
+    pub fn is_stale<T: HasId>(&self, id: &T::Id) -> bool {
+        match (T::TTL, self.fetched.get(&id.uuid())) {
+            (Some(ttl), Some(fetched)) => match chrono::Duration::from_std(ttl) {
+                Ok(ttl) => *fetched + ttl < Utc::now(),
+                Err(_) => false,
+            },
+            _ => false,
         }
     }
-    /// Remove element from the cache.
-    pub fn remove(&mut self, uuid: &Uuid) {
-        log::trace!("Remove {uuid} from cache");
-        self.data.remove(uuid);
-        self.relationships.remove(uuid);
-        self.expiration_dates.remove(uuid);
+    pub fn contains<T: HasId + Any>(&self, id: &T::Id) -> bool {
+        self.data.get(&id.uuid()).is_some_and(|v| v.is::<T>())
     }
-    /// Add a relationship from a to b.
-    pub fn link(&mut self, a: &Uuid, b: &Uuid, kind: RelationshipKind) {
-        log::trace!("Link {a} -> {b} ({kind:?})");
+    /// Mark data as just fetched (i.e. when the api says it didn't change), so that it isn't
+    /// stale anymore.
+    pub fn touch<T: HasId>(&mut self, id: &T::Id) {
+        if let Some(fetched) = self.fetched.get_mut(&id.uuid()) {
+            *fetched = Utc::now();
+            self.dirty = true;
+        }
+    }
+    /// Make an object look like it was fetched at a date.
+    #[cfg(test)]
+    pub fn set_fetched<T: HasId>(&mut self, id: &T::Id, at: DateTime<Utc>) {
+        self.fetched.insert(id.uuid(), at);
+    }
+    /// Mark a stale object as being fetched again, returns false if it already is.
+    pub fn start_refresh<T: HasId>(&mut self, id: &T::Id) -> bool {
+        self.refreshing.insert(id.uuid())
+    }
+    /// The object was fetched again (or that failed), it can be refreshed the next time it's
+    /// stale.
+    pub fn end_refresh<T: HasId>(&mut self, id: &T::Id) {
+        self.refreshing.remove(&id.uuid());
+    }
+    pub fn validators(&self, url: &str) -> Option<&Validators> {
+        self.validators.get(url)
+    }
+    pub fn set_validators(&mut self, url: String, validators: Validators) {
+        self.validators.insert(url, validators);
+        self.dirty = true;
+    }
+    /// Add element in cache, its relationships are kept if it was already there. If the cached
+    /// element has a more recent version, nothing is changed and false is returned. The element
+    /// expires after its type's HasId::EXPIRES_AFTER.
+    pub fn insert<T: HasId + Any + Send + Sync + Clone>(&mut self, id: T::Id, value: T) -> bool {
+        let uuid = id.uuid();
+
+        let cached_version = self
+            .data
+            .get(&uuid)
+            .and_then(|b| b.downcast_ref::<T>())
+            .and_then(|old| old.version());
+        if let (Some(old), Some(new)) = (cached_version, value.version()) {
+            if new < old {
+                log::trace!("Not adding {id} to cache, version {new} is older than {old}");
+                return false;
+            }
+        }
 
-        if let Some(rels) = self.relationships.get_mut(a) {
-            rels.push((*b, kind));
+        log::trace!("Add {id} to cache");
+        let kind = if self.data.contains_key(&uuid) {
+            ChangeKind::Updated
         } else {
-            self.relationships.insert(*a, vec![(*b, kind)]);
+            ChangeKind::Inserted
+        };
+        self.record(uuid, kind, TypeId::of::<T>(), || {
+            Some(Arc::new(value.clone()))
+        });
+        self.put(uuid, value, Utc::now());
+        let tick = self.tick();
+        self.accessed.insert(uuid, tick);
+        self.relationships.entry(uuid).or_default();
+
+        self.sweep_if_due();
+        let budget = MEMORY_BUDGET.load(Ordering::Relaxed);
+        if self.size > budget {
+            // a bit more, to not do it at each insert
+            self.evict_to(budget / 10 * 9);
         }
+        true
     }
-    /// Remove relationship from a to b
-    pub fn unlink(&mut self, a: &Uuid, b: &Uuid) {
-        log::trace!("Remove link between {a} and {b}");
-        if let Some(rels) = self.relationships.get_mut(a) {
-            for (i, e) in rels.iter().enumerate() {
-                if e.0 == *b {
-                    rels.remove(i);
-                    break;
+    fn put<T: HasId + Any + Send + Sync>(&mut self, uuid: Uuid, value: T, fetched: DateTime<Utc>) {
+        let size = value.size();
+        self.size += size;
+        if let Some(old) = self.sizes.insert(uuid, size) {
+            self.size -= old;
+        }
+        match T::EXPIRES_AFTER {
+            Some(d) => self.expiration_dates.insert(uuid, Instant::now() + d),
+            None => self.expiration_dates.remove(&uuid),
+        };
+        self.data.insert(uuid, Box::new(value));
+        self.fetched.insert(uuid, fetched);
+        self.removed.remove(&uuid);
+        self.dirty = true;
+    }
+    /// Keep a change for the subscribers, the value is only made when someone wants it.
+    fn record(
+        &mut self,
+        id: Uuid,
+        kind: ChangeKind,
+        type_id: TypeId,
+        value: impl FnOnce() -> Option<Arc<dyn Any + Send + Sync>>,
+    ) {
+        let mut change = CacheChange {
+            id,
+            kind,
+            type_id,
+            value: None,
+        };
+        let wanted = SUBSCRIBERS
+            .lock()
+            .iter()
+            .any(|(subs, _)| interested(subs, &change));
+        if wanted {
+            change.value = value();
+            self.changes.push(change);
+        }
+    }
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+    /// Sweep if it wasn't done for a while. Called on access, and by the app from a timer so that
+    /// the expired data doesn't wait for the next insert.
+    pub fn sweep_if_due(&mut self) {
+        if self.last_sweep.elapsed() > SWEEP_INTERVAL {
+            self.sweep();
+        }
+    }
+    /// Remove the expired data.
+    pub fn sweep(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<Uuid> = self
+            .expiration_dates
+            .iter()
+            .filter(|(_, exp)| **exp < now)
+            .map(|(uuid, _)| *uuid)
+            .collect();
+        if !expired.is_empty() {
+            log::trace!("Removing {} expired objects", expired.len());
+        }
+        for uuid in expired {
+            self.remove_uuid(uuid);
+        }
+        self.last_sweep = now;
+    }
+    /// Remove the least recently used objects until the cache fits in the size. Objects without a
+    /// size (the api object) are kept.
+    fn evict_to(&mut self, size: usize) {
+        if self.size <= size {
+            return;
+        }
+        let mut candidates: Vec<_> = self
+            .sizes
+            .iter()
+            .filter(|(_, s)| **s > 0)
+            .map(|(uuid, _)| {
+                let accessed = self.accessed.get(uuid).copied().unwrap_or(0);
+                (accessed, self.fetched.get(uuid).copied(), *uuid)
+            })
+            .collect();
+        candidates.sort_unstable();
+
+        let mut evicted = 0;
+        for (_, _, uuid) in candidates {
+            if self.size <= size {
+                break;
+            }
+            self.evict(uuid);
+            evicted += 1;
+        }
+        log::debug!("Evicted {evicted} objects from the api cache");
+    }
+    /// Remove an object, and the lists it was in (i.e. the chapters of its manga), so that they
+    /// are fetched again instead of being incomplete.
+    fn evict(&mut self, uuid: Uuid) {
+        let sources: Vec<Uuid> = self
+            .incoming
+            .get(&uuid)
+            .map(|s| s.iter().copied().collect())
+            .unwrap_or_default();
+        for a in sources {
+            let kinds: Vec<_> = self.relationships[&a]
+                .iter()
+                .filter(|(b, _)| *b == uuid)
+                .map(|(_, k)| k.clone())
+                .collect();
+            self.retain_links(a, |(_, k)| !kinds.contains(k));
+        }
+        // still valid (i.e. in the disk cache), so no notification
+        self.forget(uuid);
+    }
+    /// Remove element from the cache, with its relationships and the relationships to it. The
+    /// objects that are part of it (see owned_links) are removed too. It is also removed from the
+    /// disk cache on the next save.
+    pub fn remove<T: HasId>(&mut self, id: &T::Id) {
+        log::trace!("Remove {id} from cache");
+        self.remove_uuid(id.uuid());
+    }
+    fn remove_uuid(&mut self, uuid: Uuid) {
+        let mut owned = Vec::new();
+        if let Some(type_id) = self.data.get(&uuid).map(|b| (**b).type_id()) {
+            self.record(uuid, ChangeKind::Removed, type_id, || None);
+            let kinds = owned_links(type_id);
+            owned.extend(
+                self.relationships
+                    .get(&uuid)
+                    .into_iter()
+                    .flatten()
+                    .filter(|(_, k)| kinds.contains(k))
+                    .map(|(b, _)| *b),
+            );
+        }
+        self.forget(uuid);
+        self.removed.insert(uuid);
+
+        for o in owned {
+            self.remove_uuid(o);
+        }
+    }
+    fn forget(&mut self, uuid: Uuid) {
+        self.data.remove(&uuid);
+        self.retain_links(uuid, |_| false);
+        self.relationships.remove(&uuid);
+        self.expiration_dates.remove(&uuid);
+        self.fetched.remove(&uuid);
+        self.accessed.remove(&uuid);
+        if let Some(size) = self.sizes.remove(&uuid) {
+            self.size -= size;
+        }
+        self.dirty = true;
+
+        for a in self.incoming.remove(&uuid).unwrap_or_default() {
+            if let Some(rels) = self.relationships.get_mut(&a) {
+                rels.retain(|(b, _)| *b != uuid);
+            }
+        }
+    }
+    /// Add a relationship (and its reverse), returns false if it already existed.
+    fn add_link(&mut self, a: Uuid, rel: (Uuid, RelationshipKind)) -> bool {
+        let rels = self.relationships.entry(a).or_default();
+        if rels.contains(&rel) {
+            return false;
+        }
+        self.incoming.entry(rel.0).or_default().insert(a);
+        rels.push(rel);
+        true
+    }
+    /// Keep the relationships of a that match the predicate, the reverse relationships are
+    /// updated. Returns false if a has no relationships.
+    fn retain_links(
+        &mut self,
+        a: Uuid,
+        mut keep: impl FnMut(&(Uuid, RelationshipKind)) -> bool,
+    ) -> bool {
+        let rels = match self.relationships.get_mut(&a) {
+            Some(rels) => rels,
+            None => return false,
+        };
+        let mut removed = Vec::new();
+        rels.retain(|rel| {
+            let k = keep(rel);
+            if !k {
+                removed.push(rel.0);
+            }
+            k
+        });
+        for b in removed {
+            // a might still have links of another kind to b
+            if rels.iter().any(|(x, _)| *x == b) {
+                continue;
+            }
+            if let Some(sources) = self.incoming.get_mut(&b) {
+                sources.remove(&a);
+                if sources.is_empty() {
+                    self.incoming.remove(&b);
                 }
             }
         }
+        true
     }
-    /// Get (clone) data with specific uuid and type, returns None if either are wrong.
-    pub fn get<T: Any + Clone>(&mut self, uuid: &Uuid) -> Option<T> {
-        log::trace!("Access {uuid}");
+    /// Add a relationship from a to b, if it doesn't already exist.
+    pub fn link(&mut self, a: &impl Id, b: &impl Id, kind: RelationshipKind) {
+        log::trace!("Link {a} -> {b} ({kind:?})");
+
+        if self.add_link(a.uuid(), (b.uuid(), kind)) {
+            self.dirty = true;
+        }
+    }
+    /// Remove relationships (of any kind) from a to b
+    pub fn unlink(&mut self, a: &impl Id, b: &impl Id) {
+        log::trace!("Remove link between {a} and {b}");
+        let b = b.uuid();
+        if self.retain_links(a.uuid(), |(x, _)| *x != b) {
+            self.dirty = true;
+        }
+    }
+    /// Remove all the relationships of a kind from a (i.e. before linking the ones of a newer
+    /// version).
+    pub fn unlink_kind(&mut self, a: &impl Id, kind: &RelationshipKind) {
+        log::trace!("Remove links of {a} ({kind:?})");
+        if self.retain_links(a.uuid(), |(_, k)| k != kind) {
+            self.dirty = true;
+        }
+    }
+    /// Get (clone) data with specific id and type, returns None if either are wrong.
+    pub fn get<T: HasId + Any + Clone>(&mut self, id: &T::Id) -> Option<T> {
+        log::trace!("Access {id}");
+        let uuid = id.uuid();
+        self.sweep_if_due();
 
         // delete if expired
-        if let Some(exp) = self.expiration_dates.get(uuid) {
+        if let Some(exp) = self.expiration_dates.get(&uuid) {
             if Instant::now().cmp(exp) == std::cmp::Ordering::Greater {
                 log::trace!("Removing expired data");
-                self.remove(uuid);
+                self.remove::<T>(id);
                 return None;
             }
         }
 
-        match self.data.get(uuid) {
-            Some(boxed) => boxed.downcast_ref::<T>().cloned(),
-            None => None,
-        }
+        let value = self.data.get(&uuid)?.downcast_ref::<T>()?.clone();
+        let tick = self.tick();
+        self.accessed.insert(uuid, tick);
+        Some(value)
     }
-    /// Get uuid of objects linked to another.
-    pub fn get_linked(&self, uuid: &Uuid, kind: RelationshipKind) -> Option<Vec<Uuid>> {
-        log::trace!("Access related to {uuid} ({kind:?})");
-        self.relationships.get(uuid).map(|rels| {
+    /// Get ids of objects linked to another.
+    pub fn get_linked<I: Id>(&self, id: &impl Id, kind: RelationshipKind) -> Option<Vec<I>> {
+        log::trace!("Access related to {id} ({kind:?})");
+        self.relationships.get(&id.uuid()).map(|rels| {
             rels.iter()
-                .filter_map(|x| if x.1 == kind { Some(x.0) } else { None })
+                .filter_map(|x| {
+                    if x.1 == kind {
+                        Some(I::from_uuid(x.0))
+                    } else {
+                        None
+                    }
+                })
                 .collect()
         })
     }
+    /// Get (clone) objects of type T linked to another, with their ids. Objects that aren't in
+    /// the cache (or expired) are skipped.
+    pub fn related<T: HasId + Any + Clone>(
+        &mut self,
+        id: &impl Id,
+        kind: RelationshipKind,
+    ) -> Vec<(T::Id, T)> {
+        self.get_linked::<T::Id>(id, kind)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|id| self.get::<T>(&id).map(|v| (id, v)))
+            .collect()
+    }
     /// clear the cache
     pub fn clear(&mut self) {
         self.data.clear();
+        self.relationships.clear();
+        self.incoming.clear();
+        self.expiration_dates.clear();
+        self.fetched.clear();
+        self.sizes.clear();
+        self.size = 0;
+        self.accessed.clear();
+        self.validators.clear();
+        self.removed.clear();
+        self.cleared = true;
+        self.dirty = true;
+    }
+}
+
+fn read_cache_file(path: &Path) -> Result<Option<CacheFile>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::structs::{
+        ids::{AtHomeId, AuthorId, ChapterId, GroupId, MangaId},
+        test_chapter,
+    };
+
+    /// The reverse relationships, as they should be.
+    fn expected_incoming(cache: &ApiCache) -> HashMap<Uuid, HashSet<Uuid>> {
+        let mut res: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+        for (a, rels) in &cache.relationships {
+            for (b, _) in rels {
+                res.entry(*b).or_default().insert(*a);
+            }
+        }
+        res
+    }
+
+    fn ids<I: Id>(n: usize) -> Vec<I> {
+        (0..n).map(|_| I::from_uuid(Uuid::new_v4())).collect()
+    }
+
+    #[test]
+    fn reverse_links() {
+        let mut cache = ApiCache::new();
+        let mangas: Vec<MangaId> = ids(2);
+        let chapters: Vec<ChapterId> = ids(4);
+        for c in &chapters {
+            cache.link(&mangas[0], c, RelationshipKind::Chapter);
+            cache.link(c, &mangas[0], RelationshipKind::Manga);
+        }
+        cache.link(&mangas[1], &chapters[0], RelationshipKind::Chapter);
+        cache.link(&mangas[1], &chapters[0], RelationshipKind::Volume);
+        assert_eq!(cache.incoming, expected_incoming(&cache));
+
+        // still linked with another kind
+        cache.unlink_kind(&mangas[1], &RelationshipKind::Volume);
+        assert!(cache.incoming[&chapters[0].uuid()].contains(&mangas[1].uuid()));
+        cache.unlink(&mangas[1], &chapters[0]);
+        assert!(!cache.incoming[&chapters[0].uuid()].contains(&mangas[1].uuid()));
+        assert_eq!(cache.incoming, expected_incoming(&cache));
+
+        cache.remove::<Chapter>(&chapters[1]);
+        assert_eq!(
+            cache.get_linked::<ChapterId>(&mangas[0], RelationshipKind::Chapter),
+            Some(vec![chapters[0], chapters[2], chapters[3]])
+        );
+        assert!(!cache.incoming.contains_key(&chapters[1].uuid()));
+        assert_eq!(cache.incoming, expected_incoming(&cache));
+
+        cache.remove::<Manga>(&mangas[0]);
+        assert!(cache.incoming.is_empty());
+        assert_eq!(cache.incoming, expected_incoming(&cache));
+    }
+
+    #[test]
+    fn removal_cascades_to_parts() {
+        let mut cache = ApiCache::new();
+        let chapter: ChapterId = Uuid::new_v4().into();
+        let at_home: AtHomeId = Uuid::new_v4().into();
+        let author_id: AuthorId = Uuid::new_v4().into();
+        cache.insert(chapter, test_chapter(None, Some("1")));
+        cache.insert(author_id, author("a"));
+        cache.insert(at_home, at_home_chapter());
+        cache.link(&chapter, &at_home, RelationshipKind::AtHome);
+        cache.link(&chapter, &author_id, RelationshipKind::User);
+
+        cache.remove::<Chapter>(&chapter);
+        assert!(!cache.contains::<AtHomeServerChapter>(&at_home));
+        assert!(cache.removed.contains(&at_home.uuid()));
+        // not a part of the chapter
+        assert!(cache.contains::<Author>(&author_id));
+        assert_eq!(cache.incoming, expected_incoming(&cache));
+    }
+
+    #[test]
+    fn evict_drops_lists() {
+        let mut cache = ApiCache::new();
+        let manga: MangaId = Uuid::new_v4().into();
+        let group: GroupId = Uuid::new_v4().into();
+        let chapters: Vec<ChapterId> = ids(3);
+        for c in &chapters {
+            cache.link(&manga, c, RelationshipKind::Chapter);
+            cache.link(c, &manga, RelationshipKind::Manga);
+        }
+        cache.link(&manga, &group, RelationshipKind::ScanlationGroup);
+
+        cache.evict(chapters[0].uuid());
+        // the chapter list is incomplete, so it is dropped, other links stay
+        assert_eq!(
+            cache.get_linked::<ChapterId>(&manga, RelationshipKind::Chapter),
+            Some(vec![])
+        );
+        assert_eq!(
+            cache.get_linked::<GroupId>(&manga, RelationshipKind::ScanlationGroup),
+            Some(vec![group])
+        );
+        assert_eq!(cache.incoming, expected_incoming(&cache));
+    }
+
+    #[test]
+    fn sweep_on_access() {
+        let mut cache = ApiCache::new();
+        let chapter: ChapterId = Uuid::new_v4().into();
+        let at_home: AtHomeId = Uuid::new_v4().into();
+        let author_id: AuthorId = Uuid::new_v4().into();
+        cache.insert(author_id, author("a"));
+        cache.insert(at_home, at_home_chapter());
+        cache.link(&chapter, &at_home, RelationshipKind::AtHome);
+
+        let past = Instant::now() - Duration::from_secs(1);
+        cache.expiration_dates.insert(at_home.uuid(), past);
+        cache.get::<Author>(&author_id);
+        // not due yet
+        assert!(cache.contains::<AtHomeServerChapter>(&at_home));
+
+        cache.last_sweep = past - SWEEP_INTERVAL;
+        cache.get::<Author>(&author_id);
+        assert!(!cache.contains::<AtHomeServerChapter>(&at_home));
+        assert_eq!(
+            cache.get_linked::<AtHomeId>(&chapter, RelationshipKind::AtHome),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn changes_are_sent_once_unlocked() {
+        let a: AuthorId = Uuid::new_v4().into();
+        let b: AuthorId = Uuid::new_v4().into();
+        let changes = subscribe(vec![Subscription::Id(a.uuid())]);
+
+        let shared = Mutex::new(ApiCache::new());
+        let mut cache = CacheGuard::lock(&shared);
+        cache.insert(a, author("a"));
+        cache.insert(b, author("b"));
+        cache.remove::<Author>(&a);
+        assert!(changes.try_recv().is_err());
+        drop(cache);
+
+        let inserted = changes.try_recv().unwrap();
+        assert_eq!(inserted.id::<Author>(), Some(a));
+        assert_eq!(inserted.value::<Author>().unwrap().name, "a");
+        let removed = changes.try_recv().unwrap();
+        assert_eq!(removed.kind, ChangeKind::Removed);
+        // b isn't wanted by anyone
+        assert!(changes.try_recv().is_err());
+        assert!(shared.lock().changes.is_empty());
+    }
+
+    fn author(name: &str) -> Author {
+        Author {
+            name: name.to_owned(),
+            version: 1,
+        }
+    }
+
+    fn at_home_chapter() -> AtHomeServerChapter {
+        AtHomeServerChapter {
+            base_url: String::new(),
+            data: vec![],
+            data_saver: vec![],
+            hash: String::new(),
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("mngdx-test-{}.json", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn removed_stays_removed() {
+        let path = temp_path();
+        let [a, b]: [AuthorId; 2] = [(); 2].map(|_| Uuid::new_v4().into());
+        let manga: MangaId = Uuid::new_v4().into();
+
+        let mut cache = ApiCache::new();
+        cache.insert(a, author("a"));
+        cache.insert(b, author("b"));
+        cache.link(&manga, &a, RelationshipKind::Author);
+        cache.link(&manga, &b, RelationshipKind::Author);
+        cache.save_to(&path).unwrap();
+
+        cache.remove::<Author>(&a);
+        cache.save_to(&path).unwrap();
+        assert!(!cache.contains::<Author>(&a));
+
+        let mut loaded = ApiCache::load_from(&path).unwrap();
+        assert!(loaded.get::<Author>(&a).is_none());
+        assert_eq!(loaded.get::<Author>(&b).unwrap().name, "b");
+        assert_eq!(
+            loaded.get_linked::<AuthorId>(&manga, RelationshipKind::Author),
+            Some(vec![b])
+        );
+
+        cache.clear();
+        cache.save_to(&path).unwrap();
+        let loaded = ApiCache::load_from(&path).unwrap();
+        assert!(!loaded.contains::<Author>(&a) && !loaded.contains::<Author>(&b));
+        assert!(loaded.relationships.is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn evicted_stays_on_disk() {
+        let path = temp_path();
+        let [a, b]: [AuthorId; 2] = [(); 2].map(|_| Uuid::new_v4().into());
+        let manga: MangaId = Uuid::new_v4().into();
+
+        let mut cache = ApiCache::new();
+        cache.insert(a, author("a"));
+        cache.insert(b, author("b"));
+        cache.link(&a, &manga, RelationshipKind::Manga);
+        cache.save_to(&path).unwrap();
+
+        cache.evict(a.uuid());
+        cache.insert(b, author("b2"));
+        cache.save_to(&path).unwrap();
+        // not brought back in memory
+        assert!(!cache.contains::<Author>(&a));
+
+        let mut loaded = ApiCache::load_from(&path).unwrap();
+        assert_eq!(loaded.get::<Author>(&a).unwrap().name, "a");
+        assert_eq!(loaded.get::<Author>(&b).unwrap().name, "b2");
+        assert_eq!(
+            loaded.get_linked::<MangaId>(&a, RelationshipKind::Manga),
+            Some(vec![manga])
+        );
+
+        fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,103 @@
+// Whether mangadex can be reached, shared by all the Apis. Requests fail right away while offline,
+// and mangadex is pinged again from time to time (see Api::ping).
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::{const_mutex, Mutex};
+
+use super::ApiError;
+
+/// How long to wait before trying again when offline.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+static ONLINE: AtomicBool = AtomicBool::new(true);
+static LAST_CHECK: Mutex<Option<Instant>> = const_mutex(None);
+
+pub fn is_online() -> bool {
+    ONLINE.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set_online(online: bool) {
+    *LAST_CHECK.lock() = Some(Instant::now());
+    if ONLINE.swap(online, Ordering::Relaxed) != online {
+        if online {
+            log::info!("Back online");
+        } else {
+            log::warn!("Mangadex can't be reached, going offline");
+        }
+    }
+}
+
+/// The error of a request that couldn't be sent, mangadex not answering at all means we are
+/// offline.
+pub(super) fn request_error(e: reqwest::Error) -> ApiError {
+    if e.is_connect() || e.is_timeout() {
+        set_online(false);
+        ApiError::Offline
+    } else {
+        e.into()
+    }
+}
+
+/// If mangadex should be pinged again, when offline for long enough.
+pub(super) fn should_retry() -> bool {
+    !is_online()
+        && LAST_CHECK
+            .lock()
+            .is_none_or(|t| t.elapsed() > RETRY_INTERVAL)
+}
+
+/// Taken by the tests that change the connectivity status, as it's shared by all of them.
+#[cfg(test)]
+pub static TEST_STATUS: Mutex<()> = const_mutex(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn unreachable_means_offline() {
+        let _status = TEST_STATUS.lock();
+        set_online(true);
+
+        // nothing listens on the port of a listener that was dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let e = rt()
+            .block_on(reqwest::get(format!("http://127.0.0.1:{port}/ping")))
+            .unwrap_err();
+        assert!(matches!(request_error(e), ApiError::Offline));
+        assert!(!is_online());
+        // it was just checked
+        assert!(!should_retry());
+
+        *LAST_CHECK.lock() = Some(Instant::now() - RETRY_INTERVAL * 2);
+        assert!(should_retry());
+
+        set_online(true);
+        assert!(!should_retry());
+    }
+
+    #[test]
+    fn other_errors_stay_online() {
+        let _status = TEST_STATUS.lock();
+        set_online(true);
+
+        let e = rt().block_on(reqwest::get("not an url")).unwrap_err();
+        assert!(matches!(request_error(e), ApiError::Request(_)));
+        assert!(is_online());
+    }
+}
@@ -1,24 +1,36 @@
-use std::fmt::Display;
+use std::{any::Any, fmt::Display, future::Future, sync::Arc};
 
+use parking_lot::Mutex;
 use request::*;
 use reqwest::Url;
+use serde::{de::DeserializeOwned, Serialize};
 use tokio::time::Duration;
 use uuid::Uuid;
 
 use self::structs::*;
 use self::{
-    cache::ApiCache,
+    cache::{ApiCache, CacheGuard},
+    structs::chapters::ChapterList,
+    structs::ids::{ApiId, AuthorId, ChapterId, CoverId, HasId, MangaId, TagId, VolumeId},
     structs::json::{body, data::RelationshipKind, responses},
+    structs::lang_codes::LanguageCode,
+    structs::tags::TagCatalog,
+    writes::{PendingWrite, PendingWrites},
 };
+use crate::images;
 
 pub mod cache;
+pub mod connectivity;
+mod query;
 mod request;
+pub mod search;
 pub mod structs;
+pub mod writes;
 
 const API_URL: &str = "https://api.mangadex.org";
-// uuid of the "api object" in the api cache. This object doesn't hold any data itself, but is
+// id of the "api object" in the api cache. This object doesn't hold any data itself, but is
 // linked to objects (i.e. the tags from the /manga/tag endpoint, as a way to keep them accessible.
-const API_UUID: Uuid = Uuid::nil();
+const API_ID: ApiId = ApiId(Uuid::nil());
 
 #[derive(Debug)]
 pub enum ApiError {
@@ -29,6 +41,8 @@ pub enum ApiError {
     Them,
     RateLimit(Duration),
     Request(reqwest::Error),
+    Query(query::QueryError),
+    Offline,
 }
 impl Display for ApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -45,6 +59,8 @@ impl Display for ApiError {
             ApiError::Request(_) => {
                 write!(f, "There was a (reqwest) error when sending the request.")
             }
+            ApiError::Query(_) => write!(f, "The query of the request couldn't be built."),
+            ApiError::Offline => write!(f, "Mangadex can't be reached."),
         }
     }
 }
@@ -55,18 +71,56 @@ impl From<reqwest::Error> for ApiError {
     }
 }
 
+impl From<query::QueryError> for ApiError {
+    fn from(v: query::QueryError) -> Self {
+        Self::Query(v)
+    }
+}
+
 impl std::error::Error for ApiError {}
 
+impl ApiError {
+    /// If sending the request again later could work. Read markers can be sent twice, so a
+    /// request that failed without us knowing why is tried again.
+    pub fn is_transient(&self) -> bool {
+        !matches!(
+            self,
+            ApiError::BadRequest | ApiError::NotFound | ApiError::Query(_)
+        )
+    }
+}
+
 /// Api helper with cache
 pub struct Api {
     refresh: Option<String>,
     session: Option<String>,
     api: Url,
     client: reqwest::Client,
-    pub cache: ApiCache,
+    cache: Arc<Mutex<ApiCache>>,
     data_saver: bool,
 }
 
+/// Run a fetch with a new Api in the background, to refresh stale data that was returned from the
+/// cache. What it gets goes to the cache, which is saved to disk from a timer. The object can be
+/// refreshed again once it's done.
+fn refresh_later<T, F, Fut>(cache: Arc<Mutex<ApiCache>>, id: T::Id, f: F)
+where
+    T: HasId + 'static,
+    F: FnOnce(Api) -> Fut,
+    Fut: Future<Output = Result<(), ApiError>> + Send + 'static,
+{
+    log::debug!("Refreshing {id}");
+    let mut api = Api::new();
+    api.cache = cache.clone();
+    let fut = f(api);
+    tokio::spawn(async move {
+        if let Err(e) = fut.await {
+            log::warn!("Couldn't refresh {id} ({e})");
+        }
+        CacheGuard::lock(&cache).end_refresh::<T>(&id);
+    });
+}
+
 impl Api {
     fn endpoint(&self, path: &str) -> Url {
         let mut copy = self.api.clone();
@@ -76,18 +130,136 @@ impl Api {
     }
 
     pub fn new() -> Self {
-        let mut cache = ApiCache::new();
-        cache.insert(API_UUID, (), None);
         Self {
             refresh: None,
             session: None,
             api: Url::parse(API_URL).unwrap(),
-            client: reqwest::Client::new(),
-            cache,
+            client: reqwest::Client::builder()
+                .connect_timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+            cache: ApiCache::shared(),
             data_saver: false,
         }
     }
 
+    /// The cache, shared with the other Apis. Don't hold on to it while calling into the Api.
+    pub fn cache(&self) -> CacheGuard<'_> {
+        CacheGuard::lock(&self.cache)
+    }
+
+    /// Send a request, when the objects it gives are all cached only get the response if it
+    /// changed. None means that they didn't, they aren't stale anymore then.
+    async fn send_revalidate<T: HasId + Any, B: DeserializeOwned>(
+        &mut self,
+        req: ApiRequest<(), B>,
+        cached: &[T::Id],
+    ) -> Result<Option<B>, ApiError> {
+        if cached.is_empty() || !cached.iter().all(|id| self.cache().contains::<T>(id)) {
+            return req.send_validated(self).await.map(Some);
+        }
+        let res = req.send_conditional(self).await?;
+        if res.is_none() {
+            for id in cached {
+                self.cache().touch::<T>(id);
+            }
+        }
+        Ok(res)
+    }
+
+    /// If the object is stale, fetch it again in the background, unless another Api sharing the
+    /// cache already does.
+    fn refresh_if_stale<T, F, Fut>(&mut self, id: T::Id, f: F)
+    where
+        T: HasId + 'static,
+        F: FnOnce(Api) -> Fut,
+        Fut: Future<Output = Result<(), ApiError>> + Send + 'static,
+    {
+        // no point while offline, the cached data is all we have
+        if !connectivity::is_online() {
+            return;
+        }
+        let start = {
+            let mut cache = self.cache();
+            cache.is_stale::<T>(&id) && cache.start_refresh::<T>(&id)
+        };
+        if start {
+            refresh_later::<T, _, _>(self.cache.clone(), id, f);
+        }
+    }
+
+    /// Check if mangadex can be reached, and update the connectivity status.
+    pub async fn ping(&mut self) -> bool {
+        let online = match self.client.get(self.endpoint("/ping")).send().await {
+            Ok(res) => res.status().is_success(),
+            Err(e) => {
+                log::debug!("Ping failed ({e})");
+                false
+            }
+        };
+        connectivity::set_online(online);
+        online
+    }
+
+    /// Send a change to mangadex, if it can't be reached it is queued and sent again later by
+    /// replay_writes.
+    async fn send_write<A: Serialize>(
+        &mut self,
+        endpoint: String,
+        body: A,
+    ) -> Result<(), ApiError> {
+        let body = serde_json::to_value(body).map_err(|_| ApiError::Other)?;
+        let write = PendingWrite::new(endpoint, body);
+        match self.post_write(&write).await {
+            Err(ApiError::Offline) => {
+                log::info!("Offline, queueing the write to {}", write.endpoint);
+                PendingWrites::push(write).map_err(|e| {
+                    log::error!("Couldn't queue the write ({e})");
+                    ApiError::Offline
+                })
+            }
+            res => res,
+        }
+    }
+
+    /// Send a write, without queueing it.
+    async fn post_write(&mut self, write: &PendingWrite) -> Result<(), ApiError> {
+        ApiRequest::<serde_json::Value, serde_json::Value> {
+            endpoint: write.endpoint.clone(),
+            kind: ApiRequestKind::Post,
+            body: ApiRequestBody::Json(write.body.clone()),
+            ..Default::default()
+        }
+        .send(self)
+        .await
+        .map(drop)
+    }
+
+    /// Send the writes that were queued while offline, returns how many were sent.
+    pub async fn replay_writes(&mut self) -> usize {
+        writes::replay(&writes::pending_path(), self, |api, write| {
+            Box::pin(api.post_write(write))
+        })
+        .await
+    }
+
+    /// Mark chapters of a manga as read / unread, queued if offline.
+    pub async fn mark_read(
+        &mut self,
+        manga: MangaId,
+        read: Vec<ChapterId>,
+        unread: Vec<ChapterId>,
+    ) -> Result<(), ApiError> {
+        self.send_write(
+            format!("/manga/{}/read", manga),
+            body::MangaRead {
+                chapter_ids_read: read,
+                chapter_ids_unread: unread,
+            },
+        )
+        .await
+    }
+
     pub fn enable_data_saver(&mut self) {
         self.data_saver = true;
     }
@@ -148,55 +320,111 @@ impl Api {
         }
     }
 
-    pub async fn tag_view(&mut self, uuid: Uuid) -> Result<Tag, ApiError> {
+    pub async fn tag_view(&mut self, id: TagId) -> Result<Tag, ApiError> {
         // make sure we have all the tags in cache
         self.manga_all_tags().await?;
 
-        self.cache.get::<Tag>(&uuid).ok_or(ApiError::Other)
+        self.cache().get::<Tag>(&id).ok_or(ApiError::Other)
     }
 
-    pub async fn manga_all_tags(&mut self) -> Result<Vec<Uuid>, ApiError> {
-        if let Some(cached) = self.cache.get_linked(&API_UUID, RelationshipKind::Tag) {
+    pub async fn manga_all_tags(&mut self) -> Result<Vec<TagId>, ApiError> {
+        let cached = self
+            .cache()
+            .get_linked::<TagId>(&API_ID, RelationshipKind::Tag);
+        if let Some(cached) = cached {
             if !cached.is_empty() {
+                self.refresh_if_stale::<Tag, _, _>(cached[0], |mut api| async move {
+                    api.fetch_all_tags().await.map(drop)
+                });
                 return Ok(cached);
             }
         }
-
-        let res = ApiRequest::<(), responses::MangaTag> {
+        self.fetch_all_tags().await
+    }
+    async fn fetch_all_tags(&mut self) -> Result<Vec<TagId>, ApiError> {
+        let req = ApiRequest::<(), responses::MangaTag> {
             endpoint: "/manga/tag".to_owned(),
             ..Default::default()
+        };
+        let cached: Vec<TagId> = self
+            .cache()
+            .get_linked(&API_ID, RelationshipKind::Tag)
+            .unwrap_or_default();
+
+        match self.send_revalidate::<Tag, _>(req, &cached).await? {
+            Some(res) => Ok(res.store(&mut self.cache())),
+            None => Ok(cached),
         }
-        .send(self)
-        .await?;
+    }
 
-        Ok(res.store(&mut self.cache))
+    /// All the tags, with lookups by name and by group.
+    pub async fn tag_catalog(
+        &mut self,
+        languages: &[LanguageCode],
+    ) -> Result<TagCatalog, ApiError> {
+        self.manga_all_tags().await?;
+
+        Ok(TagCatalog::new(
+            self.cache().related::<Tag>(&API_ID, RelationshipKind::Tag),
+            languages,
+        ))
     }
 
-    pub async fn manga_view(&mut self, uuid: Uuid) -> Result<Manga, ApiError> {
-        if let Some(cached) = self.cache.get::<Manga>(&uuid) {
+    pub async fn manga_view(&mut self, id: MangaId) -> Result<Manga, ApiError> {
+        let cached = self.cache().get::<Manga>(&id);
+        if let Some(cached) = cached {
+            self.refresh_if_stale::<Manga, _, _>(id, |mut api| async move {
+                api.fetch_manga(id).await.map(drop)
+            });
             return Ok(cached);
         }
-
-        let res = ApiRequest::<(), responses::MangaView> {
-            endpoint: format!("/manga/{uuid}"),
+        self.fetch_manga(id).await
+    }
+    async fn fetch_manga(&mut self, id: MangaId) -> Result<Manga, ApiError> {
+        let req = ApiRequest::<(), responses::MangaView> {
+            endpoint: format!("/manga/{id}"),
             ..Default::default()
+        };
+        match self.send_revalidate::<Manga, _>(req, &[id]).await? {
+            Some(res) => Ok(res.store(&mut self.cache())),
+            None => self.cache().get(&id).ok_or(ApiError::Other),
         }
-        .send_simple(self)
-        .await?;
-        Ok(res.store(&mut self.cache))
     }
 
-    pub async fn manga_tags(&mut self, uuid: Uuid) -> Result<Vec<Uuid>, ApiError> {
-        // make sure the manga is in the cache
-        self.manga_view(uuid).await?;
+    pub async fn manga_tags(&mut self, id: MangaId) -> Result<Vec<TagId>, ApiError> {
+        Ok(self.manga_view(id).await?.tags)
+    }
 
-        if let Some(cached) = self.cache.get_linked(&uuid, RelationshipKind::Tag) {
-            if !cached.is_empty() {
-                return Ok(cached);
-            }
-        }
-        // Something fucked up, we don't know what.
-        Err(ApiError::Other)
+    /// A manga with its tags, authors, artists and main cover.
+    pub async fn manga_details(&mut self, id: MangaId) -> Result<MangaDetails, ApiError> {
+        let manga = self.manga_view(id).await?;
+
+        let tags = manga
+            .tags
+            .iter()
+            .filter_map(|t| self.cache().get::<Tag>(t).map(|tag| (*t, tag)))
+            .collect();
+        let authors = self
+            .cache()
+            .get_linked(&id, RelationshipKind::Author)
+            .unwrap_or_default();
+        let artists = self
+            .cache()
+            .get_linked(&id, RelationshipKind::Artist)
+            .unwrap_or_default();
+        let main_cover = self
+            .cache()
+            .get_linked::<CoverId>(&id, RelationshipKind::MainCoverArt)
+            .and_then(|c| c.first().copied());
+
+        Ok(MangaDetails {
+            id,
+            manga,
+            tags,
+            authors,
+            artists,
+            main_cover,
+        })
     }
 
     /// Search mangas.
@@ -206,8 +434,8 @@ impl Api {
         filter: MangaListFilter,
         offset: i32,
         count: i32,
-    ) -> Result<Vec<Uuid>, ApiError> {
-        let query = filter.to_query();
+    ) -> Result<Vec<MangaId>, ApiError> {
+        let query = ApiRequestQuery::from_serialize(&filter)?;
         let res = ApiRequest::<(), responses::MangaList> {
             endpoint: "/manga".to_owned(),
             query,
@@ -215,11 +443,29 @@ impl Api {
         }
         .send_paginated::<100>(self, offset, count)
         .await?;
-        Ok(res.store(&mut self.cache))
+        Ok(res.store(&mut self.cache()))
+    }
+
+    /// Number of mangas matching a filter, only one manga is actually fetched.
+    /// WARNING: This always sends a request.
+    pub async fn manga_count(&mut self, filter: &MangaListFilter) -> Result<i32, ApiError> {
+        let mut query = ApiRequestQuery::from_serialize(filter)?;
+        query.insert("limit", 1);
+        let res = ApiRequest::<(), responses::MangaList> {
+            endpoint: "/manga".to_owned(),
+            query,
+            ..Default::default()
+        }
+        .send(self)
+        .await?;
+
+        let total = res.total;
+        res.store(&mut self.cache());
+        Ok(total)
     }
 
     /// WARNING: this always sends a request, as this isn't really cacheable
-    pub async fn manga_random(&mut self) -> Result<Uuid, ApiError> {
+    pub async fn manga_random(&mut self) -> Result<MangaId, ApiError> {
         let res = ApiRequest::<(), responses::MangaRandom> {
             endpoint: "/manga/random".to_owned(),
             ..Default::default()
@@ -228,47 +474,91 @@ impl Api {
         .await?;
 
         let id = res.data.id;
-        res.store(&mut self.cache);
+        res.store(&mut self.cache());
 
         Ok(id)
     }
 
-    pub async fn manga_chapters(&mut self, uuid: Uuid) -> Result<Vec<Uuid>, ApiError> {
-        if let Some(cached) = self.cache.get_linked(&uuid, RelationshipKind::Chapter) {
+    pub async fn manga_chapters(&mut self, id: MangaId) -> Result<Vec<ChapterId>, ApiError> {
+        let cached = self
+            .cache()
+            .get_linked::<ChapterId>(&id, RelationshipKind::Chapter);
+        if let Some(cached) = cached {
             if !cached.is_empty() {
+                // the feed is as old as its chapters
+                self.refresh_if_stale::<Chapter, _, _>(cached[0], |mut api| async move {
+                    api.fetch_chapters(id).await.map(drop)
+                });
                 return Ok(cached);
             }
         }
-
+        self.fetch_chapters(id).await
+    }
+    async fn fetch_chapters(&mut self, id: MangaId) -> Result<Vec<ChapterId>, ApiError> {
         let res = ApiRequest::<(), responses::MangaFeed> {
-            endpoint: format!("/manga/{uuid}/feed"),
+            endpoint: format!("/manga/{id}/feed"),
             ..Default::default()
         }
         .send_paginated_all::<500>(self)
         .await?;
 
-        Ok(res.store(&mut self.cache))
+        Ok(res.store(&mut self.cache()))
+    }
+
+    /// Chapters of a manga, sorted by volume and chapter number.
+    pub async fn manga_chapter_list(&mut self, id: MangaId) -> Result<ChapterList, ApiError> {
+        let manga = self.manga_view(id).await?;
+        let ids = self.manga_chapters(id).await?;
+
+        Ok(ChapterList::new(
+            ids.into_iter().filter_map(|id| {
+                let chapter = self.cache().get::<Chapter>(&id)?;
+                let groups = self
+                    .cache()
+                    .get_linked(&id, RelationshipKind::ScanlationGroup)
+                    .unwrap_or_default();
+                Some((id, chapter, groups))
+            }),
+            manga.chapter_numbers_reset_on_new_volume,
+        ))
     }
 
-    pub async fn manga_volumes(&mut self, uuid: Uuid) -> Result<Vec<Uuid>, ApiError> {
-        if let Some(cached) = self.cache.get_linked(&uuid, RelationshipKind::Volume) {
+    /// Volumes of a manga, sorted.
+    pub async fn manga_volumes(&mut self, id: MangaId) -> Result<Vec<VolumeId>, ApiError> {
+        let cached = self
+            .cache()
+            .get_linked::<VolumeId>(&id, RelationshipKind::Volume);
+        if let Some(cached) = cached {
             if !cached.is_empty() {
+                self.refresh_if_stale::<Volume, _, _>(cached[0], |mut api| async move {
+                    api.fetch_volumes(id).await.map(drop)
+                });
                 return Ok(cached);
             }
         }
-
-        let res = ApiRequest::<(), responses::MangaAggregate> {
-            endpoint: format!("/manga/{uuid}/aggregate"),
+        self.fetch_volumes(id).await
+    }
+    async fn fetch_volumes(&mut self, id: MangaId) -> Result<Vec<VolumeId>, ApiError> {
+        let req = ApiRequest::<(), responses::MangaAggregate> {
+            endpoint: format!("/manga/{id}/aggregate"),
             ..Default::default()
+        };
+        let cached: Vec<VolumeId> = self
+            .cache()
+            .get_linked(&id, RelationshipKind::Volume)
+            .unwrap_or_default();
+
+        match self.send_revalidate::<Volume, _>(req, &cached).await? {
+            Some(mut res) => {
+                res.manga_id = Some(id);
+                Ok(res.store(&mut self.cache()))
+            }
+            None => Ok(cached),
         }
-        .send(self)
-        .await?;
-
-        Ok(res.store(&mut self.cache))
     }
 
-    pub fn volume_chapters(&mut self, uuid: Uuid) -> Option<Vec<Uuid>> {
-        if let Some(cached) = self.cache.get_linked(&uuid, RelationshipKind::Chapter) {
+    pub fn volume_chapters(&mut self, id: VolumeId) -> Option<Vec<ChapterId>> {
+        if let Some(cached) = self.cache().get_linked(&id, RelationshipKind::Chapter) {
             if !cached.is_empty() {
                 return Some(cached);
             }
@@ -277,55 +567,105 @@ impl Api {
         None
     }
 
-    pub fn volume_view(&mut self, uuid: Uuid) -> Option<Volume> {
-        self.cache.get::<Volume>(&uuid)
+    pub fn volume_view(&mut self, id: VolumeId) -> Option<Volume> {
+        self.cache().get::<Volume>(&id)
     }
 
-    pub async fn manga_volume(&mut self, name: String, manga: Uuid) -> Result<Uuid, ApiError> {
-        let volumes = self.manga_volumes(manga).await?;
-
-        for vid in volumes {
-            match self.volume_view(vid) {
-                Some(v) if v.volume == name => return Ok(vid),
-                _ => {}
-            }
+    pub async fn manga_volume(
+        &mut self,
+        name: String,
+        manga: MangaId,
+    ) -> Result<VolumeId, ApiError> {
+        let id = VolumeId::new(manga, &name);
+        if self.manga_volumes(manga).await?.contains(&id) {
+            Ok(id)
+        } else {
+            Err(ApiError::NotFound)
         }
+    }
 
-        Err(ApiError::NotFound)
+    /// Chapters before and after a chapter, from the aggregate of its manga (so without fetching
+    /// the whole feed).
+    pub async fn chapter_neighbours(
+        &mut self,
+        id: ChapterId,
+    ) -> Result<ChapterNeighbours, ApiError> {
+        // make sure the chapter (and so its manga) is in the cache
+        self.chapter_view(id).await?;
+        let manga = self
+            .cache()
+            .get_linked::<MangaId>(&id, RelationshipKind::Manga)
+            .and_then(|m| m.first().copied())
+            .ok_or(ApiError::NotFound)?;
+
+        let chapters: Vec<_> = self
+            .manga_volumes(manga)
+            .await?
+            .into_iter()
+            .filter_map(|v| self.volume_view(v))
+            .flat_map(|v| v.chapters)
+            .collect();
+        let i = chapters
+            .iter()
+            .position(|c| c.id == id || c.others.contains(&id))
+            .ok_or(ApiError::NotFound)?;
+
+        Ok(ChapterNeighbours {
+            previous: i.checked_sub(1).map(|i| chapters[i].id),
+            next: chapters.get(i + 1).map(|c| c.id),
+        })
     }
 
-    pub async fn chapter_view(&mut self, uuid: Uuid) -> Result<Chapter, ApiError> {
-        if let Some(chapter) = self.cache.get(&uuid) {
+    pub async fn chapter_view(&mut self, id: ChapterId) -> Result<Chapter, ApiError> {
+        let cached = self.cache().get(&id);
+        if let Some(chapter) = cached {
+            self.refresh_if_stale::<Chapter, _, _>(id, |mut api| async move {
+                api.fetch_chapter(id).await.map(drop)
+            });
             return Ok(chapter);
         }
-
-        let res = ApiRequest::<(), responses::ChapterView> {
-            endpoint: format!("/chapter/{uuid}").to_owned(),
+        self.fetch_chapter(id).await
+    }
+    async fn fetch_chapter(&mut self, id: ChapterId) -> Result<Chapter, ApiError> {
+        let req = ApiRequest::<(), responses::ChapterView> {
+            endpoint: format!("/chapter/{id}"),
             ..Default::default()
+        };
+        match self.send_revalidate::<Chapter, _>(req, &[id]).await? {
+            Some(res) => Ok(res.store(&mut self.cache())),
+            None => self.cache().get(&id).ok_or(ApiError::Other),
         }
-        .send(self)
-        .await?;
-
-        Ok(res.store(&mut self.cache))
     }
 
-    pub async fn chapter_pages(&mut self, uuid: Uuid) -> Result<Vec<String>, ApiError> {
-        let ah = match self
-            .cache
-            .get_linked(&uuid, RelationshipKind::AtHome)
-            .map(|x| x.get(0).map(|x| self.cache.get(x)))
-        {
-            Some(Some(Some(v))) => v,
-            _ => {
+    pub async fn chapter_pages(&mut self, id: ChapterId) -> Result<Vec<String>, ApiError> {
+        let cached = self
+            .cache()
+            .related::<AtHomeServerChapter>(&id, RelationshipKind::AtHome)
+            .pop();
+        let ah = match cached {
+            Some((_, v)) => v,
+            None => {
+                // every page is on disk, no need to ask for a server
+                if let Some((hash, files)) = images::cache::cached_chapter(&id, self.data_saver) {
+                    log::trace!("chapter pages {id} (image cache)");
+                    return Ok(self.page_urls(images::cache::UPLOADS_URL, &hash, files));
+                }
                 // couldn't find anything in the cache
                 let mut res = ApiRequest::<(), responses::AtHomeServer> {
-                    endpoint: format!("/at-home/server/{uuid}"),
+                    endpoint: format!("/at-home/server/{id}"),
                     ..Default::default()
                 }
                 .send(self)
                 .await?;
-                res.chapter_id = Some(uuid);
-                res.store(&mut self.cache)
+                res.chapter_id = Some(id);
+                let ah: AtHomeServerChapter = res.store(&mut self.cache());
+                images::cache::remember_chapter(
+                    id,
+                    ah.hash.clone(),
+                    ah.data.clone(),
+                    ah.data_saver.clone(),
+                );
+                ah
             }
         };
         let pages = if self.data_saver {
@@ -333,31 +673,148 @@ impl Api {
         } else {
             ah.data
         };
-        log::trace!("chapter pages {uuid}");
-        Ok(pages
+        log::trace!("chapter pages {id}");
+        Ok(self.page_urls(&ah.base_url, &ah.hash, pages))
+    }
+    fn page_urls(&self, base_url: &str, hash: &str, files: Vec<String>) -> Vec<String> {
+        let quality = if self.data_saver {
+            "data-saver"
+        } else {
+            "data"
+        };
+        files
             .into_iter()
-            .map(|x| {
-                format!(
-                    "{}/{}/{}/{x}",
-                    ah.base_url,
-                    if self.data_saver {
-                        "data-saver"
-                    } else {
-                        "data"
-                    },
-                    ah.hash
-                )
-            })
+            .map(|x| format!("{base_url}/{quality}/{hash}/{x}"))
+            .collect()
+    }
+
+    pub async fn cover_view(&mut self, id: CoverId) -> Result<CoverArt, ApiError> {
+        let cached = self.cache().get(&id);
+        if let Some(cover) = cached {
+            return Ok(cover);
+        }
+        let res = ApiRequest::<(), responses::CoverArt> {
+            endpoint: format!("/cover/{id}"),
+            ..Default::default()
+        }
+        .send(self)
+        .await?;
+        Ok(res.store(&mut self.cache()))
+    }
+
+    /// Authors (or artists) by id, the ones that aren't cached are fetched.
+    pub async fn authors(&mut self, ids: &[AuthorId]) -> Result<Vec<(AuthorId, Author)>, ApiError> {
+        let missing: Vec<&AuthorId> = ids
+            .iter()
+            .filter(|id| self.cache().get::<Author>(*id).is_none())
+            .collect();
+        if !missing.is_empty() {
+            let mut query = ApiRequestQuery::new();
+            for id in missing {
+                query.insert("ids[]", id);
+            }
+            query.insert("limit", 100);
+            ApiRequest::<(), responses::AuthorList> {
+                endpoint: "/author".to_owned(),
+                query,
+                ..Default::default()
+            }
+            .send(self)
+            .await?
+            .store(&mut self.cache());
+        }
+        Ok(ids
+            .iter()
+            .filter_map(|id| self.cache().get::<Author>(id).map(|a| (*id, a)))
             .collect())
     }
 
-    /// Invalidate cached data of specific uuid, will force the next query (of that object) to
+    /// Url of a cover, if it and its manga are cached.
+    pub fn cover_url(&mut self, id: CoverId) -> Option<String> {
+        let cover = self.cache().get::<CoverArt>(&id)?;
+        let manga = self
+            .cache()
+            .get_linked::<MangaId>(&id, RelationshipKind::Manga)?
+            .pop()?;
+        Some(format!(
+            "{}/covers/{manga}/{}",
+            images::cache::UPLOADS_URL,
+            cover.file_name
+        ))
+    }
+
+    /// Invalidate cached data of specific id, will force the next query (of that object) to
     /// reach out to the api.
-    pub fn invalidate_cache(&mut self, uuid: &Uuid) {
-        self.cache.remove(uuid);
+    pub fn invalidate_cache<T: HasId>(&mut self, id: &T::Id) {
+        self.cache().remove::<T>(id);
     }
     /// do the stupid
     pub fn clear_cache(&mut self) {
-        self.cache.clear();
+        self.cache().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::api::connectivity::{set_online, TEST_STATUS};
+
+    #[test]
+    fn stale_objects_are_refreshed_once_at_a_time() {
+        let _status = TEST_STATUS.lock();
+        set_online(true);
+
+        let cache = Arc::new(Mutex::new(ApiCache::new()));
+        let author: AuthorId = Uuid::new_v4().into();
+        {
+            let mut cache = CacheGuard::lock(&cache);
+            let value = Author {
+                name: "a".to_owned(),
+                version: 1,
+            };
+            cache.insert(author, value);
+            cache.set_fetched::<Author>(&author, Utc::now() - chrono::Duration::days(2));
+        }
+        // an Api per task, like the app does
+        let mut apis: Vec<Api> = (0..2)
+            .map(|_| {
+                let mut api = Api::new();
+                api.cache = cache.clone();
+                api
+            })
+            .collect();
+
+        let started = Arc::new(AtomicUsize::new(0));
+        let refresh = |api: &mut Api| {
+            let started = started.clone();
+            api.refresh_if_stale::<Author, _, _>(author, move |_| {
+                started.fetch_add(1, Ordering::Relaxed);
+                async { Ok(()) }
+            });
+        };
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            for api in &mut apis {
+                refresh(api);
+            }
+            assert_eq!(started.load(Ordering::Relaxed), 1);
+
+            // once the refresh is done (it didn't get anything) it can be tried again
+            for _ in 0..100 {
+                tokio::task::yield_now().await;
+                refresh(&mut apis[1]);
+                if started.load(Ordering::Relaxed) == 2 {
+                    break;
+                }
+            }
+            assert_eq!(started.load(Ordering::Relaxed), 2);
+        });
     }
 }
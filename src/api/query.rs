@@ -0,0 +1,808 @@
+// Serde serializer that turns a struct into the query pairs expected by mangadex.
+//
+// The api uses php style queries, so the shapes are mapped as follow:
+//  - scalars        -> key=value
+//  - sequences      -> key[]=a&key[]=b
+//  - maps / structs -> key[field]=value
+//  - None / unit    -> nothing
+// Values are kept as is, the encoding is done when the url is built.
+
+use std::fmt::Display;
+
+use serde::{
+    ser::{self, Impossible},
+    Serialize,
+};
+
+#[derive(Debug)]
+pub struct QueryError(String);
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Couldn't serialize query: {}", self.0)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl ser::Error for QueryError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Serialize a struct (or map) into a list of (key, value) pairs.
+pub fn to_pairs<T: Serialize + ?Sized>(value: &T) -> Result<Vec<(String, String)>, QueryError> {
+    let mut out = Vec::new();
+    value.serialize(TopSerializer { out: &mut out })?;
+    Ok(out)
+}
+
+fn top_level_error() -> QueryError {
+    QueryError("top level value must be a struct or a map".to_owned())
+}
+
+/// Serializer for the top level value, only accepts structs and maps, whose fields become keys.
+struct TopSerializer<'a> {
+    out: &'a mut Vec<(String, String)>,
+}
+
+/// Serializer for a value under a known key.
+struct ValueSerializer<'a> {
+    out: &'a mut Vec<(String, String)>,
+    key: String,
+}
+
+impl<'a> ValueSerializer<'a> {
+    fn push(self, value: impl ToString) -> Result<(), QueryError> {
+        self.out.push((self.key, value.to_string()));
+        Ok(())
+    }
+}
+
+/// Serializer used for map keys, which must be plain strings.
+struct KeySerializer;
+
+struct TopMap<'a> {
+    out: &'a mut Vec<(String, String)>,
+    key: Option<String>,
+}
+
+struct NestedSeq<'a> {
+    out: &'a mut Vec<(String, String)>,
+    key: String,
+}
+
+struct NestedMap<'a> {
+    out: &'a mut Vec<(String, String)>,
+    prefix: String,
+    key: Option<String>,
+}
+
+impl<'a> ser::Serializer for TopSerializer<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    type SerializeSeq = Impossible<(), QueryError>;
+    type SerializeTuple = Impossible<(), QueryError>;
+    type SerializeTupleStruct = Impossible<(), QueryError>;
+    type SerializeTupleVariant = Impossible<(), QueryError>;
+    type SerializeMap = TopMap<'a>;
+    type SerializeStruct = TopMap<'a>;
+    type SerializeStructVariant = Impossible<(), QueryError>;
+
+    fn serialize_bool(self, _: bool) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_i8(self, _: i8) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_i16(self, _: i16) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_i32(self, _: i32) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_i64(self, _: i64) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_u8(self, _: u8) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_u16(self, _: u16) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_u32(self, _: u32) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_u64(self, _: u64) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_f32(self, _: f32) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_f64(self, _: f64) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_char(self, _: char) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_str(self, _: &str) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_none(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), QueryError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), QueryError> {
+        Ok(())
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+    ) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), QueryError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, QueryError> {
+        Err(top_level_error())
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, QueryError> {
+        Ok(TopMap {
+            out: self.out,
+            key: None,
+        })
+    }
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, QueryError> {
+        Ok(TopMap {
+            out: self.out,
+            key: None,
+        })
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, QueryError> {
+        Err(top_level_error())
+    }
+}
+
+impl<'a> ser::SerializeMap for TopMap<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), QueryError> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| QueryError("value serialized before key".to_owned()))?;
+        value.serialize(ValueSerializer {
+            out: &mut *self.out,
+            key,
+        })
+    }
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for TopMap<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), QueryError> {
+        value.serialize(ValueSerializer {
+            out: &mut *self.out,
+            key: key.to_owned(),
+        })
+    }
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for ValueSerializer<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    type SerializeSeq = NestedSeq<'a>;
+    type SerializeTuple = NestedSeq<'a>;
+    type SerializeTupleStruct = NestedSeq<'a>;
+    type SerializeTupleVariant = Impossible<(), QueryError>;
+    type SerializeMap = NestedMap<'a>;
+    type SerializeStruct = NestedMap<'a>;
+    type SerializeStructVariant = Impossible<(), QueryError>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_char(self, v: char) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_str(self, v: &str) -> Result<(), QueryError> {
+        self.push(v)
+    }
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), QueryError> {
+        Err(QueryError(format!(
+            "bytes can't be used as value ({})",
+            self.key
+        )))
+    }
+    fn serialize_none(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), QueryError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), QueryError> {
+        Ok(())
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), QueryError> {
+        self.push(variant)
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), QueryError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), QueryError> {
+        // untagged, the variant is only a way to pick the representation.
+        value.serialize(self)
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, QueryError> {
+        Ok(NestedSeq {
+            out: self.out,
+            key: format!("{}[]", self.key),
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, QueryError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, QueryError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, QueryError> {
+        Err(QueryError(format!(
+            "unsupported tuple variant ({})",
+            self.key
+        )))
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, QueryError> {
+        Ok(NestedMap {
+            out: self.out,
+            prefix: self.key,
+            key: None,
+        })
+    }
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, QueryError> {
+        self.serialize_map(None)
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, QueryError> {
+        Err(QueryError(format!(
+            "unsupported struct variant ({})",
+            self.key
+        )))
+    }
+}
+
+impl<'a> NestedSeq<'a> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        value.serialize(ValueSerializer {
+            out: &mut *self.out,
+            key: self.key.clone(),
+        })
+    }
+}
+
+impl<'a> ser::SerializeSeq for NestedSeq<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTuple for NestedSeq<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for NestedSeq<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeMap for NestedMap<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), QueryError> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| QueryError("value serialized before key".to_owned()))?;
+        value.serialize(ValueSerializer {
+            out: &mut *self.out,
+            key: format!("{}[{key}]", self.prefix),
+        })
+    }
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for NestedMap<'a> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), QueryError> {
+        value.serialize(ValueSerializer {
+            out: &mut *self.out,
+            key: format!("{}[{key}]", self.prefix),
+        })
+    }
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+fn key_error() -> QueryError {
+    QueryError("map keys must be strings, numbers or unit variants".to_owned())
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = QueryError;
+
+    type SerializeSeq = Impossible<String, QueryError>;
+    type SerializeTuple = Impossible<String, QueryError>;
+    type SerializeTupleStruct = Impossible<String, QueryError>;
+    type SerializeTupleVariant = Impossible<String, QueryError>;
+    type SerializeMap = Impossible<String, QueryError>;
+    type SerializeStruct = Impossible<String, QueryError>;
+    type SerializeStructVariant = Impossible<String, QueryError>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i8(self, v: i8) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i16(self, v: i16) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i32(self, v: i32) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_i64(self, v: i64) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u8(self, v: u8) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u16(self, v: u16) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u32(self, v: u32) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_u64(self, v: u64) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_f32(self, _: f32) -> Result<String, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_f64(self, _: f64) -> Result<String, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_char(self, v: char) -> Result<String, QueryError> {
+        Ok(v.to_string())
+    }
+    fn serialize_str(self, v: &str) -> Result<String, QueryError> {
+        Ok(v.to_owned())
+    }
+    fn serialize_bytes(self, _: &[u8]) -> Result<String, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_none(self) -> Result<String, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, QueryError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<String, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<String, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<String, QueryError> {
+        Ok(variant.to_owned())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<String, QueryError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<String, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, QueryError> {
+        Err(key_error())
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, QueryError> {
+        Err(key_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use reqwest::Url;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::api::request::{ApiRequest, ApiRequestQuery};
+
+    fn pairs<T: Serialize>(value: &T) -> Vec<(String, String)> {
+        to_pairs(value).unwrap()
+    }
+
+    fn owned(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn encoded<T: Serialize>(value: &T) -> String {
+        let req = ApiRequest::<(), ()> {
+            query: ApiRequestQuery::from_serialize(value).unwrap(),
+            ..Default::default()
+        };
+        let url = req.with_query(Url::parse("https://api.mangadex.org/manga").unwrap());
+        url.query().unwrap_or_default().to_owned()
+    }
+
+    #[derive(Serialize)]
+    struct Title {
+        title: String,
+    }
+
+    #[test]
+    fn scalars() {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Query {
+            limit: u32,
+            has_available_chapters: bool,
+            title: &'static str,
+        }
+        let query = Query {
+            limit: 10,
+            has_available_chapters: true,
+            title: "x",
+        };
+        assert_eq!(
+            pairs(&query),
+            owned(&[
+                ("limit", "10"),
+                ("hasAvailableChapters", "true"),
+                ("title", "x")
+            ])
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        #[derive(Serialize)]
+        struct Query {
+            ids: Vec<&'static str>,
+            empty: Vec<u32>,
+        }
+        let query = Query {
+            ids: vec!["a", "b"],
+            empty: vec![],
+        };
+        assert_eq!(pairs(&query), owned(&[("ids[]", "a"), ("ids[]", "b")]));
+        assert_eq!(encoded(&query), "ids%5B%5D=a&ids%5B%5D=b");
+    }
+
+    #[test]
+    fn order_map() {
+        #[derive(Serialize)]
+        struct Query {
+            order: BTreeMap<&'static str, &'static str>,
+        }
+        let query = Query {
+            order: BTreeMap::from([("followedCount", "desc"), ("createdAt", "asc")]),
+        };
+        assert_eq!(
+            pairs(&query),
+            owned(&[
+                ("order[createdAt]", "asc"),
+                ("order[followedCount]", "desc")
+            ])
+        );
+        assert_eq!(
+            encoded(&query),
+            "order%5BcreatedAt%5D=asc&order%5BfollowedCount%5D=desc"
+        );
+    }
+
+    #[test]
+    fn map_keeps_entry_order() {
+        // the sorting priority of the filter is the order of the entries.
+        #[derive(Serialize)]
+        struct Query {
+            #[serde(serialize_with = "as_map")]
+            order: Vec<(&'static str, &'static str)>,
+        }
+        fn as_map<S: serde::Serializer>(
+            order: &[(&'static str, &'static str)],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(order.iter().map(|(k, v)| (k, v)))
+        }
+        let query = Query {
+            order: vec![("relevance", "desc"), ("year", "asc")],
+        };
+        assert_eq!(
+            pairs(&query),
+            owned(&[("order[relevance]", "desc"), ("order[year]", "asc")])
+        );
+    }
+
+    #[test]
+    fn skips_none() {
+        #[derive(Serialize)]
+        struct Query {
+            title: Option<&'static str>,
+            year: Option<u32>,
+            unit: (),
+        }
+        let query = Query {
+            title: None,
+            year: Some(2020),
+            unit: (),
+        };
+        assert_eq!(pairs(&query), owned(&[("year", "2020")]));
+        let query = Query {
+            title: None,
+            year: None,
+            unit: (),
+        };
+        assert!(pairs(&query).is_empty());
+        assert_eq!(encoded(&query), "");
+    }
+
+    #[test]
+    fn percent_encoding() {
+        let cases = [
+            ("a&b", "title=a%26b"),
+            ("#1", "title=%231"),
+            ("1+1", "title=1%2B1"),
+            ("one piece", "title=one+piece"),
+            (
+                "進撃の巨人",
+                "title=%E9%80%B2%E6%92%83%E3%81%AE%E5%B7%A8%E4%BA%BA",
+            ),
+            ("a=b", "title=a%3Db"),
+        ];
+        for (title, expected) in cases {
+            let query = Title {
+                title: title.to_owned(),
+            };
+            // values are kept raw until the url is built
+            assert_eq!(pairs(&query), owned(&[("title", title)]));
+            assert_eq!(encoded(&query), expected, "{title}");
+        }
+    }
+
+    #[test]
+    fn top_level_must_be_struct() {
+        assert!(to_pairs(&1).is_err());
+        assert!(to_pairs("title").is_err());
+        assert!(to_pairs(&vec![1, 2]).is_err());
+    }
+}
@@ -1,7 +1,9 @@
-use reqwest::{RequestBuilder, StatusCode};
-use serde::Serialize;
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    RequestBuilder, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
     fmt::Display,
     marker::PhantomData,
     slice::{Iter, IterMut},
@@ -9,7 +11,12 @@ use std::{
 };
 use tokio::time::Duration;
 
-use super::{structs::json::responses::Paginate, Api, ApiError};
+use super::{
+    connectivity,
+    query::{self, QueryError},
+    structs::json::responses::Paginate,
+    Api, ApiError,
+};
 
 #[derive(Clone)]
 pub enum ApiRequestBody<T> {
@@ -26,6 +33,40 @@ impl<T: Serialize> Display for ApiRequestBody<T> {
     }
 }
 
+/// Validators of a response (ETag and Last-Modified headers), sent back to only get the response
+/// if it changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+        let v = Self {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+        };
+        (v.etag.is_some() || v.last_modified.is_some()).then_some(v)
+    }
+}
+
+/// What a request does with validators.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Validation {
+    None,
+    /// Keep the validators of the response.
+    Store,
+    /// Send the stored validators, and keep the new ones.
+    Send,
+}
+
 #[derive(Clone)]
 pub enum ApiRequestKind {
     Post,
@@ -61,34 +102,16 @@ impl ApiRequestQuery {
     pub fn insert<T: ToString>(&mut self, key: &str, value: T) {
         self.inner.push((key.to_string(), value.to_string()));
     }
-    pub fn insert_vec<T: Display>(&mut self, key: &str, value: &[T]) {
-        for v in value {
-            self.insert(&format!("{key}[]"), v);
-        }
-    }
-    pub fn insert_map<K: Display, V: Display>(&mut self, key: &str, value: &HashMap<K, V>) {
-        for (k, v) in value {
-            self.insert(&format!("{key}[{k}]"), v);
-        }
-    }
-    pub fn insert_option<T: ToString>(&mut self, key: &str, value: Option<T>) {
-        if let Some(v) = value {
-            self.insert(key, v);
-        }
-    }
-    pub fn insert_vec_option<T: Display>(&mut self, key: &str, value: &Option<Vec<T>>) {
-        if let Some(v) = value {
-            self.insert_vec(key, v);
-        }
+    /// Build a query from any serializable struct, sequences become `key[]=...` and maps / nested
+    /// structs become `key[field]=...`.
+    pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> Result<Self, QueryError> {
+        Ok(Self {
+            inner: query::to_pairs(value)?,
+        })
     }
-    pub fn insert_map_option<K: Display, V: Display>(
-        &mut self,
-        key: &str,
-        value: &Option<HashMap<K, V>>,
-    ) {
-        if let Some(v) = value {
-            self.insert_map(key, v);
-        }
+    /// Append all the pairs of another query.
+    pub fn extend(&mut self, other: ApiRequestQuery) {
+        self.inner.extend(other.inner);
     }
 }
 
@@ -170,22 +193,26 @@ where
     A: serde::Serialize,
     B: serde::de::DeserializeOwned,
 {
+    fn url(&self, api: &Api) -> Url {
+        self.with_query(api.endpoint(&self.endpoint))
+    }
+    /// Append the query and includes of the request to an url.
+    pub(super) fn with_query(&self, mut url: Url) -> Url {
+        if !self.query.is_empty() || !self.include.is_empty() {
+            // query_pairs_mut takes care of percent encoding keys and values
+            let mut pairs = url.query_pairs_mut();
+            for (k, v) in self.query.iter() {
+                pairs.append_pair(k, v);
+            }
+            for i in &self.include {
+                pairs.append_pair("includes[]", i);
+            }
+        }
+        url
+    }
     /// Build a RequestBuilder from an ApiRequest
     pub fn build(&self, api: &mut Api) -> Result<RequestBuilder, ApiError> {
-        let mut url = api.endpoint(&self.endpoint);
-        let mut query = vec![];
-
-        if !self.query.is_empty() {
-            query.extend(self.query.iter().map(|(k, v)| format!("{k}={v}")));
-        }
-
-        if !self.include.is_empty() {
-            query.extend(self.include.iter().map(|x| format!("include[]={}", x)));
-        }
-
-        if !query.is_empty() {
-            url.set_query(Some(&query.join("&")));
-        }
+        let url = self.url(api);
 
         let mut req = match self.kind {
             ApiRequestKind::Get => api.client.get(url),
@@ -204,22 +231,69 @@ where
     }
     /// Send request, without any retry / auth logic.
     pub async fn send_simple(&self, api: &mut Api) -> Result<B, ApiError> {
-        let req = self.build(api)?;
-        let res = req.send().await.map_err(Into::<ApiError>::into)?;
+        self.send_once(api, Validation::None)
+            .await?
+            .ok_or(ApiError::Other)
+    }
+    /// Send request once, None is returned if validators were sent and it didn't change.
+    async fn send_once(
+        &self,
+        api: &mut Api,
+        validation: Validation,
+    ) -> Result<Option<B>, ApiError> {
+        // don't wait for timeouts on every request while offline, only ping once in a while
+        if !connectivity::is_online() && !(connectivity::should_retry() && api.ping().await) {
+            return Err(ApiError::Offline);
+        }
+
+        let key = self.url(api).to_string();
+        let mut req = self.build(api)?;
+        if validation == Validation::Send {
+            let validators = api.cache().validators(&key).cloned();
+            if let Some(v) = validators {
+                if let Some(ref etag) = v.etag {
+                    req = req.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(ref date) = v.last_modified {
+                    req = req.header(IF_MODIFIED_SINCE, date);
+                }
+            }
+        }
+        let res = match req.send().await {
+            Ok(res) => res,
+            Err(e) => {
+                log::warn!("Couldn't send {} ({})", self, e);
+                return Err(connectivity::request_error(e));
+            }
+        };
 
         // if status >= 400 (pretty much if error)
         if res.status() >= StatusCode::BAD_REQUEST {
             log::error!(
                 "Gotten error in response ({}) request {} on {}",
                 res.status(),
-                res.headers().get("X-Request-ID").unwrap().to_str().unwrap(),
+                res.headers()
+                    .get("X-Request-ID")
+                    .and_then(|id| id.to_str().ok())
+                    .unwrap_or("(no id)"),
                 self.endpoint
             );
             log::error!("{:?}", res);
         }
 
         match res.status() {
-            StatusCode::OK => Ok(res.json::<B>().await.expect("Error when deserializing")),
+            StatusCode::OK => {
+                if validation != Validation::None {
+                    if let Some(v) = Validators::from_headers(res.headers()) {
+                        api.cache().set_validators(key, v);
+                    }
+                }
+                Ok(Some(res.json::<B>().await?))
+            }
+            StatusCode::NOT_MODIFIED if validation == Validation::Send => {
+                log::trace!("{} not modified", self.endpoint);
+                Ok(None)
+            }
             StatusCode::BAD_REQUEST => Err(ApiError::BadRequest),
             StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(ApiError::Auth),
             StatusCode::INTERNAL_SERVER_ERROR => Err(ApiError::Them),
@@ -239,25 +313,44 @@ where
     }
     /// Sends request, tries to handle Auth and RateLimit errors.
     pub async fn send(&self, api: &mut Api) -> Result<B, ApiError> {
-        let mut result = self.send_simple(api).await;
+        self.send_retry(api, Validation::None)
+            .await?
+            .ok_or(ApiError::Other)
+    }
+    /// Like send, but keeps the validators of the response for send_conditional.
+    pub async fn send_validated(&self, api: &mut Api) -> Result<B, ApiError> {
+        self.send_retry(api, Validation::Store)
+            .await?
+            .ok_or(ApiError::Other)
+    }
+    /// Like send, but only gets the response if it changed since the last time (None otherwise).
+    pub async fn send_conditional(&self, api: &mut Api) -> Result<Option<B>, ApiError> {
+        self.send_retry(api, Validation::Send).await
+    }
+    async fn send_retry(
+        &self,
+        api: &mut Api,
+        validation: Validation,
+    ) -> Result<Option<B>, ApiError> {
+        let mut result = self.send_once(api, validation).await;
 
         if let Err(ApiError::RateLimit(retry)) = result {
             // sleep a little longer just in case
             tokio::time::sleep(retry + Duration::from_millis(500)).await;
-            result = self.send_simple(api).await;
+            result = self.send_once(api, validation).await;
         }
 
         // We don't know what, but something went wrong and its probably on mangadex, so we wait a
         // little and resend.
         if let Err(ApiError::Them) = result {
             tokio::time::sleep(Duration::from_secs(1)).await;
-            result = self.send_simple(api).await;
+            result = self.send_once(api, validation).await;
         }
 
         if let Err(ApiError::Auth) = result {
             match api.refresh().await {
                 Ok(_) => {
-                    result = self.send_simple(api).await;
+                    result = self.send_once(api, validation).await;
                 }
                 Err(ApiError::Auth) => {
                     // TODO: ask for relogin
@@ -348,3 +441,121 @@ where
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::Arc,
+        thread,
+    };
+
+    use chrono::Utc;
+    use parking_lot::Mutex;
+    use reqwest::header::HeaderValue;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::api::{
+        cache::ApiCache,
+        connectivity::{set_online, TEST_STATUS},
+        structs::{ids::AuthorId, Author},
+    };
+
+    #[test]
+    fn validators_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert!(Validators::from_headers(&headers).is_none());
+
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        let v = Validators::from_headers(&headers).unwrap();
+        assert_eq!(v.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(v.last_modified, None);
+
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        let v = Validators::from_headers(&headers).unwrap();
+        assert_eq!(
+            v.last_modified.as_deref(),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+
+        headers.remove(ETAG);
+        let v = Validators::from_headers(&headers).unwrap();
+        assert_eq!(v.etag, None);
+    }
+
+    /// Answer the requests made to the url with the responses in order, the requests are sent
+    /// back once they are all answered.
+    fn serve(responses: Vec<&'static str>) -> (Url, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    assert!(n > 0, "the request wasn't finished");
+                    request.extend_from_slice(&buf[..n]);
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push(String::from_utf8(request).unwrap().to_lowercase());
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn not_modified_refreshes_the_cached_objects() {
+        let _status = TEST_STATUS.lock();
+        set_online(true);
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Type: application/json\r\n\
+             Content-Length: 2\r\nConnection: close\r\n\r\n{}",
+            "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+        ]);
+
+        let mut api = Api::new();
+        api.api = url;
+        api.cache = Arc::new(Mutex::new(ApiCache::new()));
+        let author: AuthorId = Uuid::new_v4().into();
+        api.cache().insert(
+            author,
+            Author {
+                name: "a".to_owned(),
+                version: 1,
+            },
+        );
+        api.cache()
+            .set_fetched::<Author>(&author, Utc::now() - chrono::Duration::days(2));
+        assert!(api.cache().is_stale::<Author>(&author));
+
+        let req = || ApiRequest::<(), serde_json::Value> {
+            endpoint: "/author".to_owned(),
+            ..Default::default()
+        };
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // nothing to validate against yet, the response is kept
+        let res = rt.block_on(api.send_revalidate::<Author, _>(req(), &[author]));
+        assert!(res.unwrap().is_some());
+        assert!(api.cache().is_stale::<Author>(&author));
+
+        let res = rt.block_on(api.send_revalidate::<Author, _>(req(), &[author]));
+        assert!(res.unwrap().is_none());
+        assert!(!api.cache().is_stale::<Author>(&author));
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+    }
+}
@@ -0,0 +1,631 @@
+// Small query language for searching mangas, i.e.
+//
+//     berserk tag:action -tag:romance lang:en status:ongoing year:1989 rating:safe,suggestive sort:follows
+//
+// Words without a key are joined to make up the title, as are words with a colon that isn't a
+// known key (Re:Zero). Values can be quoted ("slice of life") and most keys accept multiple comma
+// separated values. Parsing is done in two steps: the query is first parsed into a SearchQuery
+// without touching the network, then tag names are resolved using the tag catalog (see
+// Api::tag_catalog).
+
+use std::{fmt::Display, ops::Range};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use unicode_width::UnicodeWidthStr;
+use uuid::Uuid;
+
+use super::{
+    structs::{
+        filter::{IncludeMode, MangaListOrder, MangaListOrderCriteria},
+        json::data::{ContentRating, MangaStatus, PublicationDemographic},
+        lang_codes::LanguageCode,
+        MangaListFilter,
+    },
+    Api, ApiError,
+};
+
+#[derive(Debug)]
+pub enum SearchErrorKind {
+    UnterminatedQuote,
+    MissingValue(String),
+    InvalidValue(String, String),
+    NotNegatable(String),
+    UnknownTag(String),
+    /// The tags couldn't be fetched to resolve a tag name.
+    Api(ApiError),
+}
+
+/// Error when parsing a search, span is the range (in bytes) of the offending token in the input.
+#[derive(Debug)]
+pub struct SearchError {
+    pub kind: SearchErrorKind,
+    pub span: Range<usize>,
+}
+
+impl SearchError {
+    fn new(kind: SearchErrorKind, span: Range<usize>) -> Self {
+        Self { kind, span }
+    }
+    /// Underline the offending token, to be printed under the input (wide characters take two
+    /// columns in a terminal).
+    pub fn pointer(&self, input: &str) -> String {
+        let start = input[..self.span.start].width();
+        let len = input[self.span.clone()].width().max(1);
+        format!("{}{}", " ".repeat(start), "^".repeat(len))
+    }
+}
+
+impl Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            SearchErrorKind::UnterminatedQuote => write!(f, "Unterminated quote"),
+            SearchErrorKind::MissingValue(k) => write!(f, "Missing value for `{k}`"),
+            SearchErrorKind::InvalidValue(k, v) => write!(f, "Invalid value `{v}` for `{k}`"),
+            SearchErrorKind::NotNegatable(k) => write!(f, "`{k}` can't be negated"),
+            SearchErrorKind::UnknownTag(t) => write!(f, "Unknown tag `{t}`"),
+            SearchErrorKind::Api(e) => write!(f, "Couldn't fetch the tags ({e})"),
+        }?;
+        write!(f, " at {}..{}", self.span.start, self.span.end)
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// A whitespace separated part of the query, with quotes removed.
+struct Token {
+    negated: bool,
+    key: Option<String>,
+    value: String,
+    span: Range<usize>,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, SearchError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut key = None;
+        let mut quoted = false;
+        let mut end = input.len();
+
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() && !quoted {
+                end = i;
+                break;
+            }
+            chars.next();
+            match c {
+                '"' => quoted = !quoted,
+                // only the first unquoted colon separates the key from the value
+                ':' if !quoted && key.is_none() => key = Some(std::mem::take(&mut text)),
+                _ => text.push(c),
+            }
+        }
+
+        if quoted {
+            return Err(SearchError::new(
+                SearchErrorKind::UnterminatedQuote,
+                start..end,
+            ));
+        }
+
+        let (negated, key) = match key {
+            Some(k) => match k.strip_prefix('-') {
+                Some(k) => (true, Some(k.to_lowercase())),
+                None => (false, Some(k.to_lowercase())),
+            },
+            None => (false, None),
+        };
+
+        tokens.push(Token {
+            negated,
+            key,
+            value: text,
+            span: start..end,
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Keys of the filters, a word with another key is part of the title.
+const KEYS: &[&str] = &[
+    "title",
+    "tag",
+    "tagmode",
+    "lang",
+    "language",
+    "orig",
+    "original",
+    "status",
+    "rating",
+    "demo",
+    "demographic",
+    "year",
+    "sort",
+    "author",
+    "artist",
+    "creator",
+    "group",
+    "chapters",
+    "created",
+    "updated",
+];
+
+/// A tag name that still needs to be resolved into an id.
+struct PendingTag {
+    name: String,
+    exclude: bool,
+    span: Range<usize>,
+}
+
+/// A parsed search, where tags haven't been resolved yet.
+pub struct SearchQuery {
+    filter: MangaListFilter,
+    tags: Vec<PendingTag>,
+}
+
+fn parse_status(v: &str) -> Option<MangaStatus> {
+    match v {
+        "ongoing" => Some(MangaStatus::Ongoing),
+        "completed" => Some(MangaStatus::Completed),
+        "hiatus" => Some(MangaStatus::Hiatus),
+        "cancelled" => Some(MangaStatus::Cancelled),
+        _ => None,
+    }
+}
+
+fn parse_rating(v: &str) -> Option<ContentRating> {
+    match v {
+        "safe" => Some(ContentRating::Safe),
+        "suggestive" => Some(ContentRating::Suggestive),
+        "erotica" => Some(ContentRating::Erotica),
+        "pornographic" => Some(ContentRating::Pornographic),
+        _ => None,
+    }
+}
+
+fn parse_demographic(v: &str) -> Option<PublicationDemographic> {
+    match v {
+        "shounen" => Some(PublicationDemographic::Shounen),
+        "shoujo" => Some(PublicationDemographic::Shoujo),
+        "josei" => Some(PublicationDemographic::Josei),
+        "seinen" => Some(PublicationDemographic::Seinen),
+        _ => None,
+    }
+}
+
+fn parse_criteria(v: &str) -> Option<MangaListOrderCriteria> {
+    match v {
+        "title" => Some(MangaListOrderCriteria::Title),
+        "year" => Some(MangaListOrderCriteria::Year),
+        "created" => Some(MangaListOrderCriteria::CreatedAt),
+        "updated" => Some(MangaListOrderCriteria::UpdatedAt),
+        "latest" => Some(MangaListOrderCriteria::LatestUploadedChapter),
+        "follows" => Some(MangaListOrderCriteria::FollowedCount),
+        "relevance" => Some(MangaListOrderCriteria::Relevance),
+        "rating" => Some(MangaListOrderCriteria::Rating),
+        _ => None,
+    }
+}
+
+fn parse_language(v: &str) -> Option<LanguageCode> {
+    v.parse().ok()
+}
+
+fn parse_date(v: &str) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(v, "%Y-%m-%d").ok()?;
+    Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?))
+}
+
+impl SearchQuery {
+    /// Parse a search, this doesn't resolve tags (see SearchQuery::into_filter).
+    pub fn parse(input: &str) -> Result<Self, SearchError> {
+        let mut filter = MangaListFilter::default();
+        let mut tags = Vec::new();
+        let mut title = Vec::new();
+
+        for token in tokenize(input)? {
+            let key = match token.key {
+                Some(ref k) if KEYS.contains(&k.as_str()) => k.as_str(),
+                Some(_) => {
+                    // as written, with the colon
+                    title.push(input[token.span].replace('"', ""));
+                    continue;
+                }
+                None => {
+                    title.push(token.value);
+                    continue;
+                }
+            };
+
+            let err = |kind| Err(SearchError::new(kind, token.span.clone()));
+            let invalid = |v: &str| {
+                Err(SearchError::new(
+                    SearchErrorKind::InvalidValue(key.to_owned(), v.to_owned()),
+                    token.span.clone(),
+                ))
+            };
+
+            // empty items (a trailing comma) are ignored
+            let values = token
+                .value
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty());
+
+            if values.clone().next().is_none() {
+                return err(SearchErrorKind::MissingValue(key.to_owned()));
+            }
+            if token.negated && !matches!(key, "tag" | "orig" | "original") {
+                return err(SearchErrorKind::NotNegatable(key.to_owned()));
+            }
+
+            match key {
+                "title" => title.push(token.value.clone()),
+                "tag" => {
+                    for name in values {
+                        tags.push(PendingTag {
+                            name: name.to_owned(),
+                            exclude: token.negated,
+                            span: token.span.clone(),
+                        });
+                    }
+                }
+                "tagmode" => {
+                    let mode = match token.value.to_lowercase().as_str() {
+                        "all" | "and" => IncludeMode::And,
+                        "any" | "or" => IncludeMode::Or,
+                        v => return invalid(v),
+                    };
+                    filter = filter.include_tags_mode(mode);
+                }
+                "lang" | "language" => {
+                    for v in values {
+                        match parse_language(v) {
+                            Some(lang) => filter = filter.translated_language(lang),
+                            None => return invalid(v),
+                        }
+                    }
+                }
+                "orig" | "original" => {
+                    for v in values {
+                        match parse_language(v) {
+                            Some(lang) if token.negated => {
+                                filter = filter.exclude_original_language(lang)
+                            }
+                            Some(lang) => filter = filter.original_language(lang),
+                            None => return invalid(v),
+                        }
+                    }
+                }
+                "status" => {
+                    for v in values {
+                        match parse_status(&v.to_lowercase()) {
+                            Some(s) => filter = filter.status(s),
+                            None => return invalid(v),
+                        }
+                    }
+                }
+                "rating" => {
+                    for v in values {
+                        match parse_rating(&v.to_lowercase()) {
+                            Some(r) => filter = filter.content_rating(r),
+                            None => return invalid(v),
+                        }
+                    }
+                }
+                "demo" | "demographic" => {
+                    for v in values {
+                        match v.to_lowercase().as_str() {
+                            "none" => filter = filter.no_demographic(),
+                            d => match parse_demographic(d) {
+                                Some(d) => filter = filter.demographic(d),
+                                None => return invalid(v),
+                            },
+                        }
+                    }
+                }
+                "year" => match token.value.as_str() {
+                    "none" => filter = filter.no_year(),
+                    v => match v.parse() {
+                        Ok(year) => filter = filter.year(year),
+                        Err(_) => return invalid(v),
+                    },
+                },
+                // criterias are kept in the order they are given, the first one has priority
+                "sort" => {
+                    for v in values {
+                        let v = v.to_lowercase();
+                        let (criteria, order) = match v.split_once(':') {
+                            Some((c, "asc")) => (c, MangaListOrder::Asc),
+                            Some((c, "desc")) => (c, MangaListOrder::Desc),
+                            Some(_) => return invalid(&v),
+                            // titles make more sense sorted from A to Z, everything else from
+                            // the most to the least.
+                            None if v == "title" => (v.as_str(), MangaListOrder::Asc),
+                            None => (v.as_str(), MangaListOrder::Desc),
+                        };
+                        match parse_criteria(criteria) {
+                            Some(c) => filter = filter.order(c, order),
+                            None => return invalid(&v),
+                        }
+                    }
+                }
+                "author" | "artist" | "creator" | "group" => {
+                    for v in values {
+                        let id = match Uuid::parse_str(v) {
+                            Ok(id) => id,
+                            Err(_) => return invalid(v),
+                        };
+                        filter = match key {
+                            "author" => filter.author(id.into()),
+                            "artist" => filter.artist(id.into()),
+                            "creator" => filter.author_or_artist(id.into()),
+                            _ => filter.group(id.into()),
+                        };
+                    }
+                }
+                "chapters" => match token.value.to_lowercase().as_str() {
+                    "yes" | "true" => filter = filter.has_available_chapters(true),
+                    "no" | "false" => filter = filter.has_available_chapters(false),
+                    v => return invalid(v),
+                },
+                "created" | "updated" => match parse_date(&token.value) {
+                    Some(d) if key == "created" => filter = filter.created_at_since(d),
+                    Some(d) => filter = filter.updated_at_since(d),
+                    None => return invalid(&token.value),
+                },
+                _ => unreachable!("{key} is in KEYS but not handled"),
+            }
+        }
+
+        if !title.is_empty() {
+            filter = filter.title(title.join(" "));
+        }
+
+        Ok(Self { filter, tags })
+    }
+
+    /// Names of the tags to resolve, in order.
+    #[cfg(test)]
+    pub fn tag_names(&self) -> Vec<&str> {
+        self.tags.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    /// Resolve the tag names using the tags of the api (named in languages first) and build the
+    /// filter.
+    pub async fn into_filter(
+        self,
+        api: &mut Api,
+        languages: &[LanguageCode],
+    ) -> Result<MangaListFilter, SearchError> {
+        let mut filter = self.filter;
+
+        if self.tags.is_empty() {
+            return Ok(filter);
+        }
+
+        let catalog = match api.tag_catalog(languages).await {
+            Ok(c) => c,
+            Err(e) => {
+                return Err(SearchError::new(
+                    SearchErrorKind::Api(e),
+                    self.tags[0].span.clone(),
+                ))
+            }
+        };
+
+        for pending in self.tags {
+            match catalog.find(&pending.name) {
+                Some((id, _)) if pending.exclude => filter = filter.exclude_tag(id),
+                Some((id, _)) => filter = filter.include_tag(id),
+                None => {
+                    return Err(SearchError::new(
+                        SearchErrorKind::UnknownTag(pending.name),
+                        pending.span,
+                    ))
+                }
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::query;
+
+    fn parse(input: &str) -> SearchQuery {
+        match SearchQuery::parse(input) {
+            Ok(q) => q,
+            Err(e) => panic!("{input}: {e}"),
+        }
+    }
+
+    fn pairs(input: &str) -> Vec<(String, String)> {
+        query::to_pairs(&parse(input).filter).unwrap()
+    }
+
+    fn values(input: &str, key: &str) -> Vec<String> {
+        pairs(input)
+            .into_iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    fn tags(input: &str) -> Vec<(String, bool)> {
+        parse(input)
+            .tags
+            .into_iter()
+            .map(|t| (t.name, t.exclude))
+            .collect()
+    }
+
+    #[test]
+    fn titles() {
+        let cases = [
+            ("berserk", "berserk"),
+            ("one  piece", "one piece"),
+            (r#""slice of life""#, "slice of life"),
+            (r#"the "end: of" days"#, "the end: of days"),
+            ("title:berserk tag:action", "berserk"),
+            (r#"title:"made in abyss" extra"#, "made in abyss extra"),
+            ("tag:action 進撃の巨人", "進撃の巨人"),
+            ("Re:Zero", "Re:Zero"),
+            (r#"re:"zero kara" lang:en"#, "re:zero kara"),
+            ("Steins;Gate tag:scifi -foo:bar", "Steins;Gate -foo:bar"),
+            ("foo:", "foo:"),
+        ];
+        for (input, title) in cases {
+            assert_eq!(values(input, "title"), [title], "{input}");
+        }
+        assert!(values("tag:action", "title").is_empty());
+        assert!(values("", "title").is_empty());
+    }
+
+    #[test]
+    fn tags_are_pending() {
+        let cases: &[(&str, &[(&str, bool)])] = &[
+            ("tag:action", &[("action", false)]),
+            ("-tag:romance", &[("romance", true)]),
+            (
+                "tag:action,comedy -tag:romance",
+                &[("action", false), ("comedy", false), ("romance", true)],
+            ),
+            (r#"tag:"slice of life""#, &[("slice of life", false)]),
+            ("TAG:Gore", &[("Gore", false)]),
+            ("tag:action,", &[("action", false)]),
+            ("tag:,action,,gore", &[("action", false), ("gore", false)]),
+        ];
+        for (input, expected) in cases {
+            let expected: Vec<_> = expected.iter().map(|(n, e)| (n.to_string(), *e)).collect();
+            assert_eq!(tags(input), expected, "{input}");
+        }
+        assert_eq!(values("tagmode:any", "includedTagsMode"), ["OR"]);
+    }
+
+    #[test]
+    fn filters() {
+        let cases: &[(&str, &str, &[&str])] = &[
+            ("lang:en,fr", "availableTranslatedLanguage[]", &["en", "fr"]),
+            ("orig:ja", "originalLanguage[]", &["ja"]),
+            ("-orig:ko", "excludedOriginalLanguage[]", &["ko"]),
+            ("status:Ongoing", "status[]", &["ongoing"]),
+            (
+                "rating:safe,suggestive",
+                "contentRating[]",
+                &["safe", "suggestive"],
+            ),
+            (
+                "demo:none,seinen",
+                "publicationDemographic[]",
+                &["none", "seinen"],
+            ),
+            ("year:1989", "year", &["1989"]),
+            ("year:none", "year", &["none"]),
+            ("chapters:yes", "hasAvailableChapters", &["true"]),
+            (
+                "created:2021-03-04",
+                "createdAtSince",
+                &["2021-03-04T00:00:00"],
+            ),
+        ];
+        for (input, key, expected) in cases {
+            assert_eq!(values(input, key), *expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn sort() {
+        let cases: &[(&str, &[(&str, &str)])] = &[
+            ("berserk", &[]),
+            ("sort:follows", &[("order[followedCount]", "desc")]),
+            ("sort:title", &[("order[title]", "asc")]),
+            ("sort:year:asc", &[("order[year]", "asc")]),
+            (
+                "sort:rating,created:asc",
+                &[("order[rating]", "desc"), ("order[createdAt]", "asc")],
+            ),
+            (
+                "sort:latest sort:title",
+                &[
+                    ("order[latestUploadedChapter]", "desc"),
+                    ("order[title]", "asc"),
+                ],
+            ),
+            // setting a criteria again changes its direction, not its priority
+            (
+                "sort:year,follows,year:asc",
+                &[("order[year]", "asc"), ("order[followedCount]", "desc")],
+            ),
+        ];
+        for (input, expected) in cases {
+            let order: Vec<_> = pairs(input)
+                .into_iter()
+                .filter(|(k, _)| k.starts_with("order["))
+                .collect();
+            let expected: Vec<_> = expected
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            assert_eq!(order, expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let cases: &[(&str, Range<usize>)] = &[
+            (r#"berserk "guts"#, 8..13),
+            ("tag:", 0..4),
+            ("-status:ongoing", 0..15),
+            ("status:paused", 0..13),
+            ("lang:en,klingon year:1989", 0..15),
+            ("year:soon", 0..9),
+            ("sort:follows:up", 0..15),
+            ("sort:pages", 0..10),
+            ("author:someone", 0..14),
+            ("created:yesterday", 0..17),
+            ("進撃 year:x", 7..13),
+        ];
+        for (input, span) in cases {
+            match SearchQuery::parse(input) {
+                Ok(_) => panic!("{input} should fail"),
+                Err(e) => assert_eq!(e.span, *span, "{input}: {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn error_kinds() {
+        let kind = |input| SearchQuery::parse(input).err().unwrap().kind;
+        assert!(matches!(kind(r#""a"#), SearchErrorKind::UnterminatedQuote));
+        assert!(matches!(kind("lang:"), SearchErrorKind::MissingValue(k) if k == "lang"));
+        assert!(matches!(kind("tag:,"), SearchErrorKind::MissingValue(k) if k == "tag"));
+        assert!(matches!(kind("-year:1"), SearchErrorKind::NotNegatable(k) if k == "year"));
+        assert!(matches!(
+            kind("rating:nsfw"),
+            SearchErrorKind::InvalidValue(k, v) if k == "rating" && v == "nsfw"
+        ));
+    }
+
+    #[test]
+    fn pointer() {
+        let input = "進撃 year:x";
+        let err = SearchQuery::parse(input).err().unwrap();
+        // 進撃 is four columns wide
+        assert_eq!(err.pointer(input), "     ^^^^^^");
+        let input = "é year:x";
+        let err = SearchQuery::parse(input).err().unwrap();
+        assert_eq!(err.pointer(input), "  ^^^^^^");
+    }
+}
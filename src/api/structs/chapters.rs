@@ -0,0 +1,636 @@
+// Ordered view of the chapters of a manga. The api gives chapters and volumes as strings ("10.5",
+// "Extra", or nothing at all), so they are sorted with a comparator that compares runs of digits
+// as numbers.
+
+use std::{cmp::Ordering, collections::HashMap, ops::Range};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ids::{ChapterId, GroupId},
+    lang_codes::LanguageCode,
+    Chapter,
+};
+
+/// Compare two strings, treating runs of digits as numbers (i.e. "2" < "10", "10.5" < "10.10").
+/// At the same position, numbers come before anything else.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |it: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = it.peek().copied().filter(char::is_ascii_digit) {
+                        digits.push(c);
+                        it.next();
+                    }
+                    digits
+                };
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                // compare without parsing to not care about overflows: leading zeroes don't
+                // matter, then the longest number is the biggest.
+                let x = x.trim_start_matches('0');
+                let y = y.trim_start_matches('0');
+                match x.len().cmp(&y.len()).then_with(|| x.cmp(y)) {
+                    Ordering::Equal => {}
+                    o => return o,
+                }
+            }
+            (Some(x), Some(_)) if x.is_ascii_digit() => return Ordering::Less,
+            (Some(_), Some(y)) if y.is_ascii_digit() => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                match x.to_lowercase().cmp(y.to_lowercase()) {
+                    Ordering::Equal => {}
+                    o => return o,
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Compare optional numbers, missing ones go last.
+pub fn option_cmp(a: Option<&str>, b: Option<&str>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => natural_cmp(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Integer part of a chapter number, if it is a number at all.
+fn chapter_integer(chapter: &Chapter) -> Option<u64> {
+    let number = chapter.chapter.as_deref()?.trim();
+    number.split('.').next()?.parse().ok()
+}
+
+/// Which upload to pick when a chapter has several (different languages or groups).
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct TranslationPreferences {
+    /// Languages in order of preference, uploads in other languages are ignored. Empty means any
+    /// language.
+    pub languages: Vec<LanguageCode>,
+    /// Scanlation groups in order of preference, other groups come after them.
+    pub groups: Vec<GroupId>,
+}
+
+impl TranslationPreferences {
+    pub fn accepts(&self, entry: &ChapterEntry) -> bool {
+        self.languages.is_empty() || self.languages.contains(&entry.chapter.translated_language)
+    }
+
+    /// Lower is better.
+    fn rank(&self, entry: &ChapterEntry) -> (usize, usize) {
+        let lang = self
+            .languages
+            .iter()
+            .position(|l| *l == entry.chapter.translated_language)
+            .unwrap_or(usize::MAX);
+        let group = entry
+            .groups
+            .iter()
+            .filter_map(|g| self.groups.iter().position(|p| p == g))
+            .min()
+            .unwrap_or(usize::MAX);
+        (lang, group)
+    }
+
+    /// The best upload, the first one wins on ties.
+    fn best<'a>(
+        &self,
+        uploads: impl Iterator<Item = &'a ChapterEntry>,
+    ) -> Option<&'a ChapterEntry> {
+        uploads
+            .filter(|e| self.accepts(e))
+            .enumerate()
+            .min_by_key(|(i, e)| (self.rank(e), *i))
+            .map(|(_, e)| e)
+    }
+}
+
+#[derive(Clone)]
+pub struct ChapterEntry {
+    pub id: ChapterId,
+    pub chapter: Chapter,
+    /// Scanlation groups of the upload.
+    pub groups: Vec<GroupId>,
+    /// Whether some chapters are missing between this one and the previous one.
+    pub gap_before: bool,
+}
+
+impl ChapterEntry {
+    pub fn volume(&self) -> Option<&str> {
+        self.chapter.volume.as_deref()
+    }
+    pub fn number(&self) -> Option<&str> {
+        self.chapter.chapter.as_deref()
+    }
+    /// Whether two entries are the same chapter (i.e. uploads of different groups). The volume
+    /// only matters if the numbers reset on each volume. Chapters without a number (oneshots,
+    /// extras) are only the same if they have the same title, in the same volume.
+    pub fn same_chapter(&self, other: &ChapterEntry, resets_on_volume: bool) -> bool {
+        match (self.number(), other.number()) {
+            (Some(a), Some(b)) => a == b && (!resets_on_volume || self.volume() == other.volume()),
+            (None, None) => {
+                let title = self.chapter.title.as_deref().filter(|t| !t.is_empty());
+                title.is_some()
+                    && title == other.chapter.title.as_deref()
+                    && self.volume() == other.volume()
+            }
+            _ => false,
+        }
+    }
+    pub fn shares_group(&self, other: &ChapterEntry) -> bool {
+        self.groups.iter().any(|g| other.groups.contains(g))
+    }
+}
+
+/// Flag the entries after which chapters are missing.
+fn mark_gaps(entries: &mut [ChapterEntry], resets_on_volume: bool) {
+    let mut previous: Option<(Option<String>, u64)> = None;
+    for e in entries {
+        let current = match chapter_integer(&e.chapter) {
+            Some(n) => n,
+            None => continue,
+        };
+        e.gap_before = match &previous {
+            Some((volume, n)) => {
+                let comparable = !resets_on_volume || volume.as_deref() == e.volume();
+                comparable && current > n + 1
+            }
+            None => false,
+        };
+        previous = Some((e.chapter.volume.clone(), current));
+    }
+}
+
+/// Give the entries without a volume the (lowest) volume of another upload with the same number.
+fn fill_volumes(entries: &mut [ChapterEntry]) {
+    let mut volumes: HashMap<String, String> = HashMap::new();
+    for e in entries.iter() {
+        if let (Some(n), Some(v)) = (e.number(), e.volume()) {
+            volumes
+                .entry(n.to_owned())
+                .and_modify(|cur| {
+                    if natural_cmp(v, cur) == Ordering::Less {
+                        *cur = v.to_owned();
+                    }
+                })
+                .or_insert_with(|| v.to_owned());
+        }
+    }
+    for e in entries.iter_mut().filter(|e| e.volume().is_none()) {
+        let volume = e.number().and_then(|n| volumes.get(n)).cloned();
+        e.chapter.volume = volume;
+    }
+}
+
+/// Chapters of a manga, sorted by volume then by chapter number.
+#[derive(Clone)]
+pub struct ChapterList {
+    entries: Vec<ChapterEntry>,
+    resets_on_volume: bool,
+}
+
+impl ChapterList {
+    /// Sort the chapters (with the groups of each upload), if the numbers reset on each volume,
+    /// gaps are only looked for inside volumes. Otherwise, uploads without a volume take the one
+    /// of another upload of the same chapter, so that all uploads of a chapter end up together.
+    pub fn new(
+        chapters: impl IntoIterator<Item = (ChapterId, Chapter, Vec<GroupId>)>,
+        resets_on_volume: bool,
+    ) -> Self {
+        let mut entries: Vec<ChapterEntry> = chapters
+            .into_iter()
+            .map(|(id, chapter, groups)| ChapterEntry {
+                id,
+                chapter,
+                groups,
+                gap_before: false,
+            })
+            .collect();
+
+        if !resets_on_volume {
+            fill_volumes(&mut entries);
+        }
+
+        // chapters without a number are sorted by title, to keep the uploads of one together
+        entries.sort_by(|a, b| {
+            option_cmp(a.volume(), b.volume())
+                .then_with(|| option_cmp(a.number(), b.number()))
+                .then_with(|| match (a.number(), b.number()) {
+                    (None, None) => a.chapter.title.cmp(&b.chapter.title),
+                    _ => std::cmp::Ordering::Equal,
+                })
+        });
+
+        mark_gaps(&mut entries, resets_on_volume);
+
+        Self {
+            entries,
+            resets_on_volume,
+        }
+    }
+
+    /// Keep only one upload per chapter, the best one according to the preferences. Chapters
+    /// without any upload in an accepted language are dropped.
+    pub fn deduplicated(&self, prefs: &TranslationPreferences) -> ChapterList {
+        let mut entries = Vec::new();
+        let mut start = 0;
+        while start < self.entries.len() {
+            let first = &self.entries[start];
+            // untitled extras aren't the same chapter as anything, not even themselves
+            let len = 1 + self.entries[start + 1..]
+                .iter()
+                .take_while(|e| e.same_chapter(first, self.resets_on_volume))
+                .count();
+            if let Some(best) = prefs.best(self.entries[start..start + len].iter()) {
+                entries.push(best.clone());
+            }
+            start += len;
+        }
+        mark_gaps(&mut entries, self.resets_on_volume);
+
+        ChapterList {
+            entries,
+            resets_on_volume: self.resets_on_volume,
+        }
+    }
+
+    /// Replace the upload with this id (or add it, without groups), the list is sorted again.
+    pub fn set_chapter(&mut self, id: ChapterId, chapter: Chapter) {
+        let mut entries: Vec<_> = std::mem::take(&mut self.entries)
+            .into_iter()
+            .map(|e| (e.id, e.chapter, e.groups))
+            .collect();
+        match entries.iter_mut().find(|(i, _, _)| *i == id) {
+            Some(e) => e.1 = chapter,
+            None => entries.push((id, chapter, Vec::new())),
+        }
+        *self = Self::new(entries, self.resets_on_volume);
+    }
+
+    pub fn entries(&self) -> &[ChapterEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn position(&self, id: &ChapterId) -> Option<usize> {
+        self.entries.iter().position(|e| e.id == *id)
+    }
+
+    pub fn get(&self, id: &ChapterId) -> Option<&ChapterEntry> {
+        self.position(id).map(|i| &self.entries[i])
+    }
+
+    /// The chapter after this one, uploads of the same chapter are skipped.
+    pub fn next(&self, id: &ChapterId) -> Option<&ChapterEntry> {
+        let i = self.position(id)?;
+        let current = &self.entries[i];
+        self.entries[i + 1..]
+            .iter()
+            .find(|e| !e.same_chapter(current, self.resets_on_volume))
+    }
+
+    /// The chapter before this one, uploads of the same chapter are skipped.
+    pub fn previous(&self, id: &ChapterId) -> Option<&ChapterEntry> {
+        let i = self.position(id)?;
+        let current = &self.entries[i];
+        self.entries[..i]
+            .iter()
+            .rev()
+            .find(|e| !e.same_chapter(current, self.resets_on_volume))
+    }
+
+    /// Pick the upload of the chapter `e` to read after `current`: one from the same group if
+    /// there is one, else the preferred one.
+    fn pick_upload(
+        &self,
+        e: &ChapterEntry,
+        current: &ChapterEntry,
+        prefs: &TranslationPreferences,
+    ) -> Option<&ChapterEntry> {
+        let uploads = || {
+            self.entries
+                .iter()
+                .filter(|u| u.same_chapter(e, self.resets_on_volume) && prefs.accepts(u))
+        };
+        uploads()
+            .find(|u| u.shares_group(current))
+            .or_else(|| prefs.best(uploads()))
+    }
+
+    /// The next chapter with an accepted upload, from the same group as this one if possible.
+    pub fn next_preferred(
+        &self,
+        id: &ChapterId,
+        prefs: &TranslationPreferences,
+    ) -> Option<&ChapterEntry> {
+        let i = self.position(id)?;
+        let current = &self.entries[i];
+        let next = self.entries[i + 1..]
+            .iter()
+            .find(|e| !e.same_chapter(current, self.resets_on_volume) && prefs.accepts(e))?;
+        self.pick_upload(next, current, prefs)
+    }
+
+    /// The previous chapter with an accepted upload, from the same group as this one if possible.
+    pub fn previous_preferred(
+        &self,
+        id: &ChapterId,
+        prefs: &TranslationPreferences,
+    ) -> Option<&ChapterEntry> {
+        let i = self.position(id)?;
+        let current = &self.entries[i];
+        let previous = self.entries[..i]
+            .iter()
+            .rev()
+            .find(|e| !e.same_chapter(current, self.resets_on_volume) && prefs.accepts(e))?;
+        self.pick_upload(previous, current, prefs)
+    }
+
+    /// Chapters grouped by volume, in order. Chapters without a volume are in the last group.
+    pub fn volumes(&self) -> Vec<(Option<&str>, &[ChapterEntry])> {
+        let mut res = Vec::new();
+        let mut range: Range<usize> = 0..0;
+
+        for (i, e) in self.entries.iter().enumerate() {
+            if i > 0 && e.volume() != self.entries[range.start].volume() {
+                res.push((
+                    self.entries[range.start].volume(),
+                    &self.entries[range.clone()],
+                ));
+                range.start = i;
+            }
+            range.end = i + 1;
+        }
+        if !range.is_empty() {
+            res.push((
+                self.entries[range.start].volume(),
+                &self.entries[range.clone()],
+            ));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::api::structs::test_chapter;
+
+    fn chapter(volume: Option<&str>, number: Option<&str>) -> (ChapterId, Chapter, Vec<GroupId>) {
+        let chapter = test_chapter(volume, number);
+        (Uuid::new_v4().into(), chapter, Vec::new())
+    }
+
+    fn titles(list: &ChapterList) -> Vec<Option<&str>> {
+        list.entries()
+            .iter()
+            .map(|e| e.chapter.title.as_deref())
+            .collect()
+    }
+
+    fn numbers(list: &ChapterList) -> Vec<(Option<&str>, Option<&str>)> {
+        list.entries()
+            .iter()
+            .map(|e| (e.volume(), e.number()))
+            .collect()
+    }
+
+    #[test]
+    fn natural_order() {
+        let sorted = [
+            "0", "0.5", "1", "1.5", "2", "2.1", "2.5", "2.10", "10", "10.5", "11", "100", "a",
+            "Extra", "extra 2", "extra 10", "none",
+        ];
+        for (i, a) in sorted.iter().enumerate() {
+            assert_eq!(natural_cmp(a, a), Ordering::Equal, "{a}");
+            for b in &sorted[i + 1..] {
+                assert_eq!(natural_cmp(a, b), Ordering::Less, "{a} < {b}");
+                assert_eq!(natural_cmp(b, a), Ordering::Greater, "{b} > {a}");
+            }
+        }
+    }
+
+    #[test]
+    fn natural_equalities() {
+        let cases = [("01", "1"), ("007.5", "7.5"), ("Extra", "extra"), ("", "")];
+        for (a, b) in cases {
+            assert_eq!(natural_cmp(a, b), Ordering::Equal, "{a} = {b}");
+        }
+        // doesn't overflow
+        assert_eq!(
+            natural_cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn missing_numbers_go_last() {
+        assert_eq!(option_cmp(Some("10"), None), Ordering::Less);
+        assert_eq!(option_cmp(None, Some("1")), Ordering::Greater);
+        assert_eq!(option_cmp(None, None), Ordering::Equal);
+        assert_eq!(option_cmp(Some("2"), Some("10")), Ordering::Less);
+    }
+
+    #[test]
+    fn list_order() {
+        let list = ChapterList::new(
+            [
+                chapter(None, None),
+                chapter(Some("2"), Some("10")),
+                chapter(Some("1"), Some("2")),
+                chapter(None, Some("12")),
+                chapter(Some("1"), Some("1.5")),
+                chapter(Some("10"), Some("100")),
+                chapter(Some("1"), Some("1")),
+                chapter(None, Some("11")),
+            ],
+            false,
+        );
+        assert_eq!(
+            numbers(&list),
+            [
+                (Some("1"), Some("1")),
+                (Some("1"), Some("1.5")),
+                (Some("1"), Some("2")),
+                (Some("2"), Some("10")),
+                (Some("10"), Some("100")),
+                (None, Some("11")),
+                (None, Some("12")),
+                // oneshot
+                (None, None),
+            ]
+        );
+        let volumes: Vec<_> = list.volumes().iter().map(|(v, e)| (*v, e.len())).collect();
+        assert_eq!(
+            volumes,
+            [(Some("1"), 3), (Some("2"), 1), (Some("10"), 1), (None, 3)]
+        );
+    }
+
+    #[test]
+    fn changed_chapters_are_sorted_again() {
+        let (id, mut changed, _) = chapter(Some("1"), Some("1"));
+        let mut list = ChapterList::new(
+            [
+                (id, changed.clone(), Vec::new()),
+                chapter(Some("1"), Some("2")),
+            ],
+            false,
+        );
+        changed.chapter = Some("3".to_owned());
+        list.set_chapter(id, changed);
+        assert_eq!(
+            numbers(&list),
+            [(Some("1"), Some("2")), (Some("1"), Some("3"))]
+        );
+        assert_eq!(list.position(&id), Some(1));
+
+        let (new, chapter, _) = chapter(Some("1"), Some("1"));
+        list.set_chapter(new, chapter);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.position(&new), Some(0));
+    }
+
+    #[test]
+    fn gaps() {
+        let gaps = |chapters: Vec<_>, resets| {
+            ChapterList::new(chapters, resets)
+                .entries()
+                .iter()
+                .map(|e| e.gap_before)
+                .collect::<Vec<_>>()
+        };
+        let chapters = || {
+            vec![
+                chapter(Some("1"), Some("1")),
+                chapter(Some("1"), Some("1.5")),
+                chapter(Some("1"), Some("2")),
+                chapter(Some("2"), Some("1")),
+                chapter(Some("2"), Some("4")),
+            ]
+        };
+        assert_eq!(gaps(chapters(), true), [false, false, false, false, true]);
+        assert_eq!(gaps(chapters(), false), [false, false, false, false, true]);
+        // when numbers reset on each volume, chapters of different volumes aren't compared
+        let chapters = || vec![chapter(Some("1"), Some("1")), chapter(Some("2"), Some("5"))];
+        assert_eq!(gaps(chapters(), true), [false, false]);
+        assert_eq!(gaps(chapters(), false), [false, true]);
+    }
+
+    fn uploads(resets: bool) -> (ChapterList, GroupId, GroupId) {
+        let a: GroupId = Uuid::new_v4().into();
+        let b: GroupId = Uuid::new_v4().into();
+        let with = |(id, chapter, _): (ChapterId, Chapter, Vec<GroupId>), g: GroupId| {
+            (id, chapter, vec![g])
+        };
+        let titled = |(id, mut chapter, groups): (ChapterId, Chapter, Vec<GroupId>), t: &str| {
+            chapter.title = Some(t.to_owned());
+            (id, chapter, groups)
+        };
+        let list = ChapterList::new(
+            [
+                with(chapter(Some("1"), Some("1")), a),
+                with(chapter(None, Some("1")), b),
+                with(chapter(Some("1"), Some("2")), a),
+                with(chapter(None, Some("3")), b),
+                titled(with(chapter(None, None), a), "Extra"),
+                with(chapter(None, None), a),
+                titled(with(chapter(None, None), a), "Omake"),
+                titled(with(chapter(None, None), b), "Extra"),
+                with(chapter(None, None), b),
+            ],
+            resets,
+        );
+        (list, a, b)
+    }
+
+    #[test]
+    fn dedup_ignores_missing_volumes() {
+        let (list, a, b) = uploads(false);
+        // the upload without a volume is sorted with the other one
+        assert_eq!(
+            numbers(&list),
+            [
+                (Some("1"), Some("1")),
+                (Some("1"), Some("1")),
+                (Some("1"), Some("2")),
+                (None, Some("3")),
+                (None, None),
+                (None, None),
+                (None, None),
+                (None, None),
+                (None, None),
+            ]
+        );
+        let prefs = TranslationPreferences {
+            groups: vec![b],
+            ..Default::default()
+        };
+        let dedup = list.deduplicated(&prefs);
+        assert_eq!(
+            numbers(&dedup),
+            [
+                (Some("1"), Some("1")),
+                (Some("1"), Some("2")),
+                (None, Some("3")),
+                (None, None),
+                (None, None),
+                (None, None),
+                (None, None),
+            ]
+        );
+        assert_eq!(dedup.entries()[0].groups, [b]);
+        assert_eq!(dedup.entries()[1].groups, [a]);
+        // only the uploads of the extra with the same title are merged
+        assert_eq!(
+            titles(&dedup)[3..],
+            [None, None, Some("Extra"), Some("Omake")]
+        );
+        assert_eq!(dedup.entries()[5].groups, [b]);
+
+        let first = list.entries()[0].id;
+        let next = list.next(&first).unwrap();
+        assert_eq!(next.number(), Some("2"));
+    }
+
+    #[test]
+    fn dedup_keeps_volumes_when_numbers_reset() {
+        let (list, _, _) = uploads(true);
+        let dedup = list.deduplicated(&TranslationPreferences::default());
+        assert_eq!(
+            numbers(&dedup),
+            [
+                (Some("1"), Some("1")),
+                (Some("1"), Some("2")),
+                (None, Some("1")),
+                (None, Some("3")),
+                (None, None),
+                (None, None),
+                (None, None),
+                (None, None),
+            ]
+        );
+    }
+}
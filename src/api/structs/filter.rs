@@ -0,0 +1,432 @@
+// Filters for the search endpoints, they are serialized into the query of the request (see
+// api::query).
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{
+    de::{IntoDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{
+    ids::{AuthorId, GroupId, MangaId, TagId},
+    json::data,
+    lang_codes::LanguageCode,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum IncludeMode {
+    And,
+    Or,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MangaListOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MangaListOrderCriteria {
+    Title,
+    Year,
+    CreatedAt,
+    UpdatedAt,
+    LatestUploadedChapter,
+    FollowedCount,
+    Relevance,
+    Rating,
+}
+
+/// Some parameters accept the special value "none" (i.e. no year, no demographic).
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum OrNone<T> {
+    Value(T),
+    #[serde(serialize_with = "serialize_none_str")]
+    None,
+}
+
+fn serialize_none_str<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("none")
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for OrNone<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // strings are tried first, "none" would be taken as a value by enums that accept
+        // unknown ones
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw<T> {
+            Str(String),
+            Value(T),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Str(s) if s == "none" => Ok(OrNone::None),
+            Raw::Str(s) => T::deserialize(s.into_deserializer()).map(OrNone::Value),
+            Raw::Value(v) => Ok(OrNone::Value(v)),
+        }
+    }
+}
+
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// The api doesn't accept timezones in dates, and wants them without any fraction of second.
+fn serialize_date<S: Serializer>(
+    date: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match date {
+        Some(d) => serializer.collect_str(&d.format(DATE_FORMAT)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_date<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) => NaiveDateTime::parse_from_str(&s, DATE_FORMAT)
+            .map(|d| Some(Utc.from_utc_datetime(&d)))
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Orders are serialized as a map, in the order they were added as the first one has priority.
+fn serialize_order<S: Serializer>(
+    order: &[(MangaListOrderCriteria, MangaListOrder)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(order.iter().map(|(k, v)| (k, v)))
+}
+
+fn deserialize_order<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(MangaListOrderCriteria, MangaListOrder)>, D::Error> {
+    // a visitor is needed to keep the order of the entries
+    struct OrderVisitor;
+
+    impl<'de> Visitor<'de> for OrderVisitor {
+        type Value = Vec<(MangaListOrderCriteria, MangaListOrder)>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a map of sorting criterias to orders")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut res = Vec::with_capacity(map.size_hint().unwrap_or(0));
+            while let Some(entry) = map.next_entry()? {
+                res.push(entry);
+            }
+            Ok(res)
+        }
+    }
+
+    deserializer.deserialize_map(OrderVisitor)
+}
+
+/// Filter for the /manga endpoint, serialized into the query of the request.
+///
+/// Built with chained calls, this one sorts by follow count:
+/// ```ignore
+/// MangaListFilter::default()
+///     .title("berserk")
+///     .include_tag(action)
+///     .content_rating(ContentRating::Safe)
+///     .order(MangaListOrderCriteria::FollowedCount, MangaListOrder::Desc)
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MangaListFilter {
+    title: Option<String>,
+    author_or_artist: Option<AuthorId>,
+    authors: Vec<AuthorId>,
+    artists: Vec<AuthorId>,
+    year: Option<OrNone<i32>>,
+    included_tags: Vec<TagId>,
+    included_tags_mode: IncludeMode,
+    excluded_tags: Vec<TagId>,
+    excluded_tags_mode: IncludeMode,
+    status: Vec<data::MangaStatus>,
+    original_language: Vec<LanguageCode>,
+    excluded_original_language: Vec<LanguageCode>,
+    available_translated_language: Vec<LanguageCode>,
+    publication_demographic: Vec<OrNone<data::PublicationDemographic>>,
+    ids: Vec<MangaId>,
+    content_rating: Vec<data::ContentRating>,
+    #[serde(
+        serialize_with = "serialize_date",
+        deserialize_with = "deserialize_date"
+    )]
+    created_at_since: Option<DateTime<Utc>>,
+    #[serde(
+        serialize_with = "serialize_date",
+        deserialize_with = "deserialize_date"
+    )]
+    updated_at_since: Option<DateTime<Utc>>,
+    has_available_chapters: Option<bool>,
+    group: Option<GroupId>,
+    #[serde(
+        serialize_with = "serialize_order",
+        deserialize_with = "deserialize_order"
+    )]
+    order: Vec<(MangaListOrderCriteria, MangaListOrder)>,
+}
+
+impl Default for MangaListFilter {
+    fn default() -> Self {
+        Self {
+            title: None,
+            author_or_artist: None,
+            authors: Vec::new(),
+            artists: Vec::new(),
+            year: None,
+            included_tags: Vec::new(),
+            included_tags_mode: IncludeMode::And,
+            excluded_tags: Vec::new(),
+            excluded_tags_mode: IncludeMode::Or,
+            status: Vec::new(),
+            original_language: Vec::new(),
+            excluded_original_language: Vec::new(),
+            available_translated_language: Vec::new(),
+            publication_demographic: Vec::new(),
+            ids: Vec::new(),
+            content_rating: Vec::new(),
+            created_at_since: None,
+            updated_at_since: None,
+            has_available_chapters: None,
+            group: None,
+            order: Vec::new(),
+        }
+    }
+}
+
+impl MangaListFilter {
+    /// Search in the titles (and alt titles).
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+    /// Only mangas where this person is either an author or an artist.
+    pub fn author_or_artist(mut self, id: AuthorId) -> Self {
+        self.author_or_artist = Some(id);
+        self
+    }
+    pub fn author(mut self, id: AuthorId) -> Self {
+        self.authors.push(id);
+        self
+    }
+    pub fn artist(mut self, id: AuthorId) -> Self {
+        self.artists.push(id);
+        self
+    }
+    pub fn year(mut self, year: i32) -> Self {
+        self.year = Some(OrNone::Value(year));
+        self
+    }
+    /// Only mangas without a year.
+    pub fn no_year(mut self) -> Self {
+        self.year = Some(OrNone::None);
+        self
+    }
+    pub fn include_tag(mut self, id: TagId) -> Self {
+        self.included_tags.push(id);
+        self
+    }
+    pub fn include_tags(mut self, ids: impl IntoIterator<Item = TagId>) -> Self {
+        self.included_tags.extend(ids);
+        self
+    }
+    /// Whether mangas need all (And) or any (Or) of the included tags. Defaults to And.
+    pub fn include_tags_mode(mut self, mode: IncludeMode) -> Self {
+        self.included_tags_mode = mode;
+        self
+    }
+    pub fn exclude_tag(mut self, id: TagId) -> Self {
+        self.excluded_tags.push(id);
+        self
+    }
+    pub fn exclude_tags(mut self, ids: impl IntoIterator<Item = TagId>) -> Self {
+        self.excluded_tags.extend(ids);
+        self
+    }
+    /// Whether mangas are excluded if they have all (And) or any (Or) of the excluded tags.
+    /// Defaults to Or.
+    pub fn exclude_tags_mode(mut self, mode: IncludeMode) -> Self {
+        self.excluded_tags_mode = mode;
+        self
+    }
+    pub fn status(mut self, status: data::MangaStatus) -> Self {
+        self.status.push(status);
+        self
+    }
+    pub fn original_language(mut self, lang: LanguageCode) -> Self {
+        self.original_language.push(lang);
+        self
+    }
+    pub fn exclude_original_language(mut self, lang: LanguageCode) -> Self {
+        self.excluded_original_language.push(lang);
+        self
+    }
+    pub fn translated_language(mut self, lang: LanguageCode) -> Self {
+        self.available_translated_language.push(lang);
+        self
+    }
+    pub fn demographic(mut self, demographic: data::PublicationDemographic) -> Self {
+        self.publication_demographic
+            .push(OrNone::Value(demographic));
+        self
+    }
+    /// Include mangas without any demographic.
+    pub fn no_demographic(mut self) -> Self {
+        self.publication_demographic.push(OrNone::None);
+        self
+    }
+    pub fn id(mut self, id: MangaId) -> Self {
+        self.ids.push(id);
+        self
+    }
+    pub fn ids(mut self, ids: impl IntoIterator<Item = MangaId>) -> Self {
+        self.ids.extend(ids);
+        self
+    }
+    pub fn content_rating(mut self, rating: data::ContentRating) -> Self {
+        self.content_rating.push(rating);
+        self
+    }
+    pub fn created_at_since(mut self, date: DateTime<Utc>) -> Self {
+        self.created_at_since = Some(date);
+        self
+    }
+    pub fn updated_at_since(mut self, date: DateTime<Utc>) -> Self {
+        self.updated_at_since = Some(date);
+        self
+    }
+    /// Only mangas that have (or don't have) chapters in the translated languages (or in any
+    /// language if there are none).
+    pub fn has_available_chapters(mut self, has: bool) -> Self {
+        self.has_available_chapters = Some(has);
+        self
+    }
+    /// Only mangas translated by this scanlation group.
+    pub fn group(mut self, id: GroupId) -> Self {
+        self.group = Some(id);
+        self
+    }
+    /// Add a sorting criteria, criterias added first take priority. Setting an already present
+    /// criteria changes its direction without changing its priority. Without any criteria,
+    /// mangadex sorts by latest uploaded chapter (descending).
+    pub fn order(mut self, criteria: MangaListOrderCriteria, order: MangaListOrder) -> Self {
+        match self.order.iter_mut().find(|(c, _)| *c == criteria) {
+            Some(e) => e.1 = order,
+            None => self.order.push((criteria, order)),
+        }
+        self
+    }
+    /// Remove all sorting criterias.
+    pub fn clear_order(mut self) -> Self {
+        self.order.clear();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::api::query;
+
+    fn date(s: &str) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(s, DATE_FORMAT).unwrap())
+    }
+
+    fn round_trip(filter: &MangaListFilter) -> MangaListFilter {
+        let json = serde_json::to_string(filter).unwrap();
+        match serde_json::from_str(&json) {
+            Ok(f) => f,
+            Err(e) => panic!("{json}: {e}"),
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let filter = MangaListFilter::default()
+            .title("berserk")
+            .author(Uuid::new_v4().into())
+            .no_year()
+            .include_tag(Uuid::new_v4().into())
+            .exclude_tags_mode(IncludeMode::And)
+            .status(data::MangaStatus::Ongoing)
+            .translated_language(LanguageCode::English)
+            .demographic(data::PublicationDemographic::Seinen)
+            .no_demographic()
+            .content_rating(data::ContentRating::Safe)
+            .created_at_since(date("2021-03-04T05:06:07"))
+            .updated_at_since(date("1999-12-31T23:59:59"))
+            .has_available_chapters(true)
+            .order(MangaListOrderCriteria::Year, MangaListOrder::Asc)
+            .order(MangaListOrderCriteria::FollowedCount, MangaListOrder::Desc)
+            .order(MangaListOrderCriteria::Title, MangaListOrder::Asc);
+        let back = round_trip(&filter);
+
+        assert_eq!(back, filter);
+        assert_eq!(back.year, Some(OrNone::None));
+        assert_eq!(
+            back.publication_demographic,
+            [
+                OrNone::Value(data::PublicationDemographic::Seinen),
+                OrNone::None
+            ]
+        );
+        assert_eq!(
+            query::to_pairs(&back).unwrap(),
+            query::to_pairs(&filter).unwrap()
+        );
+    }
+
+    #[test]
+    fn order_priority_survives() {
+        // the criterias are deliberately not in alphabetical order
+        let criterias = [
+            MangaListOrderCriteria::Relevance,
+            MangaListOrderCriteria::CreatedAt,
+            MangaListOrderCriteria::Year,
+            MangaListOrderCriteria::Rating,
+        ];
+        let filter = criterias.iter().fold(MangaListFilter::default(), |f, c| {
+            f.order(*c, MangaListOrder::Desc)
+        });
+        let back = round_trip(&filter);
+        let order: Vec<_> = back.order.iter().map(|(c, _)| *c).collect();
+        assert_eq!(order, criterias);
+    }
+
+    #[test]
+    fn empty_and_partial() {
+        let back = round_trip(&MangaListFilter::default());
+        assert!(back.order.is_empty());
+        assert!(back.created_at_since.is_none());
+        assert!(query::to_pairs(&back)
+            .unwrap()
+            .iter()
+            .all(|(k, _)| k.ends_with("Mode")));
+
+        // missing fields take their default value
+        let filter: MangaListFilter = serde_json::from_str(
+            r#"{"title":"abc","year":1989,"createdAtSince":"2020-01-02T03:04:05"}"#,
+        )
+        .unwrap();
+        assert_eq!(filter.title.as_deref(), Some("abc"));
+        assert_eq!(filter.year, Some(OrNone::Value(1989)));
+        assert_eq!(filter.created_at_since, Some(date("2020-01-02T03:04:05")));
+        assert_eq!(filter.included_tags_mode, IncludeMode::And);
+        assert_eq!(filter.excluded_tags_mode, IncludeMode::Or);
+    }
+}
@@ -0,0 +1,304 @@
+// Typed ids, so that a chapter id can't be given where a manga id is expected. They are
+// serialized as the uuid they wrap.
+
+use std::{
+    fmt::{Debug, Display},
+    hash::Hash,
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{json::data, AtHomeServerChapter, Manga, Volume};
+
+/// Anything that can identify an object in the api cache.
+pub trait Id:
+    Copy + Eq + Hash + Display + Debug + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+    fn uuid(&self) -> Uuid;
+    fn from_uuid(uuid: Uuid) -> Self;
+}
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Types stored in the api cache, with the type of their id.
+pub trait HasId {
+    type Id: Id;
+    /// How long the data can be used before it should be fetched again, None if it doesn't
+    /// change.
+    const TTL: Option<Duration> = None;
+    /// How long the data is kept at all, for data that can't be used after some time.
+    const EXPIRES_AFTER: Option<Duration> = None;
+
+    /// Rough size of the object in memory, for the cache budget.
+    fn size(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+
+    /// Version of the object (mangadex objects have one), used to not replace cached data with
+    /// older data.
+    fn version(&self) -> Option<i32> {
+        None
+    }
+}
+
+macro_rules! id_type {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #[serde(transparent)]
+        pub struct $name(pub Uuid);
+
+        impl Id for $name {
+            fn uuid(&self) -> Uuid {
+                self.0
+            }
+            fn from_uuid(uuid: Uuid) -> Self {
+                Self(uuid)
+            }
+        }
+
+        impl From<Uuid> for $name {
+            fn from(v: Uuid) -> Self {
+                Self(v)
+            }
+        }
+
+        impl From<$name> for Uuid {
+            fn from(v: $name) -> Self {
+                v.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = uuid::Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Uuid::parse_str(s).map(Self)
+            }
+        }
+    };
+}
+
+id_type!(MangaId);
+id_type!(ChapterId);
+id_type!(CoverId);
+id_type!(
+    /// Id of a scanlation group.
+    GroupId
+);
+id_type!(
+    /// Id of an author, artists are authors too.
+    AuthorId
+);
+id_type!(
+    /// Id of a custom list.
+    ListId
+);
+id_type!(TagId);
+id_type!(
+    /// Id of a user, like the uploader of a chapter.
+    UserId
+);
+id_type!(
+    /// Id of the at-home server data of a chapter, made up when it's fetched.
+    AtHomeId
+);
+id_type!(
+    /// Id of the api object, that the lists of everything (like all the tags) are linked to.
+    ApiId
+);
+
+/// Id of a volume of a manga. Volumes aren't mangadex objects, so their id is made from the manga
+/// and the volume name, to be the same each time the aggregate is fetched.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct VolumeId(pub Uuid);
+
+impl VolumeId {
+    pub fn new(manga: MangaId, volume: &str) -> Self {
+        // two fnv-1a hashes of the name (with different offsets) mixed into the manga id. This
+        // needs to be stable across runs, so no DefaultHasher.
+        let fnv = |offset: u64| {
+            volume
+                .bytes()
+                .fold(offset, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+        };
+        let hash = (fnv(0xcbf29ce484222325) as u128) << 64 | fnv(0x84222325cbf29ce4) as u128;
+        Self(Uuid::from_u128(manga.0.as_u128() ^ hash))
+    }
+}
+
+impl Id for VolumeId {
+    fn uuid(&self) -> Uuid {
+        self.0
+    }
+    fn from_uuid(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+}
+
+impl Display for VolumeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl HasId for Manga {
+    type Id = MangaId;
+    const TTL: Option<Duration> = Some(HOUR);
+
+    fn version(&self) -> Option<i32> {
+        Some(self.version)
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.title.size()
+            + self.description.size()
+            + self.alt_titles.iter().map(|t| t.size()).sum::<usize>()
+            + self.tags.len() * std::mem::size_of::<TagId>()
+    }
+}
+impl HasId for data::Manga {
+    type Id = MangaId;
+
+    fn version(&self) -> Option<i32> {
+        Some(self.version)
+    }
+}
+impl HasId for data::Chapter {
+    type Id = ChapterId;
+    const TTL: Option<Duration> = Some(HOUR);
+
+    fn version(&self) -> Option<i32> {
+        Some(self.version)
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + [&self.title, &self.volume, &self.chapter, &self.external_url]
+                .iter()
+                .map(|s| s.as_ref().map_or(0, |s| s.len()))
+                .sum::<usize>()
+    }
+}
+impl HasId for data::CoverArt {
+    type Id = CoverId;
+    const TTL: Option<Duration> = Some(DAY);
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.file_name.len()
+            + self.description.as_ref().map_or(0, |d| d.len())
+    }
+}
+impl HasId for data::Author {
+    type Id = AuthorId;
+    const TTL: Option<Duration> = Some(DAY);
+
+    fn version(&self) -> Option<i32> {
+        Some(self.version)
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.name.len()
+    }
+}
+impl HasId for data::Tag {
+    type Id = TagId;
+    const TTL: Option<Duration> = Some(DAY);
+
+    fn version(&self) -> Option<i32> {
+        Some(self.version)
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.name.size() + self.description.size()
+    }
+}
+
+impl HasId for Volume {
+    type Id = VolumeId;
+    const TTL: Option<Duration> = Some(HOUR);
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.volume.len()
+            + self
+                .chapters
+                .iter()
+                .map(|c| {
+                    std::mem::size_of::<data::VolumeChapter>()
+                        + c.chapter.len()
+                        + c.others.len() * std::mem::size_of::<ChapterId>()
+                })
+                .sum::<usize>()
+    }
+}
+// objects we make ourselves, they have random ids
+impl HasId for AtHomeServerChapter {
+    type Id = AtHomeId;
+
+    // the base url isn't valid for long
+    const EXPIRES_AFTER: Option<Duration> = Some(Duration::from_secs(15 * 60));
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.base_url.len()
+            + self.hash.len()
+            + self
+                .data
+                .iter()
+                .chain(&self.data_saver)
+                .map(|p| p.len())
+                .sum::<usize>()
+    }
+}
+// the api object
+impl HasId for () {
+    type Id = ApiId;
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn ids_are_serialized_as_uuids() {
+        let uuid = Uuid::new_v4();
+        let manga = MangaId(uuid);
+        assert_eq!(
+            serde_json::to_value(manga).unwrap(),
+            json!(uuid.to_string())
+        );
+        assert_eq!(
+            serde_json::from_value::<MangaId>(json!(uuid.to_string())).unwrap(),
+            manga
+        );
+
+        let chapters = vec![ChapterId(uuid), ChapterId(Uuid::nil())];
+        let value = serde_json::to_value(&chapters).unwrap();
+        assert_eq!(value, json!([uuid.to_string(), Uuid::nil().to_string()]));
+        assert_eq!(
+            serde_json::from_value::<Vec<ChapterId>>(value).unwrap(),
+            chapters
+        );
+
+        let volume = VolumeId::new(manga, "1");
+        let value = serde_json::to_value(volume).unwrap();
+        assert_eq!(value, json!(volume.0.to_string()));
+        assert_eq!(serde_json::from_value::<VolumeId>(value).unwrap(), volume);
+    }
+}
@@ -2,6 +2,8 @@
 pub mod body {
     use serde::Serialize;
 
+    use crate::api::structs::ids::ChapterId;
+
     // /auth/login
     #[derive(Serialize)]
     pub struct AuthLogin {
@@ -14,16 +16,24 @@ pub mod body {
     pub struct AuthRefresh {
         pub token: String,
     }
+
+    // /manga/{id}/read
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct MangaRead {
+        pub chapter_ids_read: Vec<ChapterId>,
+        pub chapter_ids_unread: Vec<ChapterId>,
+    }
 }
 
 /// body or responses (server -> client)
 pub mod responses {
-    use std::collections::HashMap;
+    use std::ops::{Deref, DerefMut};
 
-    use serde::Deserialize;
-    use uuid::Uuid;
+    use serde::{Deserialize, Deserializer};
 
     use super::data::{self, Chapter, Manga, Wrapper};
+    use crate::api::structs::ids::{ChapterId, MangaId};
 
     /// Trait to work with responses with pagination
     pub trait Paginate {
@@ -32,6 +42,66 @@ pub mod responses {
         fn count(&self) -> i32;
     }
 
+    /// List of objects where the ones that can't be deserialized are skipped (and logged), so
+    /// that one odd object doesn't fail the whole response.
+    pub struct Records<T> {
+        items: Vec<T>,
+        skipped: i32,
+    }
+
+    impl<T> Records<T> {
+        /// Number of objects sent by the api, including the skipped ones (needed for pagination).
+        pub fn received(&self) -> i32 {
+            self.items.len() as i32 + self.skipped
+        }
+        pub fn append(&mut self, other: &mut Self) {
+            self.items.append(&mut other.items);
+            self.skipped += std::mem::take(&mut other.skipped);
+        }
+    }
+
+    impl<T> Deref for Records<T> {
+        type Target = Vec<T>;
+
+        fn deref(&self) -> &Self::Target {
+            &self.items
+        }
+    }
+    impl<T> DerefMut for Records<T> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.items
+        }
+    }
+
+    impl<T> IntoIterator for Records<T> {
+        type Item = T;
+        type IntoIter = std::vec::IntoIter<T>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.items.into_iter()
+        }
+    }
+
+    impl<'de, T: serde::de::DeserializeOwned> Deserialize<'de> for Records<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let values = Vec::<serde_json::Value>::deserialize(deserializer)?;
+            let mut res = Records {
+                items: Vec::with_capacity(values.len()),
+                skipped: 0,
+            };
+            for v in values {
+                match serde_json::from_value(v) {
+                    Ok(item) => res.items.push(item),
+                    Err(e) => {
+                        log::warn!("Skipped an object that couldn't be deserialized ({e})");
+                        res.skipped += 1;
+                    }
+                }
+            }
+            Ok(res)
+        }
+    }
+
     // POST /auth/login
     #[derive(Deserialize)]
     pub struct AuthLoginToken {
@@ -61,7 +131,7 @@ pub mod responses {
     // GET /manga/{id}/feed
     #[derive(Deserialize)]
     pub struct MangaFeed {
-        pub data: Vec<Wrapper<Chapter>>,
+        pub data: Records<Wrapper<Chapter>>,
         pub total: i32,
     }
 
@@ -82,34 +152,42 @@ pub mod responses {
         pub chapter: data::AtHomeServerChapter,
 
         // not actually sent by the api, but necesary to know which chapter this is.
-        pub chapter_id: Option<Uuid>,
+        pub chapter_id: Option<ChapterId>,
     }
 
     // GET /manga
     #[derive(Deserialize)]
     pub struct MangaList {
-        pub data: Vec<Wrapper<data::Manga>>,
+        pub data: Records<Wrapper<data::Manga>>,
         pub total: i32,
     }
 
     // GET /manga/{id}/aggregate
     #[derive(Deserialize)]
     pub struct MangaAggregate {
-        pub volumes: HashMap<String, data::Volume>,
+        #[serde(deserialize_with = "data::map_or_seq")]
+        pub volumes: Vec<data::Volume>,
 
-        pub manga_id: Option<Uuid>,
+        pub manga_id: Option<MangaId>,
     }
 
     // GET /manga/tag
     #[derive(Deserialize)]
     pub struct MangaTag {
-        pub data: Vec<Wrapper<data::Tag>>,
+        pub data: Records<Wrapper<data::Tag>>,
     }
 
     // GET /cover
     #[derive(Deserialize)]
     pub struct CoverArtList {
-        pub data: Vec<Wrapper<data::CoverArt>>,
+        pub data: Records<Wrapper<data::CoverArt>>,
+        pub total: i32,
+    }
+
+    // GET /author
+    #[derive(Deserialize)]
+    pub struct AuthorList {
+        pub data: Records<Wrapper<data::Author>>,
         pub total: i32,
     }
 
@@ -135,7 +213,7 @@ pub mod responses {
             self.data.append(&mut o.data);
         }
         fn count(&self) -> i32 {
-            self.data.len() as i32
+            self.data.received()
         }
     }
 
@@ -147,7 +225,7 @@ pub mod responses {
             self.data.append(&mut o.data);
         }
         fn count(&self) -> i32 {
-            self.data.len() as i32
+            self.data.received()
         }
     }
 
@@ -159,59 +237,77 @@ pub mod responses {
             self.data.append(&mut o.data);
         }
         fn count(&self) -> i32 {
-            self.data.len() as i32
+            self.data.received()
         }
     }
 }
 
 /// General data, objects as defined by the openapi specs.
 pub mod data {
-    use serde::{Deserialize, Serialize};
-    use std::collections::HashMap;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serialize};
+    use std::collections::{BTreeMap, HashMap};
     use uuid::Uuid;
 
-    use crate::api::structs::lang_codes::LanguageCode;
+    use crate::api::structs::{
+        ids::{AuthorId, ChapterId, CoverId, GroupId, HasId, ListId, MangaId, TagId, UserId},
+        lang_codes::LanguageCode,
+        links::ExternalLink,
+        tags::TagGroup,
+    };
 
     // Enums
 
-    #[derive(Deserialize, Serialize, Debug, Clone)]
-    #[serde(rename_all = "snake_case")]
+    #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(from = "String", into = "String")]
     pub enum PublicationDemographic {
         Shounen,
         Shoujo,
         Josei,
         Seinen,
+
+        /// Values added to the api after this was written.
+        Unknown(String),
     }
 
-    #[derive(Deserialize, Serialize, Debug, Clone)]
-    #[serde(rename_all = "snake_case")]
+    #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(from = "String", into = "String")]
     pub enum ContentRating {
         Safe,
         Suggestive,
         Erotica,
         Pornographic,
+
+        /// Values added to the api after this was written.
+        Unknown(String),
     }
 
-    #[derive(Deserialize, Debug, Clone)]
-    #[serde(rename_all = "snake_case")]
+    #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(from = "String", into = "String")]
     pub enum MangaState {
         Draft,
         Submitted,
         Published,
         Rejected,
+
+        /// Values added to the api after this was written.
+        Unknown(String),
     }
 
-    #[derive(Deserialize, Serialize, Debug, Clone)]
-    #[serde(rename_all = "snake_case")]
+    #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(from = "String", into = "String")]
     pub enum MangaStatus {
         Ongoing,
         Completed,
         Hiatus,
         Cancelled,
+
+        /// Values added to the api after this was written.
+        Unknown(String),
     }
 
-    #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
-    #[serde(rename_all = "snake_case")]
+    #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(from = "String", into = "String")]
     pub enum RelationshipKind {
         Manga,
         Chapter,
@@ -227,10 +323,13 @@ pub mod data {
         AtHome,
         Volume,
         MainCoverArt,
+
+        /// Values added to the api after this was written.
+        Unknown(String),
     }
 
-    #[derive(Deserialize, Debug, Clone)]
-    #[serde(rename_all = "snake_case")]
+    #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(from = "String")]
     pub enum RelatedManga {
         Monochrome,
         Colored,
@@ -248,6 +347,9 @@ pub mod data {
         SharedUniverse,
         AlternateStory,
         AlternateVersion,
+
+        /// Values added to the api after this was written.
+        Unknown(String),
     }
 
     // Structs
@@ -255,8 +357,9 @@ pub mod data {
     /// Most data fromm the api looks like that (plus a type attribute but we don't need it). This
     /// is needed to hold relationships, the actuall data properties are in the attribute field.
     #[derive(Deserialize, Debug, Clone)]
-    pub struct Wrapper<T> {
-        pub id: Uuid,
+    #[serde(bound(deserialize = "T: Deserialize<'de>"))]
+    pub struct Wrapper<T: HasId> {
+        pub id: T::Id,
         pub attributes: T,
         pub relationships: Vec<Relationship>,
     }
@@ -264,53 +367,54 @@ pub mod data {
     /// LocalizedString are a comment occurence in the api, they hold a string in different
     /// languages (not always the sames). This type is here to make working with them easier, as
     /// the underlying type is really just a hashmap.
-    #[derive(Deserialize, Debug, Clone)]
+    /// The api sends an empty array (or null) instead of an empty map, so this can't just derive
+    /// Deserialize.
+    #[derive(Debug, Clone, Default)]
     pub struct LocalizedString(HashMap<LanguageCode, String>);
 
-    /// Represents a relationship between data, related is only filled when the kind is Manga.
-    #[derive(Deserialize, Debug, Clone)]
-    pub struct Relationship {
-        pub id: Uuid,
-        #[serde(rename = "type")]
-        pub kind: RelationshipKind,
-        pub related: Option<RelatedManga>,
+    /// Represents a relationship between data, with the id typed after the kind of the related
+    /// object. related is only sent for mangas.
+    #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(from = "RawRelationship")]
+    pub enum Relationship {
+        Manga {
+            id: MangaId,
+            related: Option<RelatedManga>,
+        },
+        Chapter(ChapterId),
+        CoverArt(CoverId),
+        Author(AuthorId),
+        Artist(AuthorId),
+        ScanlationGroup(GroupId),
+        Tag(TagId),
+        User(UserId),
+        CustomList(ListId),
+
+        /// Kinds added to the api after this was written (and ours, which the api doesn't send).
+        Unknown(RelationshipKind),
     }
 
-    #[derive(Deserialize, Debug, Clone)]
-    #[serde(rename_all = "camelCase")]
-    pub struct MangaAttributesLinks {
-        #[serde(rename = "al")]
-        anilist: Option<String>,
-        #[serde(rename = "ap")]
-        animeplanet: Option<String>,
-        #[serde(rename = "bw")]
-        bookwalker: Option<String>,
-        #[serde(rename = "mu")]
-        mangaupdates: Option<String>,
-        #[serde(rename = "nu")]
-        novelupdates: Option<String>,
-        #[serde(rename = "kt")]
-        kitsu: Option<String>,
-        #[serde(rename = "amz")]
-        amazon: Option<String>,
-        #[serde(rename = "ebj")]
-        ebookjapan: Option<String>,
-        #[serde(rename = "mal")]
-        myanimelist: Option<String>,
-        #[serde(rename = "cdj")]
-        cd_japan: Option<String>,
-        raw: Option<String>,
-        engtl: Option<String>,
-        #[serde(flatten)]
-        other: HashMap<String, String>,
+    /// A relationship as sent by the api, before its id is typed.
+    #[derive(Deserialize)]
+    struct RawRelationship {
+        id: Uuid,
+        #[serde(rename = "type")]
+        kind: RelationshipKind,
+        related: Option<RelatedManga>,
     }
 
-    #[derive(Deserialize, Debug, Clone)]
+    /// Links to other sites, sorted by their id (see ExternalLink).
+    /// Like LocalizedString, the api sends an empty array (or null) when there are no links.
+    #[derive(Serialize, Debug, Clone, Default)]
+    #[serde(into = "BTreeMap<String, String>")]
+    pub struct MangaAttributesLinks(Vec<ExternalLink>);
+
+    #[derive(Deserialize, Serialize, Debug, Clone)]
     pub struct Tag {
         pub name: LocalizedString,
-        // NOTE: removed because most of the time empty and breaks stuff
-        //pub description: Option<LocalizedString>,
-        pub group: String,
+        #[serde(default)]
+        pub description: LocalizedString,
+        pub group: TagGroup,
         pub version: i32,
     }
 
@@ -321,6 +425,7 @@ pub mod data {
         pub alt_titles: Vec<LocalizedString>,
         pub description: LocalizedString,
         pub is_locked: bool,
+        #[serde(default)]
         pub links: MangaAttributesLinks,
         pub original_language: String,
         pub last_volume: Option<String>,
@@ -332,12 +437,42 @@ pub mod data {
         pub chapter_numbers_reset_on_new_volume: bool,
         pub state: MangaState,
         pub version: i32,
-        pub created_at: String,
-        pub updated_at: String,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
         pub tags: Vec<Wrapper<Tag>>,
     }
 
-    #[derive(Deserialize, Debug, Clone)]
+    /// The json attributes of a manga as the api sends them, with some of them replaced, for
+    /// tests.
+    #[cfg(test)]
+    pub fn test_manga_attributes(replaced: serde_json::Value) -> serde_json::Value {
+        let mut attributes = serde_json::json!({
+            "title": { "en": "Title" },
+            "altTitles": [],
+            "description": [],
+            "isLocked": false,
+            "links": { "al": "1" },
+            "originalLanguage": "ja",
+            "lastVolume": null,
+            "lastChapter": null,
+            "publicationDemographic": "seinen",
+            "status": "ongoing",
+            "year": 1989,
+            "contentRating": "safe",
+            "chapterNumbersResetOnNewVolume": false,
+            "state": "published",
+            "version": 1,
+            "createdAt": "2020-01-01T00:00:00+00:00",
+            "updatedAt": "2020-01-01T00:00:00+00:00",
+            "tags": [],
+        });
+        for (k, v) in replaced.as_object().unwrap() {
+            attributes[k] = v.clone();
+        }
+        attributes
+    }
+
+    #[derive(Deserialize, Serialize, Debug, Clone)]
     #[serde(rename_all = "camelCase")]
     pub struct Chapter {
         pub title: Option<String>,
@@ -345,13 +480,33 @@ pub mod data {
         pub chapter: Option<String>,
         pub pages: i32,
         pub translated_language: LanguageCode,
-        pub uploader: Option<Uuid>,
+        pub uploader: Option<UserId>,
         pub external_url: Option<String>,
         pub version: i32,
-        pub created_at: String,
-        pub updated_at: String,
-        pub publish_at: String,
-        pub readable_at: String,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+        pub publish_at: DateTime<Utc>,
+        pub readable_at: DateTime<Utc>,
+    }
+
+    /// A one page english chapter, for tests.
+    #[cfg(test)]
+    pub fn test_chapter(volume: Option<&str>, number: Option<&str>) -> Chapter {
+        let now = Utc::now();
+        Chapter {
+            title: None,
+            volume: volume.map(ToOwned::to_owned),
+            chapter: number.map(ToOwned::to_owned),
+            pages: 1,
+            translated_language: LanguageCode::English,
+            uploader: None,
+            external_url: None,
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            publish_at: now,
+            readable_at: now,
+        }
     }
 
     #[derive(Deserialize)]
@@ -365,44 +520,357 @@ pub mod data {
     #[derive(Deserialize)]
     pub struct Volume {
         pub volume: String,
-        #[serde(rename = "count")]
-        pub chapters: HashMap<String, VolumeChapter>,
+        #[serde(deserialize_with = "map_or_seq")]
+        pub chapters: Vec<VolumeChapter>,
     }
 
-    #[derive(Deserialize, Clone)]
+    /// A chapter of the aggregate, others are the other uploads of the same chapter.
+    #[derive(Deserialize, Serialize, Clone, Debug)]
     pub struct VolumeChapter {
         pub chapter: String,
-        pub id: Uuid,
-        pub others: Vec<Uuid>,
+        pub id: ChapterId,
+        pub others: Vec<ChapterId>,
+    }
+
+    #[derive(Deserialize, Serialize, Clone, Debug)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Author {
+        pub name: String,
+        pub version: i32,
     }
 
-    #[derive(Deserialize, Clone)]
+    #[derive(Deserialize, Serialize, Clone)]
     #[serde(rename_all = "camelCase")]
     pub struct CoverArt {
         pub volume: Option<String>,
         pub file_name: String,
         pub description: Option<String>,
         pub locale: Option<LanguageCode>,
-        pub created_at: String,
-        pub updated_at: String,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    /// The api sends an array instead of a map when the keys are "0", "1"... (or when it's
+    /// empty), only the values are kept.
+    pub fn map_or_seq<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<T>, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw<T> {
+            Map(HashMap<String, T>),
+            Seq(Vec<T>),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Map(m) => m.into_values().collect(),
+            Raw::Seq(s) => s,
+        })
     }
 
     // Impls
 
+    impl From<String> for ContentRating {
+        fn from(v: String) -> Self {
+            match v.as_str() {
+                "safe" => Self::Safe,
+                "suggestive" => Self::Suggestive,
+                "erotica" => Self::Erotica,
+                "pornographic" => Self::Pornographic,
+                _ => {
+                    log::debug!("Unknown content rating ({v})");
+                    Self::Unknown(v)
+                }
+            }
+        }
+    }
+    impl From<ContentRating> for String {
+        fn from(v: ContentRating) -> Self {
+            match v {
+                ContentRating::Safe => "safe".to_owned(),
+                ContentRating::Suggestive => "suggestive".to_owned(),
+                ContentRating::Erotica => "erotica".to_owned(),
+                ContentRating::Pornographic => "pornographic".to_owned(),
+                ContentRating::Unknown(s) => s,
+            }
+        }
+    }
+    impl From<String> for PublicationDemographic {
+        fn from(v: String) -> Self {
+            match v.as_str() {
+                "shounen" => Self::Shounen,
+                "shoujo" => Self::Shoujo,
+                "josei" => Self::Josei,
+                "seinen" => Self::Seinen,
+                _ => {
+                    log::debug!("Unknown publication demographic ({v})");
+                    Self::Unknown(v)
+                }
+            }
+        }
+    }
+    impl From<PublicationDemographic> for String {
+        fn from(v: PublicationDemographic) -> Self {
+            match v {
+                PublicationDemographic::Shounen => "shounen".to_owned(),
+                PublicationDemographic::Shoujo => "shoujo".to_owned(),
+                PublicationDemographic::Josei => "josei".to_owned(),
+                PublicationDemographic::Seinen => "seinen".to_owned(),
+                PublicationDemographic::Unknown(s) => s,
+            }
+        }
+    }
+    impl From<String> for MangaState {
+        fn from(v: String) -> Self {
+            match v.as_str() {
+                "draft" => Self::Draft,
+                "submitted" => Self::Submitted,
+                "published" => Self::Published,
+                "rejected" => Self::Rejected,
+                _ => {
+                    log::debug!("Unknown manga state ({v})");
+                    Self::Unknown(v)
+                }
+            }
+        }
+    }
+    impl From<MangaState> for String {
+        fn from(v: MangaState) -> Self {
+            match v {
+                MangaState::Draft => "draft".to_owned(),
+                MangaState::Submitted => "submitted".to_owned(),
+                MangaState::Published => "published".to_owned(),
+                MangaState::Rejected => "rejected".to_owned(),
+                MangaState::Unknown(s) => s,
+            }
+        }
+    }
+    impl From<String> for MangaStatus {
+        fn from(v: String) -> Self {
+            match v.as_str() {
+                "ongoing" => Self::Ongoing,
+                "completed" => Self::Completed,
+                "hiatus" => Self::Hiatus,
+                "cancelled" => Self::Cancelled,
+                _ => {
+                    log::debug!("Unknown manga status ({v})");
+                    Self::Unknown(v)
+                }
+            }
+        }
+    }
+    impl From<MangaStatus> for String {
+        fn from(v: MangaStatus) -> Self {
+            match v {
+                MangaStatus::Ongoing => "ongoing".to_owned(),
+                MangaStatus::Completed => "completed".to_owned(),
+                MangaStatus::Hiatus => "hiatus".to_owned(),
+                MangaStatus::Cancelled => "cancelled".to_owned(),
+                MangaStatus::Unknown(s) => s,
+            }
+        }
+    }
+    impl From<String> for RelationshipKind {
+        fn from(v: String) -> Self {
+            match v.as_str() {
+                "manga" => Self::Manga,
+                "chapter" => Self::Chapter,
+                "cover_art" => Self::CoverArt,
+                "author" => Self::Author,
+                "artist" => Self::Artist,
+                "scanlation_group" => Self::ScanlationGroup,
+                "tag" => Self::Tag,
+                "user" => Self::User,
+                "custom_list" => Self::CustomList,
+                // ours, only found in the disk cache
+                "at_home" => Self::AtHome,
+                "volume" => Self::Volume,
+                "main_cover_art" => Self::MainCoverArt,
+                _ => {
+                    log::debug!("Unknown relationship ({v})");
+                    Self::Unknown(v)
+                }
+            }
+        }
+    }
+    impl From<RelationshipKind> for String {
+        fn from(v: RelationshipKind) -> Self {
+            match v {
+                RelationshipKind::Manga => "manga".to_owned(),
+                RelationshipKind::Chapter => "chapter".to_owned(),
+                RelationshipKind::CoverArt => "cover_art".to_owned(),
+                RelationshipKind::Author => "author".to_owned(),
+                RelationshipKind::Artist => "artist".to_owned(),
+                RelationshipKind::ScanlationGroup => "scanlation_group".to_owned(),
+                RelationshipKind::Tag => "tag".to_owned(),
+                RelationshipKind::User => "user".to_owned(),
+                RelationshipKind::CustomList => "custom_list".to_owned(),
+                RelationshipKind::AtHome => "at_home".to_owned(),
+                RelationshipKind::Volume => "volume".to_owned(),
+                RelationshipKind::MainCoverArt => "main_cover_art".to_owned(),
+                RelationshipKind::Unknown(s) => s,
+            }
+        }
+    }
+    impl From<String> for RelatedManga {
+        fn from(v: String) -> Self {
+            match v.as_str() {
+                "monochrome" => Self::Monochrome,
+                "colored" => Self::Colored,
+                "preserialization" => Self::Preserialization,
+                "serialization" => Self::Serialization,
+                "prequel" => Self::Prequel,
+                "sequel" => Self::Sequel,
+                "main_story" => Self::MainStory,
+                "side_story" => Self::SideStory,
+                "adapted_from" => Self::AdaptedFrom,
+                "spin_off" => Self::SpinOff,
+                "based_on" => Self::BasedOn,
+                "doujinshi" => Self::Doujinshi,
+                "same_franchise" => Self::SameFranchise,
+                "shared_universe" => Self::SharedUniverse,
+                "alternate_story" => Self::AlternateStory,
+                "alternate_version" => Self::AlternateVersion,
+                _ => {
+                    log::debug!("Unknown manga relation ({v})");
+                    Self::Unknown(v)
+                }
+            }
+        }
+    }
+
     impl LocalizedString {
         pub fn get(&self, lang: LanguageCode) -> Option<String> {
             self.0.get(&lang).cloned()
         }
+        /// Any translation, but always the same one: English if there is one, else the one with
+        /// the smallest language code.
         pub fn any(&self) -> String {
-            self.0
-                .iter()
-                .next()
-                .map(|x| x.1.clone())
+            self.get(LanguageCode::English)
+                .or_else(|| {
+                    self.0
+                        .iter()
+                        .min_by_key(|(k, _)| String::from((*k).clone()))
+                        .map(|x| x.1.clone())
+                })
                 .unwrap_or_default()
         }
         pub fn get_or_any(&self, lang: LanguageCode) -> String {
             self.get(lang).unwrap_or_else(|| self.any())
         }
+        /// The translation in the first language of the list that has one.
+        pub fn get_preferred(&self, langs: &[LanguageCode]) -> Option<String> {
+            langs.iter().find_map(|l| self.0.get(l).cloned())
+        }
+        pub fn preferred_or_any(&self, langs: &[LanguageCode]) -> String {
+            self.get_preferred(langs).unwrap_or_else(|| self.any())
+        }
+        /// Iterate over all the translations of the string.
+        pub fn iter(&self) -> impl Iterator<Item = (&LanguageCode, &String)> {
+            self.0.iter()
+        }
+        /// Rough size in memory (for the api cache).
+        pub fn size(&self) -> usize {
+            self.0
+                .values()
+                .map(|s| std::mem::size_of::<(LanguageCode, String)>() + s.len())
+                .sum()
+        }
+    }
+    impl From<RawRelationship> for Relationship {
+        fn from(r: RawRelationship) -> Self {
+            let id = r.id;
+            match r.kind {
+                RelationshipKind::Manga => Self::Manga {
+                    id: MangaId(id),
+                    related: r.related,
+                },
+                RelationshipKind::Chapter => Self::Chapter(ChapterId(id)),
+                RelationshipKind::CoverArt => Self::CoverArt(CoverId(id)),
+                RelationshipKind::Author => Self::Author(AuthorId(id)),
+                RelationshipKind::Artist => Self::Artist(AuthorId(id)),
+                RelationshipKind::ScanlationGroup => Self::ScanlationGroup(GroupId(id)),
+                RelationshipKind::Tag => Self::Tag(TagId(id)),
+                RelationshipKind::User => Self::User(UserId(id)),
+                RelationshipKind::CustomList => Self::CustomList(ListId(id)),
+                kind => Self::Unknown(kind),
+            }
+        }
+    }
+    impl Relationship {
+        pub fn kind(&self) -> RelationshipKind {
+            match self {
+                Self::Manga { .. } => RelationshipKind::Manga,
+                Self::Chapter(_) => RelationshipKind::Chapter,
+                Self::CoverArt(_) => RelationshipKind::CoverArt,
+                Self::Author(_) => RelationshipKind::Author,
+                Self::Artist(_) => RelationshipKind::Artist,
+                Self::ScanlationGroup(_) => RelationshipKind::ScanlationGroup,
+                Self::Tag(_) => RelationshipKind::Tag,
+                Self::User(_) => RelationshipKind::User,
+                Self::CustomList(_) => RelationshipKind::CustomList,
+                Self::Unknown(kind) => kind.clone(),
+            }
+        }
+    }
+    impl MangaAttributesLinks {
+        pub fn iter(&self) -> impl Iterator<Item = &ExternalLink> {
+            self.0.iter()
+        }
+    }
+    impl From<BTreeMap<String, String>> for MangaAttributesLinks {
+        fn from(m: BTreeMap<String, String>) -> Self {
+            Self(
+                m.into_iter()
+                    .map(|(k, v)| ExternalLink::new(k, v))
+                    .collect(),
+            )
+        }
+    }
+    impl From<MangaAttributesLinks> for BTreeMap<String, String> {
+        fn from(v: MangaAttributesLinks) -> Self {
+            v.0.into_iter().map(|l| l.into_pair()).collect()
+        }
+    }
+    impl<'de> Deserialize<'de> for MangaAttributesLinks {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            #[derive(Deserialize)]
+            #[serde(untagged)]
+            enum Raw {
+                Map(BTreeMap<String, String>),
+                // only empty arrays are accepted
+                Empty([(); 0]),
+                Null(()),
+            }
+
+            Ok(match Raw::deserialize(deserializer)? {
+                Raw::Map(m) => m.into(),
+                Raw::Empty(_) | Raw::Null(_) => Self::default(),
+            })
+        }
+    }
+    impl<'de> Deserialize<'de> for LocalizedString {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            #[derive(Deserialize)]
+            #[serde(untagged)]
+            enum Raw {
+                // some translations are sent as null
+                Map(HashMap<LanguageCode, Option<String>>),
+                // only empty arrays are accepted
+                Empty([(); 0]),
+                Null(()),
+            }
+
+            Ok(match Raw::deserialize(deserializer)? {
+                Raw::Map(m) => Self(m.into_iter().filter_map(|(k, v)| Some((k, v?))).collect()),
+                Raw::Empty(_) | Raw::Null(_) => Self::default(),
+            })
+        }
+    }
+    impl Serialize for LocalizedString {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.0.serialize(serializer)
+        }
     }
     impl From<HashMap<String, String>> for LocalizedString {
         fn from(m: HashMap<String, String>) -> Self {
@@ -410,3 +878,168 @@ pub mod data {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        data::{
+            test_manga_attributes, LocalizedString, MangaAttributesLinks, MangaState,
+            PublicationDemographic, RelatedManga, Relationship, RelationshipKind, Tag,
+        },
+        responses::MangaList,
+    };
+    use crate::api::structs::{
+        ids::{AuthorId, MangaId},
+        lang_codes::LanguageCode,
+        tags::TagGroup,
+    };
+
+    fn manga(attributes: serde_json::Value) -> serde_json::Value {
+        json!({
+            "id": "a96676e5-8ae2-425e-b549-7f15dd34a6d8",
+            "type": "manga",
+            "attributes": test_manga_attributes(attributes),
+            "relationships": [],
+        })
+    }
+
+    #[test]
+    fn unknown_enum_values() {
+        let demographic: PublicationDemographic = serde_json::from_value(json!("kodomo")).unwrap();
+        assert_eq!(
+            demographic,
+            PublicationDemographic::Unknown("kodomo".to_owned())
+        );
+        let state: MangaState = serde_json::from_value(json!("archived")).unwrap();
+        assert_eq!(state, MangaState::Unknown("archived".to_owned()));
+
+        let tag: Tag = serde_json::from_value(json!({
+            "name": { "en": "Action" },
+            "description": [],
+            "group": "mood",
+            "version": 1,
+        }))
+        .unwrap();
+        assert_eq!(tag.group, TagGroup::Unknown("mood".to_owned()));
+
+        // unknown values are sent back as is
+        assert_eq!(serde_json::to_value(&demographic).unwrap(), json!("kodomo"));
+        assert_eq!(
+            serde_json::to_value(TagGroup::Theme).unwrap(),
+            json!("theme")
+        );
+    }
+
+    #[test]
+    fn links_quirks() {
+        for links in [json!(null), json!([]), json!({})] {
+            let parsed: MangaAttributesLinks = serde_json::from_value(links.clone()).unwrap();
+            assert_eq!(parsed.iter().count(), 0, "{links}");
+        }
+        assert!(serde_json::from_value::<MangaAttributesLinks>(json!(["al"])).is_err());
+    }
+
+    #[test]
+    fn odd_mangas_are_kept() {
+        let list: MangaList = serde_json::from_value(json!({
+            "data": [
+                manga(json!({})),
+                manga(json!({ "links": [], "publicationDemographic": "kodomo" })),
+                manga(json!({ "links": null, "state": "archived" })),
+            ],
+            "total": 3,
+        }))
+        .unwrap();
+        assert_eq!(list.data.len(), 3);
+        assert_eq!(list.data[0].attributes.links.iter().count(), 1);
+        assert_eq!(list.data[1].attributes.links.iter().count(), 0);
+    }
+
+    #[test]
+    fn bad_records_are_skipped() {
+        let list: MangaList = serde_json::from_value(json!({
+            "data": [
+                manga(json!({ "title": { "en": "First" } })),
+                manga(json!({ "title": 5 })),
+                manga(json!({ "title": { "en": "Third" } })),
+            ],
+            "total": 3,
+        }))
+        .unwrap();
+        assert_eq!(list.data.len(), 2);
+        assert_eq!(list.data.received(), 3);
+        let titles: Vec<_> = list.data.iter().map(|m| m.attributes.title.any()).collect();
+        assert_eq!(titles, ["First", "Third"]);
+    }
+
+    #[test]
+    fn relationship_ids_are_typed_by_kind() {
+        let id = "a96676e5-8ae2-425e-b549-7f15dd34a6d8";
+        let relationships: Vec<Relationship> = serde_json::from_value(json!([
+            { "id": id, "type": "artist" },
+            { "id": id, "type": "manga", "related": "sequel" },
+            { "id": id, "type": "mod_team" },
+        ]))
+        .unwrap();
+        let uuid = id.parse().unwrap();
+        assert_eq!(
+            relationships,
+            [
+                Relationship::Artist(AuthorId(uuid)),
+                Relationship::Manga {
+                    id: MangaId(uuid),
+                    related: Some(RelatedManga::Sequel),
+                },
+                Relationship::Unknown(RelationshipKind::Unknown("mod_team".to_owned())),
+            ]
+        );
+        assert_eq!(relationships[0].kind(), RelationshipKind::Artist);
+    }
+
+    fn localized(value: serde_json::Value) -> LocalizedString {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn preferred_translations() {
+        let s = localized(json!({ "ja": "ベルセルク", "fr": "Berserk (fr)", "en": "Berserk" }));
+        assert_eq!(
+            s.get_preferred(&[LanguageCode::German, LanguageCode::French])
+                .as_deref(),
+            Some("Berserk (fr)")
+        );
+        assert_eq!(s.get_preferred(&[LanguageCode::German]), None);
+        assert_eq!(s.get_preferred(&[]), None);
+        assert_eq!(
+            s.preferred_or_any(&[LanguageCode::Japanese, LanguageCode::French]),
+            "ベルセルク"
+        );
+        assert_eq!(s.preferred_or_any(&[LanguageCode::German]), "Berserk");
+        assert_eq!(
+            localized(json!([])).preferred_or_any(&[LanguageCode::English]),
+            ""
+        );
+    }
+
+    #[test]
+    fn any_is_always_the_same() {
+        // the map isn't ordered, each one iterates differently
+        for _ in 0..20 {
+            let s = localized(json!({ "ja": "ja", "fr": "fr", "de": "de", "zh": "zh" }));
+            assert_eq!(s.any(), "de");
+            let s = localized(json!({ "ja": "ja", "en": "en", "de": "de" }));
+            assert_eq!(s.any(), "en");
+        }
+        assert_eq!(localized(json!(null)).any(), "");
+    }
+
+    #[test]
+    fn null_translations_are_dropped() {
+        let s = localized(json!({ "en": null, "ja": "タイトル" }));
+        assert_eq!(s.get_preferred(&[LanguageCode::English]), None);
+        assert_eq!(s.preferred_or_any(&[LanguageCode::English]), "タイトル");
+        assert_eq!(localized(json!({ "en": null })).any(), "");
+    }
+}
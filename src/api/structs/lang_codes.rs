@@ -1,10 +1,10 @@
 // Shoutout to Vim Macros
 
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Hash, PartialEq, Eq, Deserialize, Debug, Clone)]
+#[derive(Hash, PartialEq, Eq, Deserialize, Serialize, Debug, Clone)]
 #[serde(from = "String", into = "String")]
 pub enum LanguageCode {
     // OFFICIAL ISO 639-1 CODES
@@ -152,11 +152,15 @@ pub enum LanguageCode {
 
     // MANGADEX SPECIFIC
     TraditionalChinese,   // zh-hk
+    TaiwaneseChinese,     // zh-tw
     BrazilianPortugese,   // pt-br
+    EuropeanPortuguese,   // pt-pt
     LatinAmericanSpanish, // es-la
     RomanizedJapanese,    // ja-ro
     RomanizedKorean,      // ko-ro
     RomanizedChinese,     // zh-ro
+    SerbianCyrillic,      // sr-cyrl
+    SerbianLatin,         // sr-latn
 
     Filipino, // tl
 
@@ -167,6 +171,486 @@ pub enum LanguageCode {
     Any(String),
 }
 
+/// Every known language (so without Tagalog, which has the code of Filipino), i.e. for language
+/// pickers.
+pub const LANGUAGES: &[LanguageCode] = &[
+    LanguageCode::Afar,
+    LanguageCode::Abkhazian,
+    LanguageCode::Afrikaans,
+    LanguageCode::Amharic,
+    LanguageCode::Arabic,
+    LanguageCode::Assamese,
+    LanguageCode::Aymara,
+    LanguageCode::Azerbaijani,
+    LanguageCode::Bashkir,
+    LanguageCode::Byelorussian,
+    LanguageCode::Bulgarian,
+    LanguageCode::Bihari,
+    LanguageCode::Bislama,
+    LanguageCode::Bengali,
+    LanguageCode::Tibetan,
+    LanguageCode::Breton,
+    LanguageCode::Catalan,
+    LanguageCode::Corsican,
+    LanguageCode::Czech,
+    LanguageCode::Welch,
+    LanguageCode::Danish,
+    LanguageCode::German,
+    LanguageCode::Bhutani,
+    LanguageCode::Greek,
+    LanguageCode::English,
+    LanguageCode::Esperanto,
+    LanguageCode::Spanish,
+    LanguageCode::Estonian,
+    LanguageCode::Basque,
+    LanguageCode::Persian,
+    LanguageCode::Finnish,
+    LanguageCode::Fiji,
+    LanguageCode::Faeroese,
+    LanguageCode::French,
+    LanguageCode::Frisian,
+    LanguageCode::Irish,
+    LanguageCode::ScotsGaelic,
+    LanguageCode::Galician,
+    LanguageCode::Guarani,
+    LanguageCode::Gujarati,
+    LanguageCode::Hausa,
+    LanguageCode::Hindi,
+    LanguageCode::Hebrew,
+    LanguageCode::Croatian,
+    LanguageCode::Hungarian,
+    LanguageCode::Armenian,
+    LanguageCode::Interlingua,
+    LanguageCode::Indonesian,
+    LanguageCode::Interlingue,
+    LanguageCode::Inupiak,
+    LanguageCode::Icelandic,
+    LanguageCode::Italian,
+    LanguageCode::Inuktitut,
+    LanguageCode::Japanese,
+    LanguageCode::Javanese,
+    LanguageCode::Georgian,
+    LanguageCode::Kazakh,
+    LanguageCode::Greenlandic,
+    LanguageCode::Cambodian,
+    LanguageCode::Kannada,
+    LanguageCode::Korean,
+    LanguageCode::Kashmiri,
+    LanguageCode::Kurdish,
+    LanguageCode::Kirghiz,
+    LanguageCode::Latin,
+    LanguageCode::Lingala,
+    LanguageCode::Laothian,
+    LanguageCode::Lithuanian,
+    LanguageCode::Latvian,
+    LanguageCode::Malagasy,
+    LanguageCode::Maori,
+    LanguageCode::Macedonian,
+    LanguageCode::Malayalam,
+    LanguageCode::Mongolian,
+    LanguageCode::Moldavian,
+    LanguageCode::Marathi,
+    LanguageCode::Malay,
+    LanguageCode::Maltese,
+    LanguageCode::Burmese,
+    LanguageCode::Nauru,
+    LanguageCode::Nepali,
+    LanguageCode::Dutch,
+    LanguageCode::Norwegian,
+    LanguageCode::Occitan,
+    LanguageCode::Oromo,
+    LanguageCode::Oriya,
+    LanguageCode::Punjabi,
+    LanguageCode::Polish,
+    LanguageCode::Pashto,
+    LanguageCode::Portuguese,
+    LanguageCode::Quechua,
+    LanguageCode::RhaetoRomance,
+    LanguageCode::Kirundi,
+    LanguageCode::Romanian,
+    LanguageCode::Russian,
+    LanguageCode::Kinyarwanda,
+    LanguageCode::Sanskrit,
+    LanguageCode::Sindhi,
+    LanguageCode::Sangro,
+    LanguageCode::SerboCroatian,
+    LanguageCode::Singhalese,
+    LanguageCode::Slovak,
+    LanguageCode::Slovenian,
+    LanguageCode::Samoan,
+    LanguageCode::Shona,
+    LanguageCode::Somali,
+    LanguageCode::Albanian,
+    LanguageCode::Serbian,
+    LanguageCode::Siswati,
+    LanguageCode::Sesotho,
+    LanguageCode::Sudanese,
+    LanguageCode::Swedish,
+    LanguageCode::Swahili,
+    LanguageCode::Tamil,
+    LanguageCode::Tegulu,
+    LanguageCode::Tajik,
+    LanguageCode::Thai,
+    LanguageCode::Tigrinya,
+    LanguageCode::Turkmen,
+    LanguageCode::Setswana,
+    LanguageCode::Tonga,
+    LanguageCode::Turkish,
+    LanguageCode::Tsonga,
+    LanguageCode::Tatar,
+    LanguageCode::Twi,
+    LanguageCode::Uigur,
+    LanguageCode::Ukrainian,
+    LanguageCode::Urdu,
+    LanguageCode::Uzbek,
+    LanguageCode::Vietnamese,
+    LanguageCode::Volapuk,
+    LanguageCode::Wolof,
+    LanguageCode::Xhosa,
+    LanguageCode::Yiddish,
+    LanguageCode::Yoruba,
+    LanguageCode::Zhuang,
+    LanguageCode::Chinese,
+    LanguageCode::Zulu,
+    LanguageCode::TraditionalChinese,
+    LanguageCode::TaiwaneseChinese,
+    LanguageCode::BrazilianPortugese,
+    LanguageCode::EuropeanPortuguese,
+    LanguageCode::LatinAmericanSpanish,
+    LanguageCode::RomanizedJapanese,
+    LanguageCode::RomanizedKorean,
+    LanguageCode::RomanizedChinese,
+    LanguageCode::SerbianCyrillic,
+    LanguageCode::SerbianLatin,
+    LanguageCode::Filipino,
+];
+
+impl LanguageCode {
+    /// Name of the language, in english.
+    pub fn english_name(&self) -> &str {
+        match self {
+            LanguageCode::Afar => "Afar",
+            LanguageCode::Abkhazian => "Abkhazian",
+            LanguageCode::Afrikaans => "Afrikaans",
+            LanguageCode::Amharic => "Amharic",
+            LanguageCode::Arabic => "Arabic",
+            LanguageCode::Assamese => "Assamese",
+            LanguageCode::Aymara => "Aymara",
+            LanguageCode::Azerbaijani => "Azerbaijani",
+            LanguageCode::Bashkir => "Bashkir",
+            LanguageCode::Byelorussian => "Belarusian",
+            LanguageCode::Bulgarian => "Bulgarian",
+            LanguageCode::Bihari => "Bihari",
+            LanguageCode::Bislama => "Bislama",
+            LanguageCode::Bengali => "Bengali",
+            LanguageCode::Tibetan => "Tibetan",
+            LanguageCode::Breton => "Breton",
+            LanguageCode::Catalan => "Catalan",
+            LanguageCode::Corsican => "Corsican",
+            LanguageCode::Czech => "Czech",
+            LanguageCode::Welch => "Welsh",
+            LanguageCode::Danish => "Danish",
+            LanguageCode::German => "German",
+            LanguageCode::Bhutani => "Dzongkha",
+            LanguageCode::Greek => "Greek",
+            LanguageCode::English => "English",
+            LanguageCode::Esperanto => "Esperanto",
+            LanguageCode::Spanish => "Spanish",
+            LanguageCode::Estonian => "Estonian",
+            LanguageCode::Basque => "Basque",
+            LanguageCode::Persian => "Persian",
+            LanguageCode::Finnish => "Finnish",
+            LanguageCode::Fiji => "Fijian",
+            LanguageCode::Faeroese => "Faroese",
+            LanguageCode::French => "French",
+            LanguageCode::Frisian => "Frisian",
+            LanguageCode::Irish => "Irish",
+            LanguageCode::ScotsGaelic => "Scottish Gaelic",
+            LanguageCode::Galician => "Galician",
+            LanguageCode::Guarani => "Guarani",
+            LanguageCode::Gujarati => "Gujarati",
+            LanguageCode::Hausa => "Hausa",
+            LanguageCode::Hindi => "Hindi",
+            LanguageCode::Hebrew => "Hebrew",
+            LanguageCode::Croatian => "Croatian",
+            LanguageCode::Hungarian => "Hungarian",
+            LanguageCode::Armenian => "Armenian",
+            LanguageCode::Interlingua => "Interlingua",
+            LanguageCode::Indonesian => "Indonesian",
+            LanguageCode::Interlingue => "Interlingue",
+            LanguageCode::Inupiak => "Inupiaq",
+            LanguageCode::Icelandic => "Icelandic",
+            LanguageCode::Italian => "Italian",
+            LanguageCode::Inuktitut => "Inuktitut",
+            LanguageCode::Japanese => "Japanese",
+            LanguageCode::Javanese => "Javanese",
+            LanguageCode::Georgian => "Georgian",
+            LanguageCode::Kazakh => "Kazakh",
+            LanguageCode::Greenlandic => "Greenlandic",
+            LanguageCode::Cambodian => "Khmer",
+            LanguageCode::Kannada => "Kannada",
+            LanguageCode::Korean => "Korean",
+            LanguageCode::Kashmiri => "Kashmiri",
+            LanguageCode::Kurdish => "Kurdish",
+            LanguageCode::Kirghiz => "Kyrgyz",
+            LanguageCode::Latin => "Latin",
+            LanguageCode::Lingala => "Lingala",
+            LanguageCode::Laothian => "Lao",
+            LanguageCode::Lithuanian => "Lithuanian",
+            LanguageCode::Latvian => "Latvian",
+            LanguageCode::Malagasy => "Malagasy",
+            LanguageCode::Maori => "Maori",
+            LanguageCode::Macedonian => "Macedonian",
+            LanguageCode::Malayalam => "Malayalam",
+            LanguageCode::Mongolian => "Mongolian",
+            LanguageCode::Moldavian => "Moldavian",
+            LanguageCode::Marathi => "Marathi",
+            LanguageCode::Malay => "Malay",
+            LanguageCode::Maltese => "Maltese",
+            LanguageCode::Burmese => "Burmese",
+            LanguageCode::Nauru => "Nauruan",
+            LanguageCode::Nepali => "Nepali",
+            LanguageCode::Dutch => "Dutch",
+            LanguageCode::Norwegian => "Norwegian",
+            LanguageCode::Occitan => "Occitan",
+            LanguageCode::Oromo => "Oromo",
+            LanguageCode::Oriya => "Odia",
+            LanguageCode::Punjabi => "Punjabi",
+            LanguageCode::Polish => "Polish",
+            LanguageCode::Pashto => "Pashto",
+            LanguageCode::Portuguese => "Portuguese",
+            LanguageCode::Quechua => "Quechua",
+            LanguageCode::RhaetoRomance => "Romansh",
+            LanguageCode::Kirundi => "Kirundi",
+            LanguageCode::Romanian => "Romanian",
+            LanguageCode::Russian => "Russian",
+            LanguageCode::Kinyarwanda => "Kinyarwanda",
+            LanguageCode::Sanskrit => "Sanskrit",
+            LanguageCode::Sindhi => "Sindhi",
+            LanguageCode::Sangro => "Sango",
+            LanguageCode::SerboCroatian => "Serbo-Croatian",
+            LanguageCode::Singhalese => "Sinhala",
+            LanguageCode::Slovak => "Slovak",
+            LanguageCode::Slovenian => "Slovenian",
+            LanguageCode::Samoan => "Samoan",
+            LanguageCode::Shona => "Shona",
+            LanguageCode::Somali => "Somali",
+            LanguageCode::Albanian => "Albanian",
+            LanguageCode::Serbian => "Serbian",
+            LanguageCode::Siswati => "Swati",
+            LanguageCode::Sesotho => "Sotho",
+            LanguageCode::Sudanese => "Sundanese",
+            LanguageCode::Swedish => "Swedish",
+            LanguageCode::Swahili => "Swahili",
+            LanguageCode::Tamil => "Tamil",
+            LanguageCode::Tegulu => "Telugu",
+            LanguageCode::Tajik => "Tajik",
+            LanguageCode::Thai => "Thai",
+            LanguageCode::Tigrinya => "Tigrinya",
+            LanguageCode::Turkmen => "Turkmen",
+            LanguageCode::Setswana => "Tswana",
+            LanguageCode::Tonga => "Tongan",
+            LanguageCode::Turkish => "Turkish",
+            LanguageCode::Tsonga => "Tsonga",
+            LanguageCode::Tatar => "Tatar",
+            LanguageCode::Twi => "Twi",
+            LanguageCode::Uigur => "Uyghur",
+            LanguageCode::Ukrainian => "Ukrainian",
+            LanguageCode::Urdu => "Urdu",
+            LanguageCode::Uzbek => "Uzbek",
+            LanguageCode::Vietnamese => "Vietnamese",
+            LanguageCode::Volapuk => "Volapük",
+            LanguageCode::Wolof => "Wolof",
+            LanguageCode::Xhosa => "Xhosa",
+            LanguageCode::Yiddish => "Yiddish",
+            LanguageCode::Yoruba => "Yoruba",
+            LanguageCode::Zhuang => "Zhuang",
+            LanguageCode::Chinese => "Chinese",
+            LanguageCode::Zulu => "Zulu",
+            LanguageCode::Tagalog => "Tagalog",
+
+            LanguageCode::TraditionalChinese => "Traditional Chinese",
+            LanguageCode::TaiwaneseChinese => "Traditional Chinese (Taiwan)",
+            LanguageCode::BrazilianPortugese => "Brazilian Portuguese",
+            LanguageCode::EuropeanPortuguese => "European Portuguese",
+            LanguageCode::LatinAmericanSpanish => "Latin American Spanish",
+            LanguageCode::RomanizedJapanese => "Romanized Japanese",
+            LanguageCode::RomanizedKorean => "Romanized Korean",
+            LanguageCode::RomanizedChinese => "Romanized Chinese",
+            LanguageCode::SerbianCyrillic => "Serbian (Cyrillic)",
+            LanguageCode::SerbianLatin => "Serbian (Latin)",
+
+            LanguageCode::Filipino => "Filipino",
+
+            LanguageCode::Null => "Unknown",
+
+            LanguageCode::Any(s) => s,
+        }
+    }
+
+    /// Name of the language, in the language itself.
+    pub fn native_name(&self) -> &str {
+        match self {
+            LanguageCode::Afar => "Afaraf",
+            LanguageCode::Abkhazian => "Аҧсуа",
+            LanguageCode::Afrikaans => "Afrikaans",
+            LanguageCode::Amharic => "አማርኛ",
+            LanguageCode::Arabic => "العربية",
+            LanguageCode::Assamese => "অসমীয়া",
+            LanguageCode::Aymara => "Aymar aru",
+            LanguageCode::Azerbaijani => "Azərbaycan dili",
+            LanguageCode::Bashkir => "Башҡорт теле",
+            LanguageCode::Byelorussian => "Беларуская",
+            LanguageCode::Bulgarian => "Български",
+            LanguageCode::Bihari => "भोजपुरी",
+            LanguageCode::Bislama => "Bislama",
+            LanguageCode::Bengali => "বাংলা",
+            LanguageCode::Tibetan => "བོད་ཡིག",
+            LanguageCode::Breton => "Brezhoneg",
+            LanguageCode::Catalan => "Català",
+            LanguageCode::Corsican => "Corsu",
+            LanguageCode::Czech => "Čeština",
+            LanguageCode::Welch => "Cymraeg",
+            LanguageCode::Danish => "Dansk",
+            LanguageCode::German => "Deutsch",
+            LanguageCode::Bhutani => "རྫོང་ཁ",
+            LanguageCode::Greek => "Ελληνικά",
+            LanguageCode::English => "English",
+            LanguageCode::Esperanto => "Esperanto",
+            LanguageCode::Spanish => "Español",
+            LanguageCode::Estonian => "Eesti",
+            LanguageCode::Basque => "Euskara",
+            LanguageCode::Persian => "فارسی",
+            LanguageCode::Finnish => "Suomi",
+            LanguageCode::Fiji => "Vosa Vakaviti",
+            LanguageCode::Faeroese => "Føroyskt",
+            LanguageCode::French => "Français",
+            LanguageCode::Frisian => "Frysk",
+            LanguageCode::Irish => "Gaeilge",
+            LanguageCode::ScotsGaelic => "Gàidhlig",
+            LanguageCode::Galician => "Galego",
+            LanguageCode::Guarani => "Avañe'ẽ",
+            LanguageCode::Gujarati => "ગુજરાતી",
+            LanguageCode::Hausa => "Harshen Hausa",
+            LanguageCode::Hindi => "हिन्दी",
+            LanguageCode::Hebrew => "עברית",
+            LanguageCode::Croatian => "Hrvatski",
+            LanguageCode::Hungarian => "Magyar",
+            LanguageCode::Armenian => "Հայերեն",
+            LanguageCode::Interlingua => "Interlingua",
+            LanguageCode::Indonesian => "Bahasa Indonesia",
+            LanguageCode::Interlingue => "Occidental",
+            LanguageCode::Inupiak => "Iñupiaq",
+            LanguageCode::Icelandic => "Íslenska",
+            LanguageCode::Italian => "Italiano",
+            LanguageCode::Inuktitut => "ᐃᓄᒃᑎᑐᑦ",
+            LanguageCode::Japanese => "日本語",
+            LanguageCode::Javanese => "Basa Jawa",
+            LanguageCode::Georgian => "ქართული",
+            LanguageCode::Kazakh => "Қазақ тілі",
+            LanguageCode::Greenlandic => "Kalaallisut",
+            LanguageCode::Cambodian => "ខ្មែរ",
+            LanguageCode::Kannada => "ಕನ್ನಡ",
+            LanguageCode::Korean => "한국어",
+            LanguageCode::Kashmiri => "कॉशुर",
+            LanguageCode::Kurdish => "Kurdî",
+            LanguageCode::Kirghiz => "Кыргызча",
+            LanguageCode::Latin => "Latina",
+            LanguageCode::Lingala => "Lingála",
+            LanguageCode::Laothian => "ລາວ",
+            LanguageCode::Lithuanian => "Lietuvių",
+            LanguageCode::Latvian => "Latviešu",
+            LanguageCode::Malagasy => "Fiteny malagasy",
+            LanguageCode::Maori => "Te Reo Māori",
+            LanguageCode::Macedonian => "Македонски",
+            LanguageCode::Malayalam => "മലയാളം",
+            LanguageCode::Mongolian => "Монгол",
+            LanguageCode::Moldavian => "Moldovenească",
+            LanguageCode::Marathi => "मराठी",
+            LanguageCode::Malay => "Bahasa Melayu",
+            LanguageCode::Maltese => "Malti",
+            LanguageCode::Burmese => "မြန်မာဘာသာ",
+            LanguageCode::Nauru => "Dorerin Naoero",
+            LanguageCode::Nepali => "नेपाली",
+            LanguageCode::Dutch => "Nederlands",
+            LanguageCode::Norwegian => "Norsk",
+            LanguageCode::Occitan => "Lenga d'òc",
+            LanguageCode::Oromo => "Afaan Oromoo",
+            LanguageCode::Oriya => "ଓଡ଼ିଆ",
+            LanguageCode::Punjabi => "ਪੰਜਾਬੀ",
+            LanguageCode::Polish => "Polski",
+            LanguageCode::Pashto => "پښتو",
+            LanguageCode::Portuguese => "Português",
+            LanguageCode::Quechua => "Runa Simi",
+            LanguageCode::RhaetoRomance => "Rumantsch",
+            LanguageCode::Kirundi => "Ikirundi",
+            LanguageCode::Romanian => "Română",
+            LanguageCode::Russian => "Русский",
+            LanguageCode::Kinyarwanda => "Ikinyarwanda",
+            LanguageCode::Sanskrit => "संस्कृतम्",
+            LanguageCode::Sindhi => "سنڌي",
+            LanguageCode::Sangro => "Sängö",
+            LanguageCode::SerboCroatian => "Srpskohrvatski",
+            LanguageCode::Singhalese => "සිංහල",
+            LanguageCode::Slovak => "Slovenčina",
+            LanguageCode::Slovenian => "Slovenščina",
+            LanguageCode::Samoan => "Gagana Samoa",
+            LanguageCode::Shona => "ChiShona",
+            LanguageCode::Somali => "Soomaali",
+            LanguageCode::Albanian => "Shqip",
+            LanguageCode::Serbian => "Српски",
+            LanguageCode::Siswati => "SiSwati",
+            LanguageCode::Sesotho => "Sesotho",
+            LanguageCode::Sudanese => "Basa Sunda",
+            LanguageCode::Swedish => "Svenska",
+            LanguageCode::Swahili => "Kiswahili",
+            LanguageCode::Tamil => "தமிழ்",
+            LanguageCode::Tegulu => "తెలుగు",
+            LanguageCode::Tajik => "Тоҷикӣ",
+            LanguageCode::Thai => "ไทย",
+            LanguageCode::Tigrinya => "ትግርኛ",
+            LanguageCode::Turkmen => "Türkmençe",
+            LanguageCode::Setswana => "Setswana",
+            LanguageCode::Tonga => "Lea Faka-Tonga",
+            LanguageCode::Turkish => "Türkçe",
+            LanguageCode::Tsonga => "Xitsonga",
+            LanguageCode::Tatar => "Татар теле",
+            LanguageCode::Twi => "Twi kasa",
+            LanguageCode::Uigur => "ئۇيغۇرچە",
+            LanguageCode::Ukrainian => "Українська",
+            LanguageCode::Urdu => "اردو",
+            LanguageCode::Uzbek => "Oʻzbekcha",
+            LanguageCode::Vietnamese => "Tiếng Việt",
+            LanguageCode::Volapuk => "Volapük",
+            LanguageCode::Wolof => "Wollof",
+            LanguageCode::Xhosa => "isiXhosa",
+            LanguageCode::Yiddish => "ייִדיש",
+            LanguageCode::Yoruba => "Yorùbá",
+            LanguageCode::Zhuang => "Vahcuengh",
+            LanguageCode::Chinese => "简体中文",
+            LanguageCode::Zulu => "isiZulu",
+            LanguageCode::Tagalog => "Tagalog",
+
+            LanguageCode::TraditionalChinese => "繁體中文",
+            LanguageCode::TaiwaneseChinese => "繁體中文（臺灣）",
+            LanguageCode::BrazilianPortugese => "Português (Brasil)",
+            LanguageCode::EuropeanPortuguese => "Português (Portugal)",
+            LanguageCode::LatinAmericanSpanish => "Español (Latinoamérica)",
+            LanguageCode::RomanizedJapanese => "Nihongo",
+            LanguageCode::RomanizedKorean => "Hangugeo",
+            LanguageCode::RomanizedChinese => "Zhōngwén",
+            LanguageCode::SerbianCyrillic => "Српски (ћирилица)",
+            LanguageCode::SerbianLatin => "Srpski (latinica)",
+
+            LanguageCode::Filipino => "Filipino",
+
+            LanguageCode::Null => "Unknown",
+
+            LanguageCode::Any(s) => s,
+        }
+    }
+}
+
 impl From<LanguageCode> for String {
     fn from(lang: LanguageCode) -> String {
         match lang {
@@ -311,11 +795,15 @@ impl From<LanguageCode> for String {
             LanguageCode::Zulu => "zu".to_owned(),
 
             LanguageCode::TraditionalChinese => "zh-hk".to_owned(),
+            LanguageCode::TaiwaneseChinese => "zh-tw".to_owned(),
             LanguageCode::BrazilianPortugese => "pt-br".to_owned(),
+            LanguageCode::EuropeanPortuguese => "pt-pt".to_owned(),
             LanguageCode::LatinAmericanSpanish => "es-la".to_owned(),
             LanguageCode::RomanizedJapanese => "ja-ro".to_owned(),
             LanguageCode::RomanizedKorean => "ko-ro".to_owned(),
             LanguageCode::RomanizedChinese => "zh-ro".to_owned(),
+            LanguageCode::SerbianCyrillic => "sr-cyrl".to_owned(),
+            LanguageCode::SerbianLatin => "sr-latn".to_owned(),
 
             LanguageCode::Filipino => "tl".to_owned(),
 
@@ -470,11 +958,15 @@ impl From<String> for LanguageCode {
             "zu" => LanguageCode::Zulu,
 
             "zh-hk" => LanguageCode::TraditionalChinese,
+            "zh-tw" => LanguageCode::TaiwaneseChinese,
             "pt-br" => LanguageCode::BrazilianPortugese,
+            "pt-pt" => LanguageCode::EuropeanPortuguese,
             "es-la" => LanguageCode::LatinAmericanSpanish,
             "ja-ro" => LanguageCode::RomanizedJapanese,
             "ko-ro" => LanguageCode::RomanizedKorean,
             "zh-ro" => LanguageCode::RomanizedChinese,
+            "sr-cyrl" => LanguageCode::SerbianCyrillic,
+            "sr-latn" => LanguageCode::SerbianLatin,
 
             "NULL" => {
                 log::debug!("NULL language found. (This is a problem with mangadex)");
@@ -482,9 +974,8 @@ impl From<String> for LanguageCode {
             }
 
             _ => {
-                // error because if that happens, it means I've forgotten a code, and need to add
-                // it.
-                log::error!("Unknown language code ({})", v);
+                // mangadex adds codes from time to time, they are kept as is
+                log::debug!("Unknown language code ({})", v);
                 LanguageCode::Any(v)
             }
         }
@@ -495,3 +986,99 @@ impl Display for LanguageCode {
         write!(f, "{}", <Self as Into::<String>>::into(self.clone()))
     }
 }
+
+#[derive(Debug)]
+pub struct UnknownLanguage(pub String);
+
+impl Display for UnknownLanguage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown language `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownLanguage {}
+
+/// Parse a language from its code or its name (english or native), ignoring case. Unlike
+/// `From<String>`, unknown languages are an error.
+impl FromStr for LanguageCode {
+    type Err = UnknownLanguage;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        LANGUAGES
+            .iter()
+            .find(|l| {
+                String::from((*l).clone()).eq_ignore_ascii_case(s)
+                    || l.english_name().to_lowercase() == s.to_lowercase()
+                    || l.native_name().to_lowercase() == s.to_lowercase()
+            })
+            .cloned()
+            .ok_or_else(|| UnknownLanguage(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_round_trip() {
+        for lang in LANGUAGES {
+            let code = String::from(lang.clone());
+            assert_eq!(LanguageCode::from(code.clone()), *lang, "{code}");
+        }
+        assert_eq!(
+            LanguageCode::from("xx-yy".to_owned()),
+            LanguageCode::Any("xx-yy".to_owned())
+        );
+        assert_eq!(String::from(LanguageCode::Any("xx-yy".to_owned())), "xx-yy");
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        for lang in LANGUAGES.iter().chain([&LanguageCode::Null]) {
+            let json = serde_json::to_string(lang).unwrap();
+            let back: LanguageCode = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, *lang, "{json}");
+        }
+        assert_eq!(
+            serde_json::to_string(&LanguageCode::English).unwrap(),
+            "\"en\""
+        );
+        assert_eq!(
+            serde_json::to_string(&LanguageCode::EuropeanPortuguese).unwrap(),
+            "\"pt-pt\""
+        );
+    }
+
+    #[test]
+    fn from_str() {
+        for lang in LANGUAGES {
+            let code = String::from(lang.clone());
+            assert_eq!(code.parse::<LanguageCode>().unwrap(), *lang, "{code}");
+            assert_eq!(
+                lang.english_name().parse::<LanguageCode>().unwrap(),
+                *lang,
+                "{}",
+                lang.english_name()
+            );
+            assert_eq!(
+                lang.native_name().parse::<LanguageCode>().unwrap(),
+                *lang,
+                "{}",
+                lang.native_name()
+            );
+        }
+        assert_eq!("EN".parse::<LanguageCode>().unwrap(), LanguageCode::English);
+        assert_eq!(
+            "français".parse::<LanguageCode>().unwrap(),
+            LanguageCode::French
+        );
+        assert_eq!(
+            "sr-Cyrl".parse::<LanguageCode>().unwrap(),
+            LanguageCode::SerbianCyrillic
+        );
+        assert!("klingon".parse::<LanguageCode>().is_err());
+        assert!("NULL".parse::<LanguageCode>().is_err());
+    }
+}
@@ -0,0 +1,154 @@
+// Links of a manga to other sites. The api only gives ids (or slugs) for most trackers, and full
+// urls for stores and official sites.
+
+/// A link to another site, with the id or url given by the api.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalLink {
+    AniList(String),         // al
+    AnimePlanet(String),     // ap
+    BookWalker(String),      // bw
+    MangaUpdates(String),    // mu
+    NovelUpdates(String),    // nu
+    Kitsu(String),           // kt
+    MyAnimeList(String),     // mal
+    Amazon(String),          // amz
+    EbookJapan(String),      // ebj
+    CdJapan(String),         // cdj
+    Raw(String),             // raw
+    OfficialEnglish(String), // engtl
+    /// Unknown kind of link (key, value).
+    Other(String, String),
+}
+
+impl ExternalLink {
+    pub fn new(key: String, value: String) -> Self {
+        match key.as_str() {
+            "al" => Self::AniList(value),
+            "ap" => Self::AnimePlanet(value),
+            "bw" => Self::BookWalker(value),
+            "mu" => Self::MangaUpdates(value),
+            "nu" => Self::NovelUpdates(value),
+            "kt" => Self::Kitsu(value),
+            "mal" => Self::MyAnimeList(value),
+            "amz" => Self::Amazon(value),
+            "ebj" => Self::EbookJapan(value),
+            "cdj" => Self::CdJapan(value),
+            "raw" => Self::Raw(value),
+            "engtl" => Self::OfficialEnglish(value),
+            _ => Self::Other(key, value),
+        }
+    }
+
+    /// Key and value as given by the api.
+    pub fn into_pair(self) -> (String, String) {
+        let (key, value) = match self {
+            Self::AniList(v) => ("al", v),
+            Self::AnimePlanet(v) => ("ap", v),
+            Self::BookWalker(v) => ("bw", v),
+            Self::MangaUpdates(v) => ("mu", v),
+            Self::NovelUpdates(v) => ("nu", v),
+            Self::Kitsu(v) => ("kt", v),
+            Self::MyAnimeList(v) => ("mal", v),
+            Self::Amazon(v) => ("amz", v),
+            Self::EbookJapan(v) => ("ebj", v),
+            Self::CdJapan(v) => ("cdj", v),
+            Self::Raw(v) => ("raw", v),
+            Self::OfficialEnglish(v) => ("engtl", v),
+            Self::Other(key, value) => return (key, value),
+        };
+        (key.to_owned(), value)
+    }
+
+    /// Name of the site, to show to the user.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::AniList(_) => "AniList",
+            Self::AnimePlanet(_) => "Anime-Planet",
+            Self::BookWalker(_) => "BookWalker",
+            Self::MangaUpdates(_) => "MangaUpdates",
+            Self::NovelUpdates(_) => "NovelUpdates",
+            Self::Kitsu(_) => "Kitsu",
+            Self::MyAnimeList(_) => "MyAnimeList",
+            Self::Amazon(_) => "Amazon",
+            Self::EbookJapan(_) => "eBookJapan",
+            Self::CdJapan(_) => "CDJapan",
+            Self::Raw(_) => "Raw",
+            Self::OfficialEnglish(_) => "Official English",
+            Self::Other(key, _) => key,
+        }
+    }
+
+    /// Full url of the link.
+    pub fn url(&self) -> String {
+        match self {
+            Self::AniList(id) => format!("https://anilist.co/manga/{id}"),
+            Self::AnimePlanet(slug) => format!("https://www.anime-planet.com/manga/{slug}"),
+            // i.e. "series/12345"
+            Self::BookWalker(path) => format!("https://bookwalker.jp/{path}"),
+            // old ids are numbers, new ones are base 36
+            Self::MangaUpdates(id) if id.chars().all(|c| c.is_ascii_digit()) => {
+                format!("https://www.mangaupdates.com/series.html?id={id}")
+            }
+            Self::MangaUpdates(id) => format!("https://www.mangaupdates.com/series/{id}"),
+            Self::NovelUpdates(slug) => format!("https://www.novelupdates.com/series/{slug}"),
+            // either an id or a slug, the site accepts both
+            Self::Kitsu(id) => format!("https://kitsu.io/manga/{id}"),
+            Self::MyAnimeList(id) => format!("https://myanimelist.net/manga/{id}"),
+            Self::Amazon(url)
+            | Self::EbookJapan(url)
+            | Self::CdJapan(url)
+            | Self::Raw(url)
+            | Self::OfficialEnglish(url)
+            | Self::Other(_, url) => url.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(key: &str, value: &str) -> ExternalLink {
+        ExternalLink::new(key.to_owned(), value.to_owned())
+    }
+
+    #[test]
+    fn urls() {
+        let cases = [
+            ("al", "30002", "https://anilist.co/manga/30002"),
+            (
+                "ap",
+                "berserk",
+                "https://www.anime-planet.com/manga/berserk",
+            ),
+            ("bw", "series/1234", "https://bookwalker.jp/series/1234"),
+            ("mu", "7", "https://www.mangaupdates.com/series.html?id=7"),
+            (
+                "mu",
+                "ovtotbv",
+                "https://www.mangaupdates.com/series/ovtotbv",
+            ),
+            // base 36 ids starting with digits
+            ("mu", "12ab", "https://www.mangaupdates.com/series/12ab"),
+            ("nu", "slug", "https://www.novelupdates.com/series/slug"),
+            ("kt", "berserk", "https://kitsu.io/manga/berserk"),
+            ("mal", "2", "https://myanimelist.net/manga/2"),
+            ("raw", "https://example.com/r", "https://example.com/r"),
+            ("xyz", "https://example.com/x", "https://example.com/x"),
+        ];
+        for (key, value, url) in cases {
+            assert_eq!(link(key, value).url(), url, "{key}:{value}");
+        }
+    }
+
+    #[test]
+    fn pairs_round_trip() {
+        for key in [
+            "al", "ap", "bw", "mu", "nu", "kt", "mal", "amz", "ebj", "cdj", "raw", "engtl", "xyz",
+        ] {
+            let l = link(key, "v");
+            assert_eq!(l.clone().into_pair(), (key.to_owned(), "v".to_owned()));
+            assert_eq!(l.name() == key, key == "xyz", "{key}");
+        }
+    }
+}
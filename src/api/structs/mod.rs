@@ -1,195 +1,25 @@
-use std::{
-    collections::HashMap,
-    fmt::Display,
-    time::{Duration, Instant},
-};
-
-use crate::api::request::ApiRequestQuery;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
+use self::json::{
+    data::{self, LocalizedString},
+    responses,
+};
 use self::{
-    json::{
-        data::{self, LocalizedString},
-        responses,
-    },
+    ids::{AtHomeId, AuthorId, ChapterId, CoverId, Id, MangaId, TagId, VolumeId},
     lang_codes::LanguageCode,
 };
-use super::{ApiCache, API_UUID};
-use chrono::{DateTime, Utc};
-use serde::Serialize;
+use super::{ApiCache, API_ID};
 use uuid::Uuid;
+pub mod chapters;
+pub mod filter;
+pub mod ids;
 pub mod json;
 pub mod lang_codes;
+pub mod links;
+pub mod tags;
 
-pub enum IncludeMode {
-    And,
-    Or,
-}
-impl Display for IncludeMode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::And => write!(f, "AND"),
-            Self::Or => write!(f, "OR"),
-        }
-    }
-}
-
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub enum MangaListOrder {
-    Asc,
-    Desc,
-}
-
-#[derive(Serialize, Hash, PartialEq, Eq)]
-#[serde(rename_all = "camelCase")]
-pub enum MangaListOrderCriteria {
-    Title,
-    Year,
-    CreatedAt,
-    UpdatedAt,
-    LatestUploadedChapter,
-    FollowedCount,
-    Relevance,
-}
-
-pub struct MangaListFilter {
-    title: Option<String>,
-    authors: Option<Vec<Uuid>>,
-    artists: Option<Vec<Uuid>>,
-    year: Option<i32>,
-    include_tags: Option<Vec<Uuid>>,
-    include_tags_mode: IncludeMode,
-    exclude_tags: Option<Vec<Uuid>>,
-    exclude_tags_mode: IncludeMode,
-    status: Option<Vec<data::MangaStatus>>,
-    original_language: Option<Vec<LanguageCode>>,
-    exclude_original_language: Option<Vec<LanguageCode>>,
-    availible_translated_language: Option<Vec<LanguageCode>>,
-    demographic: Option<Vec<data::PublicationDemographic>>,
-    ids: Option<Vec<Uuid>>,
-    content_rating: Option<Vec<data::ContentRating>>,
-    created_at_since: Option<DateTime<Utc>>,
-    updated_at_since: Option<DateTime<Utc>>,
-    group: Option<Uuid>,
-    order: HashMap<MangaListOrderCriteria, MangaListOrder>,
-}
-
-impl Default for MangaListFilter {
-    fn default() -> Self {
-        let mut order = HashMap::new();
-        order.insert(
-            MangaListOrderCriteria::LatestUploadedChapter,
-            MangaListOrder::Desc,
-        );
-        Self {
-            ids: None,
-            year: None,
-            title: None,
-            group: None,
-            order,
-            status: None,
-            authors: None,
-            artists: None,
-            demographic: None,
-            include_tags: None,
-            exclude_tags: None,
-            content_rating: None,
-            created_at_since: None,
-            updated_at_since: None,
-            include_tags_mode: IncludeMode::And,
-            exclude_tags_mode: IncludeMode::Or,
-            original_language: None,
-            exclude_original_language: None,
-            availible_translated_language: None,
-        }
-    }
-}
-
-impl Display for data::MangaStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            enquote::unquote(&serde_json::to_string(self).unwrap()).unwrap()
-        )
-    }
-}
-
-impl Display for data::PublicationDemographic {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            enquote::unquote(&serde_json::to_string(self).unwrap()).unwrap()
-        )
-    }
-}
-
-impl Display for data::ContentRating {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            enquote::unquote(&serde_json::to_string(self).unwrap()).unwrap()
-        )
-    }
-}
-
-impl Display for MangaListOrder {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            enquote::unquote(&serde_json::to_string(self).unwrap()).unwrap()
-        )
-    }
-}
-
-impl Display for MangaListOrderCriteria {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            enquote::unquote(&serde_json::to_string(self).unwrap()).unwrap()
-        )
-    }
-}
-
-impl MangaListFilter {
-    pub fn to_query(&self) -> ApiRequestQuery {
-        let mut res = ApiRequestQuery::new();
-
-        res.insert("includedTagsMode", &self.include_tags_mode);
-        res.insert("excludedTagsMode", &self.exclude_tags_mode);
-        res.insert_map("order", &self.order);
-        res.insert_option("year", self.year);
-        res.insert_option("title", self.title.as_ref());
-        res.insert_option("group", self.group);
-        res.insert_option(
-            "createdAtSince",
-            self.created_at_since.map(|x| x.format("%Y-%m-%dT%H:%M:%S")),
-        );
-        res.insert_option(
-            "updatedAtSince",
-            self.updated_at_since.map(|x| x.format("%Y-%m-%dT%H:%M:%S")),
-        );
-        res.insert_vec_option("authors", &self.authors);
-        res.insert_vec_option("artists", &self.artists);
-        res.insert_vec_option("includedTags", &self.include_tags);
-        res.insert_vec_option("excludedTags", &self.exclude_tags);
-        res.insert_vec_option("status", &self.status);
-        res.insert_vec_option("originalLanguage", &self.original_language);
-        res.insert_vec_option("excludedOriginalLanguage", &self.exclude_original_language);
-        res.insert_vec_option(
-            "availableTranslatedLanguage",
-            &self.availible_translated_language,
-        );
-        res.insert_vec_option("publicationDemographic", &self.demographic);
-        res.insert_vec_option("ids", &self.ids);
-        res.insert_vec_option("content_rating", &self.content_rating);
-        res
-    }
-}
+pub use filter::MangaListFilter;
 
 /// Trait used to store data gotten from the api (json::responses) into an api cache and return the
 /// obtained object.
@@ -197,7 +27,7 @@ pub trait Store<T> {
     fn store(self, cache: &mut ApiCache) -> T;
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Manga {
     pub title: LocalizedString,
     pub alt_titles: Vec<data::LocalizedString>,
@@ -213,9 +43,10 @@ pub struct Manga {
     pub content_rating: data::ContentRating,
     pub chapter_numbers_reset_on_new_volume: bool,
     pub state: data::MangaState,
+    pub tags: Vec<TagId>,
     pub version: i32,
-    pub created_at: String,
-    pub updated_at: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 impl From<data::Manga> for Manga {
     fn from(v: data::Manga) -> Self {
@@ -225,6 +56,7 @@ impl From<data::Manga> for Manga {
             links: v.links,
             state: v.state,
             status: v.status,
+            tags: v.tags.iter().map(|t| t.id).collect(),
             version: v.version,
             is_locked: v.is_locked,
             created_at: v.created_at,
@@ -240,6 +72,36 @@ impl From<data::Manga> for Manga {
         }
     }
 }
+impl Manga {
+    /// The title in the first preferred language that has one, looking in the alternative titles
+    /// when the main title isn't in that language.
+    pub fn title_in(&self, langs: &[LanguageCode]) -> String {
+        langs
+            .iter()
+            .find_map(|l| {
+                self.title
+                    .get(l.clone())
+                    .or_else(|| self.alt_titles.iter().find_map(|t| t.get(l.clone())))
+            })
+            .unwrap_or_else(|| self.title.any())
+    }
+    pub fn description_in(&self, langs: &[LanguageCode]) -> String {
+        self.description.preferred_or_any(langs)
+    }
+}
+
+/// A manga with the objects it is related to, see Api::manga_details.
+#[derive(Clone, Debug)]
+pub struct MangaDetails {
+    pub id: MangaId,
+    pub manga: Manga,
+    /// Tags that are in the cache, in the order given by the api.
+    pub tags: Vec<(TagId, Tag)>,
+    pub authors: Vec<AuthorId>,
+    pub artists: Vec<AuthorId>,
+    pub main_cover: Option<CoverId>,
+}
+
 #[derive(Clone)]
 pub struct AtHomeServerChapter {
     pub base_url: String,
@@ -248,39 +110,86 @@ pub struct AtHomeServerChapter {
     pub hash: String,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Volume {
+    pub manga: MangaId,
+    /// Name of the volume, "none" for chapters without a volume.
     pub volume: String,
+    /// Sorted by chapter number.
+    pub chapters: Vec<data::VolumeChapter>,
+}
+
+/// Chapters before and after a chapter, see Api::chapter_neighbours.
+#[derive(Clone, Debug)]
+pub struct ChapterNeighbours {
+    pub previous: Option<ChapterId>,
+    pub next: Option<ChapterId>,
+}
+
+/// Order of volumes and chapters in the aggregate, "none" goes last.
+fn aggregate_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    fn none(s: &str) -> Option<&str> {
+        (s != "none").then_some(s)
+    }
+    chapters::option_cmp(none(a), none(b))
 }
 
 // re export types that don't change
+#[cfg(test)]
+pub use data::test_chapter;
+pub use data::Author;
 pub use data::Chapter;
 pub use data::CoverArt;
 pub use data::Tag;
 
 impl Store<Manga> for responses::MangaView {
     fn store(self, cache: &mut ApiCache) -> Manga {
+        let id = self.data.id;
         let tags = self.data.attributes.tags.clone();
         let m: Manga = self.data.attributes.into();
 
-        cache.insert(self.data.id, m.clone(), None);
+        if !cache.insert(id, m.clone()) {
+            // older than what we have, keep the cached manga and its links
+            return cache.get(&id).unwrap_or(m);
+        }
+
+        // links that come from the manga object are replaced, the others (chapters, volumes,
+        // all the covers) come from other requests and are kept
+        for kind in MANGA_OWNED_LINKS {
+            cache.unlink_kind(&id, kind);
+        }
         for t in tags {
-            cache.insert(t.id, t.attributes, None);
-            cache.link(&self.data.id, &t.id, data::RelationshipKind::Tag);
-            cache.link(&t.id, &self.data.id, data::RelationshipKind::Manga);
+            cache.insert(t.id, t.attributes);
+            cache.link(&id, &t.id, data::RelationshipKind::Tag);
+            cache.link(&t.id, &id, data::RelationshipKind::Manga);
+        }
+        for r in &self.data.relationships {
+            // the cover art of a manga is its main cover
+            if let data::Relationship::CoverArt(cover) = r {
+                cache.link(&id, cover, data::RelationshipKind::MainCoverArt);
+            }
         }
         store_relationships(
             cache,
             self.data.relationships,
-            self.data.id,
+            id,
             data::RelationshipKind::Manga,
         );
         m
     }
 }
 
-impl Store<Vec<Uuid>> for responses::MangaList {
-    fn store(self, cache: &mut ApiCache) -> Vec<Uuid> {
+/// Kinds of links from a manga that are given with the manga itself.
+const MANGA_OWNED_LINKS: &[data::RelationshipKind] = &[
+    data::RelationshipKind::Tag,
+    data::RelationshipKind::Author,
+    data::RelationshipKind::Artist,
+    data::RelationshipKind::Manga,
+    data::RelationshipKind::MainCoverArt,
+];
+
+impl Store<Vec<MangaId>> for responses::MangaList {
+    fn store(self, cache: &mut ApiCache) -> Vec<MangaId> {
         let mut res = Vec::with_capacity(self.data.len());
         for m in self.data {
             res.push(m.id);
@@ -290,8 +199,8 @@ impl Store<Vec<Uuid>> for responses::MangaList {
     }
 }
 
-impl Store<Vec<Uuid>> for responses::MangaFeed {
-    fn store(self, cache: &mut ApiCache) -> Vec<Uuid> {
+impl Store<Vec<ChapterId>> for responses::MangaFeed {
+    fn store(self, cache: &mut ApiCache) -> Vec<ChapterId> {
         let mut res = Vec::with_capacity(self.data.len());
         for c in self.data {
             res.push(c.id);
@@ -301,13 +210,26 @@ impl Store<Vec<Uuid>> for responses::MangaFeed {
     }
 }
 
+/// Kinds of links from a chapter that are given with the chapter itself.
+const CHAPTER_OWNED_LINKS: &[data::RelationshipKind] = &[
+    data::RelationshipKind::Manga,
+    data::RelationshipKind::ScanlationGroup,
+    data::RelationshipKind::User,
+];
+
 impl Store<Chapter> for responses::ChapterView {
     fn store(self, cache: &mut ApiCache) -> Chapter {
-        cache.insert(self.data.id, self.data.attributes.clone(), None);
+        let id = self.data.id;
+        if !cache.insert(id, self.data.attributes.clone()) {
+            return cache.get(&id).unwrap_or(self.data.attributes);
+        }
+        for kind in CHAPTER_OWNED_LINKS {
+            cache.unlink_kind(&id, kind);
+        }
         store_relationships(
             cache,
             self.data.relationships,
-            self.data.id,
+            id,
             data::RelationshipKind::Chapter,
         );
         self.data.attributes
@@ -318,7 +240,7 @@ impl Store<AtHomeServerChapter> for responses::AtHomeServer {
     fn store(self, cache: &mut ApiCache) -> AtHomeServerChapter {
         // create new uuid as this isn't a mangadex object, but one we create to represent data
         // that we need to cache
-        let id = Uuid::new_v4();
+        let id = AtHomeId(Uuid::new_v4());
         let m = AtHomeServerChapter {
             base_url: self.base_url,
             data: self.chapter.data,
@@ -326,52 +248,81 @@ impl Store<AtHomeServerChapter> for responses::AtHomeServer {
             hash: self.chapter.hash,
         };
         let cid = self.chapter_id.unwrap();
-        cache.insert(
-            id,
-            m.clone(),
-            Some(Instant::now() + Duration::from_secs(900)),
-        );
+        cache.insert(id, m.clone());
         cache.link(&cid, &id, data::RelationshipKind::AtHome);
         cache.link(&id, &cid, data::RelationshipKind::Chapter);
         m
     }
 }
 
-impl Store<Vec<Uuid>> for responses::MangaAggregate {
-    fn store(self, cache: &mut ApiCache) -> Vec<Uuid> {
+impl Store<Vec<VolumeId>> for responses::MangaAggregate {
+    fn store(mut self, cache: &mut ApiCache) -> Vec<VolumeId> {
+        let manga = self.manga_id.unwrap();
         let mut res = Vec::with_capacity(self.volumes.len());
 
-        for (k, e) in self.volumes {
-            let id = Uuid::new_v4();
-            let v = Volume { volume: k };
+        // the aggregate replaces the volumes of the manga and the chapters of each volume, or
+        // chapters that moved would stay in their old volume.
+        let old = cache
+            .get_linked::<VolumeId>(&manga, data::RelationshipKind::Volume)
+            .unwrap_or_default();
+        for vid in &old {
+            for cid in cache
+                .get_linked::<ChapterId>(vid, data::RelationshipKind::Chapter)
+                .unwrap_or_default()
+            {
+                cache.unlink(&cid, vid);
+            }
+            cache.unlink_kind(vid, &data::RelationshipKind::Chapter);
+        }
+        cache.unlink_kind(&manga, &data::RelationshipKind::Volume);
+
+        self.volumes
+            .sort_by(|a, b| aggregate_cmp(&a.volume, &b.volume));
+        for mut e in self.volumes {
+            let id = VolumeId::new(manga, &e.volume);
+            e.chapters
+                .sort_by(|a, b| aggregate_cmp(&a.chapter, &b.chapter));
+
+            let cids: Vec<ChapterId> = e
+                .chapters
+                .iter()
+                .flat_map(|c| std::iter::once(c.id).chain(c.others.iter().copied()))
+                .collect();
+            let v = Volume {
+                manga,
+                volume: e.volume,
+                chapters: e.chapters,
+            };
 
             res.push(id);
 
-            cache.insert(id, v, None);
+            cache.insert(id, v);
 
             // link volume to manga
-            cache.link(&self.manga_id.unwrap(), &id, data::RelationshipKind::Volume);
-            cache.link(&id, &self.manga_id.unwrap(), data::RelationshipKind::Manga);
-
-            for c in e.chapters.into_values() {
-                for cid in std::iter::once(c.id).chain(c.others.into_iter()) {
-                    // link chapters to volume
-                    cache.link(&cid, &id, data::RelationshipKind::Volume);
-                    cache.link(&id, &cid, data::RelationshipKind::Chapter);
-                }
+            cache.link(&manga, &id, data::RelationshipKind::Volume);
+            cache.link(&id, &manga, data::RelationshipKind::Manga);
+
+            for cid in &cids {
+                // link chapters to volume
+                cache.link(cid, &id, data::RelationshipKind::Volume);
+                cache.link(&id, cid, data::RelationshipKind::Chapter);
             }
         }
+        // volumes that aren't in the aggregate anymore
+        for vid in old.iter().filter(|v| !res.contains(v)) {
+            cache.remove::<Volume>(vid);
+        }
         res
     }
 }
 
-impl Store<Vec<Uuid>> for responses::MangaTag {
-    fn store(self, cache: &mut ApiCache) -> Vec<Uuid> {
+impl Store<Vec<TagId>> for responses::MangaTag {
+    fn store(self, cache: &mut ApiCache) -> Vec<TagId> {
         let mut res = Vec::with_capacity(self.data.len());
 
         for e in self.data {
-            cache.insert(e.id, e.attributes, None);
-            cache.link(&API_UUID, &e.id, data::RelationshipKind::Tag);
+            cache.insert(e.id, e.attributes);
+            cache.link(&API_ID, &e.id, data::RelationshipKind::Tag);
 
             res.push(e.id);
         }
@@ -379,8 +330,8 @@ impl Store<Vec<Uuid>> for responses::MangaTag {
     }
 }
 
-impl Store<Vec<Uuid>> for responses::CoverArtList {
-    fn store(self, cache: &mut ApiCache) -> Vec<Uuid> {
+impl Store<Vec<CoverId>> for responses::CoverArtList {
+    fn store(self, cache: &mut ApiCache) -> Vec<CoverId> {
         let mut res = Vec::with_capacity(self.data.len());
         for c in self.data {
             res.push(c.id);
@@ -390,9 +341,20 @@ impl Store<Vec<Uuid>> for responses::CoverArtList {
     }
 }
 
+impl Store<Vec<AuthorId>> for responses::AuthorList {
+    fn store(self, cache: &mut ApiCache) -> Vec<AuthorId> {
+        let mut res = Vec::with_capacity(self.data.len());
+        for a in self.data {
+            res.push(a.id);
+            cache.insert(a.id, a.attributes);
+        }
+        res
+    }
+}
+
 impl Store<CoverArt> for responses::CoverArt {
     fn store(self, cache: &mut ApiCache) -> CoverArt {
-        cache.insert(self.data.id, self.data.attributes.clone(), None);
+        cache.insert(self.data.id, self.data.attributes.clone());
         store_relationships(
             cache,
             self.data.relationships,
@@ -406,9 +368,9 @@ impl Store<CoverArt> for responses::CoverArt {
 impl Store<CoverArt> for responses::MangaCoverArt {
     fn store(self, cache: &mut ApiCache) -> CoverArt {
         // set main cover art relationship where necessary
-        for c in &self.data.relationships {
-            if c.kind == data::RelationshipKind::Manga {
-                cache.link(&c.id, &self.data.id, data::RelationshipKind::MainCoverArt);
+        for r in &self.data.relationships {
+            if let data::Relationship::Manga { id, .. } = r {
+                cache.link(id, &self.data.id, data::RelationshipKind::MainCoverArt);
                 break;
             }
         }
@@ -420,11 +382,234 @@ impl Store<CoverArt> for responses::MangaCoverArt {
 fn store_relationships(
     cache: &mut ApiCache,
     relationships: Vec<data::Relationship>,
-    uuid: Uuid,
+    id: impl Id,
     kind: data::RelationshipKind,
 ) {
+    fn link_both(
+        cache: &mut ApiCache,
+        a: &impl Id,
+        b: &impl Id,
+        kind: data::RelationshipKind,
+        back: data::RelationshipKind,
+    ) {
+        cache.link(a, b, kind);
+        cache.link(b, a, back);
+    }
+
     for r in relationships {
-        cache.link(&uuid, &r.id, r.kind);
-        cache.link(&r.id, &uuid, kind.clone());
+        let forward = r.kind();
+        let back = kind.clone();
+        match r {
+            data::Relationship::Manga { id: b, .. } => link_both(cache, &id, &b, forward, back),
+            data::Relationship::Chapter(b) => link_both(cache, &id, &b, forward, back),
+            data::Relationship::CoverArt(b) => link_both(cache, &id, &b, forward, back),
+            data::Relationship::Author(b) | data::Relationship::Artist(b) => {
+                link_both(cache, &id, &b, forward, back)
+            }
+            data::Relationship::ScanlationGroup(b) => link_both(cache, &id, &b, forward, back),
+            data::Relationship::Tag(b) => link_both(cache, &id, &b, forward, back),
+            data::Relationship::User(b) => link_both(cache, &id, &b, forward, back),
+            data::Relationship::CustomList(b) => link_both(cache, &id, &b, forward, back),
+            data::Relationship::Unknown(k) => log::trace!("Skip {k:?} relationship of {id}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use data::RelationshipKind;
+
+    fn aggregate(
+        manga: MangaId,
+        volumes: &[(&str, &[(&str, ChapterId)])],
+    ) -> responses::MangaAggregate {
+        let volumes: serde_json::Map<_, _> = volumes
+            .iter()
+            .map(|(v, chapters)| {
+                let chapters: serde_json::Map<_, _> = chapters
+                    .iter()
+                    .map(|(n, id)| {
+                        (
+                            n.to_string(),
+                            json!({ "chapter": n, "id": id, "others": [] }),
+                        )
+                    })
+                    .collect();
+                (v.to_string(), json!({ "volume": v, "chapters": chapters }))
+            })
+            .collect();
+        let mut res: responses::MangaAggregate =
+            serde_json::from_value(json!({ "volumes": volumes })).unwrap();
+        res.manga_id = Some(manga);
+        res
+    }
+
+    fn sorted<T: Ord>(mut v: Vec<T>) -> Vec<T> {
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn aggregate_replaces_volumes() {
+        let mut cache = ApiCache::new();
+        let manga: MangaId = Uuid::new_v4().into();
+        let [c1, c2, c3]: [ChapterId; 3] = [(); 3].map(|_| Uuid::new_v4().into());
+        let [v1, v2, v3] = ["1", "2", "3"].map(|v| VolumeId::new(manga, v));
+
+        aggregate(
+            manga,
+            &[("1", &[("1", c1), ("2", c2)]), ("2", &[("3", c3)])],
+        )
+        .store(&mut cache);
+        assert_eq!(
+            cache.get_linked::<ChapterId>(&v2, RelationshipKind::Chapter),
+            Some(vec![c3])
+        );
+
+        // chapter 2 and 3 move to volume 3, volume 2 is gone
+        let ids = aggregate(
+            manga,
+            &[("1", &[("1", c1)]), ("3", &[("2", c2), ("3", c3)])],
+        )
+        .store(&mut cache);
+        assert_eq!(ids, [v1, v3]);
+        assert_eq!(
+            sorted(
+                cache
+                    .get_linked::<VolumeId>(&manga, RelationshipKind::Volume)
+                    .unwrap()
+            ),
+            sorted(vec![v1, v3])
+        );
+        assert!(!cache.contains::<Volume>(&v2));
+        assert_eq!(
+            cache.get_linked::<ChapterId>(&v1, RelationshipKind::Chapter),
+            Some(vec![c1])
+        );
+        assert_eq!(
+            sorted(
+                cache
+                    .get_linked::<ChapterId>(&v3, RelationshipKind::Chapter)
+                    .unwrap()
+            ),
+            sorted(vec![c2, c3])
+        );
+        for c in [c2, c3] {
+            assert_eq!(
+                cache.get_linked::<VolumeId>(&c, RelationshipKind::Volume),
+                Some(vec![v3])
+            );
+        }
+    }
+
+    fn manga(title: serde_json::Value, alt_titles: serde_json::Value) -> Manga {
+        let manga: data::Manga = serde_json::from_value(data::test_manga_attributes(
+            json!({ "title": title, "altTitles": alt_titles }),
+        ))
+        .unwrap();
+        manga.into()
+    }
+
+    fn manga_view(id: MangaId, version: i32, authors: &[AuthorId]) -> responses::MangaView {
+        let relationships: Vec<_> = authors
+            .iter()
+            .map(|a| json!({ "id": a, "type": "author" }))
+            .collect();
+        serde_json::from_value(json!({
+            "data": {
+                "id": id,
+                "type": "manga",
+                "attributes": data::test_manga_attributes(json!({
+                    "title": { "en": format!("Version {version}") },
+                    "version": version,
+                })),
+                "relationships": relationships,
+            }
+        }))
+        .unwrap()
+    }
+
+    fn authors(cache: &ApiCache, manga: &MangaId) -> Vec<AuthorId> {
+        sorted(
+            cache
+                .get_linked::<AuthorId>(manga, RelationshipKind::Author)
+                .unwrap_or_default(),
+        )
+    }
+
+    #[test]
+    fn older_versions_are_skipped() {
+        let mut cache = ApiCache::new();
+        let id: MangaId = Uuid::new_v4().into();
+        let [a1, a2]: [AuthorId; 2] = [(); 2].map(|_| Uuid::new_v4().into());
+
+        manga_view(id, 2, &[a1]).store(&mut cache);
+        // an older response (e.g. from a list fetched before) doesn't win
+        let m = manga_view(id, 1, &[a2]).store(&mut cache);
+        assert_eq!(m.version, 2);
+        assert_eq!(cache.get::<Manga>(&id).unwrap().title.any(), "Version 2");
+        assert_eq!(authors(&cache, &id), [a1]);
+        assert!(cache
+            .get_linked::<MangaId>(&a2, RelationshipKind::Manga)
+            .is_none());
+
+        // the same version is taken again
+        let m = manga_view(id, 2, &[a2]).store(&mut cache);
+        assert_eq!(m.version, 2);
+        assert_eq!(authors(&cache, &id), [a2]);
+    }
+
+    #[test]
+    fn reinsert_keeps_links() {
+        let mut cache = ApiCache::new();
+        let id: MangaId = Uuid::new_v4().into();
+        let chapter: ChapterId = Uuid::new_v4().into();
+        let [a1, a2]: [AuthorId; 2] = [(); 2].map(|_| Uuid::new_v4().into());
+
+        manga_view(id, 1, &[a1]).store(&mut cache);
+        // links that come from other requests
+        cache.link(&id, &chapter, RelationshipKind::Chapter);
+        cache.link(&chapter, &id, RelationshipKind::Manga);
+
+        manga_view(id, 2, &[a2]).store(&mut cache);
+        assert_eq!(cache.get::<Manga>(&id).unwrap().version, 2);
+        assert_eq!(
+            cache.get_linked::<ChapterId>(&id, RelationshipKind::Chapter),
+            Some(vec![chapter])
+        );
+        assert_eq!(
+            cache.get_linked::<MangaId>(&chapter, RelationshipKind::Manga),
+            Some(vec![id])
+        );
+        // the ones that come with the manga are replaced
+        assert_eq!(authors(&cache, &id), [a2]);
+    }
+
+    #[test]
+    fn titles_in_preferred_languages() {
+        let manga = manga(
+            json!({ "ja-ro": "Shingeki no Kyojin" }),
+            json!([{ "en": "Attack on Titan" }, { "fr": "L'Attaque des Titans" }]),
+        );
+        let title = |langs: &[LanguageCode]| manga.title_in(langs);
+        assert_eq!(title(&[LanguageCode::English]), "Attack on Titan");
+        assert_eq!(
+            title(&[
+                LanguageCode::German,
+                LanguageCode::French,
+                LanguageCode::English
+            ]),
+            "L'Attaque des Titans"
+        );
+        assert_eq!(
+            title(&[LanguageCode::RomanizedJapanese, LanguageCode::English]),
+            "Shingeki no Kyojin"
+        );
+        // the main title when nothing matches
+        assert_eq!(title(&[LanguageCode::German]), "Shingeki no Kyojin");
+        assert_eq!(title(&[]), "Shingeki no Kyojin");
     }
 }
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ids::TagId, json::data::Tag, lang_codes::LanguageCode, MangaListFilter};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Hash, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
+pub enum TagGroup {
+    Genre,
+    Theme,
+    Format,
+    Content,
+
+    /// Values added to the api after this was written.
+    Unknown(String),
+}
+
+impl From<String> for TagGroup {
+    fn from(v: String) -> Self {
+        match v.as_str() {
+            "genre" => Self::Genre,
+            "theme" => Self::Theme,
+            "format" => Self::Format,
+            "content" => Self::Content,
+            _ => {
+                log::debug!("Unknown tag group ({v})");
+                Self::Unknown(v)
+            }
+        }
+    }
+}
+impl From<TagGroup> for String {
+    fn from(v: TagGroup) -> Self {
+        match v {
+            TagGroup::Genre => "genre".to_owned(),
+            TagGroup::Theme => "theme".to_owned(),
+            TagGroup::Format => "format".to_owned(),
+            TagGroup::Content => "content".to_owned(),
+            TagGroup::Unknown(s) => s,
+        }
+    }
+}
+
+/// All the tags from /manga/tag, with lookups by name and group.
+#[derive(Clone)]
+pub struct TagCatalog {
+    tags: HashMap<TagId, Tag>,
+    // lowercased name (in any language) -> ids, the tags for which it is the preferred name first
+    names: HashMap<String, Vec<TagId>>,
+    // ids of each group, sorted by (preferred) name
+    groups: HashMap<TagGroup, Vec<TagId>>,
+    languages: Vec<LanguageCode>,
+}
+
+/// The name of a tag in the first of the languages it has, lowercased.
+fn sort_name(tag: &Tag, languages: &[LanguageCode]) -> String {
+    tag.name.preferred_or_any(languages).to_lowercase()
+}
+
+impl TagCatalog {
+    /// A catalog of tags, named in the first of the languages they are available in.
+    pub fn new(tags: impl IntoIterator<Item = (TagId, Tag)>, languages: &[LanguageCode]) -> Self {
+        let tags: HashMap<TagId, Tag> = tags.into_iter().collect();
+        let mut names: HashMap<String, Vec<TagId>> = HashMap::new();
+        let mut groups: HashMap<TagGroup, Vec<TagId>> = HashMap::new();
+
+        for (id, tag) in &tags {
+            for (_, name) in tag.name.iter() {
+                let ids = names.entry(name.to_lowercase()).or_default();
+                if !ids.contains(id) {
+                    ids.push(*id);
+                }
+            }
+            groups.entry(tag.group.clone()).or_default().push(*id);
+        }
+
+        // the map isn't ordered, ids break the ties so that it's the same at each run
+        for (name, ids) in names.iter_mut() {
+            ids.sort_by_cached_key(|id| (sort_name(&tags[id], languages) != *name, *id));
+        }
+        for ids in groups.values_mut() {
+            ids.sort_by_cached_key(|id| (sort_name(&tags[id], languages), *id));
+        }
+
+        Self {
+            tags,
+            names,
+            groups,
+            languages: languages.to_vec(),
+        }
+    }
+
+    pub fn get(&self, id: &TagId) -> Option<&Tag> {
+        self.tags.get(id)
+    }
+
+    /// Find a tag by name, ignoring case, in any of the languages the name is available in. The
+    /// tag with that preferred name wins, a name shared by several tags otherwise finds none.
+    pub fn find(&self, name: &str) -> Option<(TagId, &Tag)> {
+        let name = name.trim().to_lowercase();
+        let id = match self.names.get(&name)?.as_slice() {
+            [id] => id,
+            [id, ..] if sort_name(&self.tags[id], &self.languages) == name => id,
+            _ => return None,
+        };
+        self.tags.get(id).map(|t| (*id, t))
+    }
+
+    /// Tags of a group, sorted by name.
+    pub fn group(&self, group: &TagGroup) -> impl Iterator<Item = (TagId, &Tag)> {
+        self.groups
+            .get(group)
+            .into_iter()
+            .flatten()
+            .map(|id| (*id, &self.tags[id]))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TagId, &Tag)> {
+        self.tags.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagState {
+    Include,
+    Exclude,
+}
+
+/// Tags picked to be included or excluded from a search (i.e. in a tag picker).
+#[derive(Debug, Clone, Default)]
+pub struct TagSelection {
+    states: HashMap<TagId, TagState>,
+}
+
+impl TagSelection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &TagId) -> Option<TagState> {
+        self.states.get(id).copied()
+    }
+
+    /// Set the state of a tag, None to unselect it.
+    pub fn set(&mut self, id: TagId, state: Option<TagState>) {
+        match state {
+            Some(s) => self.states.insert(id, s),
+            None => self.states.remove(&id),
+        };
+    }
+
+    /// Go from unselected, to included, to excluded and back to unselected.
+    pub fn cycle(&mut self, id: TagId) {
+        let next = match self.get(&id) {
+            None => Some(TagState::Include),
+            Some(TagState::Include) => Some(TagState::Exclude),
+            Some(TagState::Exclude) => None,
+        };
+        self.set(id, next);
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+
+    /// Add the selected tags to a filter, sorted so that the same selection always makes the same
+    /// query (responses are revalidated by url).
+    pub fn apply(&self, mut filter: MangaListFilter) -> MangaListFilter {
+        let mut states: Vec<_> = self.states.iter().collect();
+        states.sort_unstable_by_key(|(id, _)| **id);
+        for (id, state) in states {
+            filter = match state {
+                TagState::Include => filter.include_tag(*id),
+                TagState::Exclude => filter.exclude_tag(*id),
+            };
+        }
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::api::query;
+
+    fn tag(names: serde_json::Value, group: &str) -> (TagId, Tag) {
+        let tag = serde_json::from_value(serde_json::json!({
+            "name": names,
+            "group": group,
+            "version": 1,
+        }))
+        .unwrap();
+        (Uuid::new_v4().into(), tag)
+    }
+
+    #[test]
+    fn find_in_any_language() {
+        let action = tag(
+            serde_json::json!({"en": "Action", "ja": "アクション"}),
+            "genre",
+        );
+        let comedy = tag(
+            serde_json::json!({"en": "Comedy", "fr": "Comédie"}),
+            "genre",
+        );
+        let catalog = TagCatalog::new([action.clone(), comedy.clone()], &[LanguageCode::English]);
+
+        assert_eq!(catalog.find("action").map(|(id, _)| id), Some(action.0));
+        assert_eq!(catalog.find(" ACTION ").map(|(id, _)| id), Some(action.0));
+        assert_eq!(catalog.find("アクション").map(|(id, _)| id), Some(action.0));
+        assert_eq!(catalog.find("COMÉDIE").map(|(id, _)| id), Some(comedy.0));
+        assert!(catalog.find("drama").is_none());
+    }
+
+    #[test]
+    fn english_name_wins() {
+        // the translation of one is the name of the other
+        let gore = tag(
+            serde_json::json!({"en": "Gore", "es": "Violence"}),
+            "content",
+        );
+        let violence = tag(serde_json::json!({"en": "Violence"}), "content");
+        let a = tag(serde_json::json!({"en": "A", "fr": "Same"}), "theme");
+        let b = tag(serde_json::json!({"en": "B", "de": "Same"}), "theme");
+        for _ in 0..10 {
+            let catalog = TagCatalog::new(
+                [gore.clone(), violence.clone(), a.clone(), b.clone()],
+                &[LanguageCode::English],
+            );
+            assert_eq!(catalog.find("violence").map(|(id, _)| id), Some(violence.0));
+            // ambiguous
+            assert!(catalog.find("same").is_none());
+        }
+    }
+
+    #[test]
+    fn preferred_name_wins() {
+        let gore = tag(
+            serde_json::json!({"en": "Gore", "es": "Violencia"}),
+            "content",
+        );
+        let violence = tag(
+            serde_json::json!({"en": "Violence", "es": "Gore"}),
+            "content",
+        );
+        let tags = [gore.clone(), violence.clone()];
+        let english = TagCatalog::new(tags.clone(), &[LanguageCode::English]);
+        assert_eq!(english.find("gore").map(|(id, _)| id), Some(gore.0));
+
+        let spanish = TagCatalog::new(
+            tags,
+            &[LanguageCode::LatinAmericanSpanish, LanguageCode::Spanish],
+        );
+        assert_eq!(spanish.find("gore").map(|(id, _)| id), Some(violence.0));
+        assert_eq!(spanish.find("violence").map(|(id, _)| id), Some(violence.0));
+        // sorted by their spanish names
+        let ids: Vec<_> = spanish
+            .group(&TagGroup::Content)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, [violence.0, gore.0]);
+    }
+
+    #[test]
+    fn groups_sorted_by_name() {
+        let tags = [
+            tag(serde_json::json!({"en": "Romance"}), "genre"),
+            tag(serde_json::json!({"en": "action"}), "genre"),
+            tag(serde_json::json!({"en": "Comedy"}), "genre"),
+            tag(serde_json::json!({"en": "Zombies"}), "theme"),
+            tag(serde_json::json!({"en": "Long Strip"}), "format"),
+            tag(serde_json::json!({"en": "New"}), "new group"),
+        ];
+        let catalog = TagCatalog::new(tags, &[LanguageCode::English]);
+        let names = |group| -> Vec<String> {
+            catalog
+                .group(&group)
+                .map(|(_, t)| t.name.get_or_any(LanguageCode::English))
+                .collect()
+        };
+
+        assert_eq!(names(TagGroup::Genre), ["action", "Comedy", "Romance"]);
+        assert_eq!(names(TagGroup::Theme), ["Zombies"]);
+        assert_eq!(names(TagGroup::Unknown("new group".to_owned())), ["New"]);
+        assert!(names(TagGroup::Content).is_empty());
+    }
+
+    #[test]
+    fn apply_is_sorted() {
+        let ids: Vec<TagId> = (0..20).map(|_| Uuid::new_v4().into()).collect();
+        let select = |ids: &mut dyn Iterator<Item = (usize, &TagId)>| {
+            let mut selection = TagSelection::new();
+            for (i, id) in ids {
+                selection.cycle(*id);
+                if i % 3 == 0 {
+                    selection.cycle(*id);
+                }
+            }
+            selection
+        };
+        let mut selection = select(&mut ids.iter().enumerate());
+        // back to unselected
+        selection.cycle(ids[1]);
+        selection.cycle(ids[1]);
+        let mut reversed = select(&mut ids.iter().enumerate().rev());
+        reversed.set(ids[1], None);
+
+        let pairs = query::to_pairs(&selection.apply(MangaListFilter::default())).unwrap();
+        let of = |key: &str| -> Vec<String> {
+            pairs
+                .iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+                .collect()
+        };
+        let mut included: Vec<String> = ids
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % 3 != 0 && *i != 1)
+            .map(|(_, id)| id.to_string())
+            .collect();
+        let mut excluded: Vec<String> = ids.iter().step_by(3).map(ToString::to_string).collect();
+        included.sort();
+        excluded.sort();
+        assert_eq!(of("includedTags[]"), included);
+        assert_eq!(of("excludedTags[]"), excluded);
+
+        // same selection, same query
+        let other = query::to_pairs(&reversed.apply(MangaListFilter::default())).unwrap();
+        assert_eq!(pairs, other);
+    }
+}
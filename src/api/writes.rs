@@ -0,0 +1,288 @@
+// Requests that change something on mangadex (i.e. read markers), kept in the data dir when they
+// can't be sent and sent again when back online.
+
+use std::{
+    fs,
+    future::Future,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use parking_lot::{const_mutex, Mutex};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::ApiError;
+use crate::paths;
+
+/// Held while the queue is read and written, so that writes queued at the same time (or while
+/// replaying) don't overwrite each other.
+static FILE: Mutex<()> = const_mutex(());
+
+/// A POST request that couldn't be sent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingWrite {
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    pub endpoint: String,
+    pub body: serde_json::Value,
+    pub queued_at: DateTime<Utc>,
+}
+
+impl PendingWrite {
+    pub fn new(endpoint: String, body: serde_json::Value) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            endpoint,
+            body,
+            queued_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PendingWrites {
+    writes: Vec<PendingWrite>,
+}
+
+pub(super) fn pending_path() -> PathBuf {
+    paths::data_dir().join("pending_writes.json")
+}
+
+impl PendingWrites {
+    /// Load the queue from disk, a missing file means an empty queue.
+    fn load_from(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    fn save_to(&self, path: &Path) -> Result<()> {
+        paths::write_atomic(path, &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Change the queue saved at path.
+    fn update<R>(path: &Path, f: impl FnOnce(&mut Self) -> R) -> Result<R> {
+        let _file = FILE.lock();
+        let mut queue = Self::load_from(path)?;
+        let res = f(&mut queue);
+        queue.save_to(path)?;
+        Ok(res)
+    }
+
+    pub fn load() -> Result<Self> {
+        let _file = FILE.lock();
+        Self::load_from(&pending_path())
+    }
+
+    /// Add a write at the end of the queue and save it.
+    pub fn push(write: PendingWrite) -> Result<()> {
+        Self::push_at(&pending_path(), write)
+    }
+    fn push_at(path: &Path, write: PendingWrite) -> Result<()> {
+        Self::update(path, |q| q.writes.push(write))
+    }
+
+    fn first_at(path: &Path) -> Result<Option<PendingWrite>> {
+        let _file = FILE.lock();
+        Ok(Self::load_from(path)?.writes.into_iter().next())
+    }
+    /// Remove a write from the queue, once it was sent.
+    fn remove_at(path: &Path, id: &Uuid) -> Result<()> {
+        Self::update(path, |q| q.writes.retain(|w| w.id != *id))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+pub(super) type Sending<'a> = Pin<Box<dyn Future<Output = Result<(), ApiError>> + Send + 'a>>;
+
+/// Send the writes queued at path in order with send. Each write is only removed from the queue
+/// once it was sent, so that nothing is lost if we are stopped in the middle. This stops at the
+/// first write that can't be sent yet (see ApiError::is_transient), the writes that can never be
+/// sent are dropped. Returns how many were sent.
+pub(super) async fn replay<C>(
+    path: &Path,
+    ctx: &mut C,
+    send: for<'a> fn(&'a mut C, &'a PendingWrite) -> Sending<'a>,
+) -> usize {
+    let mut sent = 0;
+    loop {
+        let write = match PendingWrites::first_at(path) {
+            Ok(Some(write)) => write,
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("Couldn't load the pending writes ({e})");
+                break;
+            }
+        };
+        match send(ctx, &write).await {
+            Ok(()) => sent += 1,
+            Err(e) if e.is_transient() => {
+                log::info!("Keeping the write to {} for later ({e})", write.endpoint);
+                break;
+            }
+            Err(e) => log::error!("Dropping the write to {} ({e})", write.endpoint),
+        }
+        if let Err(e) = PendingWrites::remove_at(path, &write.id) {
+            // better stop than send it again and again
+            log::error!("Couldn't remove the write from the pending writes ({e})");
+            break;
+        }
+    }
+    if sent > 0 {
+        log::info!("Sent {sent} pending writes");
+    }
+    sent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_queue() -> PathBuf {
+        std::env::temp_dir().join(format!("mngdx-test-writes-{}.json", Uuid::new_v4()))
+    }
+
+    fn write(n: u32) -> PendingWrite {
+        PendingWrite::new(format!("/manga/{n}/read"), serde_json::json!({ "n": n }))
+    }
+
+    fn endpoints(path: &Path) -> Vec<String> {
+        PendingWrites::load_from(path)
+            .unwrap()
+            .writes
+            .into_iter()
+            .map(|w| w.endpoint)
+            .collect()
+    }
+
+    /// Sends the writes with the results of a script, and records the ones it was asked to send.
+    struct Script {
+        results: Vec<Result<(), ApiError>>,
+        asked: Vec<String>,
+    }
+
+    fn script_send<'a>(s: &'a mut Script, w: &'a PendingWrite) -> Sending<'a> {
+        Box::pin(async move {
+            s.asked.push(w.endpoint.clone());
+            s.results.remove(0)
+        })
+    }
+
+    fn replay_with(path: &Path, results: Vec<Result<(), ApiError>>) -> (usize, Vec<String>) {
+        let mut script = Script {
+            results,
+            asked: Vec::new(),
+        };
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let sent = rt.block_on(replay(path, &mut script, script_send));
+        (sent, script.asked)
+    }
+
+    #[test]
+    fn push_and_remove() {
+        let path = temp_queue();
+        assert!(PendingWrites::load_from(&path).unwrap().is_empty());
+
+        for n in 1..=3 {
+            PendingWrites::push_at(&path, write(n)).unwrap();
+        }
+        let first = PendingWrites::first_at(&path).unwrap().unwrap();
+        assert_eq!(first.endpoint, "/manga/1/read");
+
+        let second = PendingWrites::load_from(&path).unwrap().writes[1].id;
+        PendingWrites::remove_at(&path, &second).unwrap();
+        assert_eq!(endpoints(&path), ["/manga/1/read", "/manga/3/read"]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn concurrent_pushes_are_kept() {
+        let path = temp_queue();
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let path = &path;
+                s.spawn(move || {
+                    for n in 0..10 {
+                        PendingWrites::push_at(path, write(t * 10 + n)).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(endpoints(&path).len(), 40);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_stops_on_transient_errors() {
+        let path = temp_queue();
+        for n in 1..=4 {
+            PendingWrites::push_at(&path, write(n)).unwrap();
+        }
+
+        let (sent, asked) = replay_with(
+            &path,
+            vec![
+                Ok(()),
+                Err(ApiError::BadRequest),
+                Err(ApiError::RateLimit(Default::default())),
+            ],
+        );
+        assert_eq!(sent, 1);
+        assert_eq!(asked.len(), 3);
+        // the sent write and the bad one are gone, the rate limited one is kept in its place
+        assert_eq!(endpoints(&path), ["/manga/3/read", "/manga/4/read"]);
+
+        let (sent, asked) = replay_with(&path, vec![Err(ApiError::Offline)]);
+        assert_eq!((sent, asked.len()), (0, 1));
+        let (sent, _) = replay_with(&path, vec![Err(ApiError::Them)]);
+        assert_eq!(sent, 0);
+        assert_eq!(endpoints(&path).len(), 2);
+
+        let (sent, asked) = replay_with(&path, vec![Ok(()), Ok(())]);
+        assert_eq!(sent, 2);
+        assert_eq!(asked, ["/manga/3/read", "/manga/4/read"]);
+        assert!(endpoints(&path).is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_keeps_writes_queued_meanwhile() {
+        let path = temp_queue();
+        PendingWrites::push_at(&path, write(1)).unwrap();
+
+        struct Pusher<'p>(&'p Path);
+        fn push_while_sending<'a>(p: &'a mut Pusher, w: &'a PendingWrite) -> Sending<'a> {
+            let path = p.0;
+            let queued = w.endpoint == "/manga/1/read";
+            Box::pin(async move {
+                if queued {
+                    PendingWrites::push_at(path, write(2)).unwrap();
+                }
+                Ok(())
+            })
+        }
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let sent = rt.block_on(replay(&path, &mut Pusher(&path), push_while_sending));
+        assert_eq!(sent, 2);
+        assert!(endpoints(&path).is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
+}
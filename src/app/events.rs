@@ -1,6 +1,25 @@
-use crate::api::Api;
+use crate::{
+    api::{
+        cache::CacheChange,
+        structs::{
+            chapters::ChapterList,
+            ids::{ChapterId, MangaId},
+            Chapter, Manga,
+        },
+        Api, ApiError,
+    },
+    consts::SEARCH_RESULTS,
+    library::{queue, Download, LibraryChapter, LibraryManga},
+};
 
-use super::{AppComponents, render::FRAME};
+use super::{
+    preferences::Preferences,
+    reader::Page,
+    render::{self, FRAME},
+    searches::{SearchPicker, Searches},
+    state::Reading,
+    AppComponents,
+};
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 use rand::prelude::SliceRandom;
 use std::{
@@ -8,15 +27,116 @@ use std::{
     sync::{atomic::AtomicBool, Arc},
 };
 use tui::backend::Backend;
-use uuid::Uuid;
 
 pub enum AppEvent {
     Dummy(String),
+    /// An object was changed in an api cache (by any task).
+    CacheChanged(CacheChange),
     Start,
     Next,
     Previous,
     Quit,
     Resize,
+    ToggleSearches,
+    Select,
+    NextChapter,
+    PreviousChapter,
+    ToggleDownloads,
+    /// Queue the download of the chapter being read.
+    DownloadChapter,
+}
+
+/// Pages of a chapter, from the library if it was downloaded.
+async fn chapter_pages(api: &mut Api, chapter: ChapterId) -> Result<Vec<Page>, ApiError> {
+    match LibraryChapter::find(&chapter) {
+        Ok(Some(c)) => return Ok(c.page_paths().into_iter().map(Page::File).collect()),
+        Ok(None) => {}
+        Err(e) => log::warn!("Couldn't look for {chapter} in the library ({e})"),
+    }
+    let pages = api.chapter_pages(chapter).await?;
+    Ok(pages.into_iter().map(Page::Url).collect())
+}
+
+/// Chapters of a manga, the downloaded ones if the api can't give them.
+async fn chapter_list(api: &mut Api, manga: MangaId) -> Option<ChapterList> {
+    let e = match api.manga_chapter_list(manga).await {
+        Ok(chapters) => return Some(chapters),
+        Err(e) => e,
+    };
+    match LibraryManga::load(&manga).and_then(|m| m.map(|m| m.chapter_list()).transpose()) {
+        Ok(Some(chapters)) => Some(chapters),
+        Ok(None) => {
+            log::error!("Couldn't get the chapters of {manga} ({e})");
+            None
+        }
+        Err(library) => {
+            log::error!("Couldn't get the chapters of {manga} ({e}, library: {library})");
+            None
+        }
+    }
+}
+
+/// Fetch the pages of a chapter and give them to the reader.
+fn read_chapter<B: Backend + Write + Send + 'static>(
+    chapter: ChapterId,
+    mut comps: AppComponents<B>,
+) {
+    let components = comps.clone();
+    comps
+        .task_producer
+        .schedule(async move {
+            let mut api = Api::new();
+            match chapter_pages(&mut api, chapter).await {
+                Ok(pages) => {
+                    components.reader.lock().read(pages, components.clone());
+                    render::request_redraw();
+                }
+                Err(e) => log::error!("Couldn't get the pages of {chapter} ({e})"),
+            }
+        })
+        .ok();
+}
+
+/// Update the views showing a manga, returns whether one did.
+fn manga_changed<B: Backend + Write + Send + 'static>(
+    comps: &AppComponents<B>,
+    id: MangaId,
+    manga: &Manga,
+) -> bool {
+    let mut state = comps.state.lock();
+    let title = manga.title_in(&state.languages);
+    let mut shown = false;
+    if let Some(reading) = state.reading.as_mut().filter(|r| r.manga == id) {
+        reading.title = Some(title.clone());
+        shown = true;
+    }
+    if let Some(picker) = state.search_picker.as_mut() {
+        for (_, t) in picker.results.iter_mut().filter(|(m, _)| *m == id) {
+            *t = title.clone();
+            shown = true;
+        }
+    }
+    shown
+}
+
+/// Update the chapters of the manga being read, returns whether they were.
+fn chapter_changed<B: Backend + Write + Send + 'static>(
+    comps: &AppComponents<B>,
+    id: ChapterId,
+    chapter: &Chapter,
+) -> bool {
+    let mut state = comps.state.lock();
+    match state
+        .reading
+        .as_mut()
+        .filter(|r| r.chapter == id || r.chapters.get(&id).is_some())
+    {
+        Some(reading) => {
+            reading.chapters.set_chapter(id, chapter.clone());
+            true
+        }
+        None => false,
+    }
 }
 
 pub fn process_event<B: Backend + Write + Send + 'static>(
@@ -31,25 +151,220 @@ pub fn process_event<B: Backend + Write + Send + 'static>(
                 .task_producer
                 .schedule(async move {
                     let mut api = Api::new();
-                    let chapters = api
-                        .manga_chapters(
-                            Uuid::parse_str("e78a489b-6632-4d61-b00b-5206f5b8b22b").unwrap(),
-                        )
-                        .await
-                        .unwrap();
-                    let chapter = chapters.choose(&mut rand::thread_rng()).unwrap();
-                    let pages = api.chapter_pages(*chapter).await.unwrap();
+                    let manga: MangaId = "e78a489b-6632-4d61-b00b-5206f5b8b22b".parse().unwrap();
+                    let preferences = Preferences::load_or_default();
+                    let translations = preferences.translations_for(&manga);
+                    let chapters = match chapter_list(&mut api, manga).await {
+                        Some(chapters) => chapters,
+                        None => return,
+                    };
+                    let chapter = match chapters
+                        .deduplicated(&translations)
+                        .entries()
+                        .choose(&mut rand::thread_rng())
+                    {
+                        Some(e) => e.id,
+                        None => {
+                            log::error!("{manga} has no chapters");
+                            return;
+                        }
+                    };
+                    let pages = match chapter_pages(&mut api, chapter).await {
+                        Ok(pages) => pages,
+                        Err(e) => {
+                            log::error!("Couldn't get the pages of {chapter} ({e})");
+                            return;
+                        }
+                    };
 
+                    // changes made before this are missed, so the title is taken from the cache
+                    let cached = api.cache().get::<Manga>(&manga);
+                    let title = cached
+                        .or_else(|| LibraryManga::load(&manga).ok().flatten().map(|m| m.manga))
+                        .map(|m| m.title_in(&preferences.languages));
+                    comps.state.lock().reading = Some(Reading {
+                        manga,
+                        title,
+                        chapter,
+                        chapters,
+                        translations,
+                    });
                     comps.reader.lock().read(pages, components);
+                    render::request_redraw();
                 })
                 .ok();
         }
+        AppEvent::NextChapter | AppEvent::PreviousChapter => {
+            let chapter = comps.state.lock().reading.as_mut().and_then(|r| {
+                let e = match event {
+                    AppEvent::NextChapter => r.chapters.next_preferred(&r.chapter, &r.translations),
+                    _ => r.chapters.previous_preferred(&r.chapter, &r.translations),
+                }?;
+                r.chapter = e.id;
+                Some(e.id)
+            });
+            if let Some(chapter) = chapter {
+                read_chapter(chapter, comps);
+            }
+        }
         AppEvent::Next => {
+            if let Some(picker) = comps.state.lock().search_picker.as_mut() {
+                picker.next();
+                return;
+            }
             comps.reader.lock().next();
         }
         AppEvent::Previous => {
+            if let Some(picker) = comps.state.lock().search_picker.as_mut() {
+                picker.previous();
+                return;
+            }
             comps.reader.lock().previous();
         }
+        AppEvent::ToggleDownloads => {
+            let mut state = comps.state.lock();
+            state.show_downloads = !state.show_downloads;
+        }
+        AppEvent::DownloadChapter => {
+            let chapter = match comps.state.lock().reading.as_ref() {
+                Some(r) => r.chapter,
+                None => return,
+            };
+            let mut producer = comps.task_producer.clone();
+            comps
+                .task_producer
+                .schedule(async move {
+                    let mut api = Api::new();
+                    match queue::enqueue(&mut api, &Download::Chapter { id: chapter }, false).await
+                    {
+                        Ok(_) => queue::schedule(&mut producer),
+                        Err(e) => log::error!("Couldn't queue the download of {chapter} ({e})"),
+                    }
+                })
+                .ok();
+        }
+        AppEvent::ToggleSearches => {
+            if comps.state.lock().search_picker.take().is_some() {
+                return;
+            }
+
+            let searches = match Searches::load() {
+                Ok(s) => s,
+                Err(e) => {
+                    log::error!("Couldn't load searches ({e})");
+                    return;
+                }
+            };
+            let picker = SearchPicker::new(&searches);
+            // entries that have already been run, to know how many mangas are new since then
+            let filters: Vec<_> = picker
+                .entries
+                .iter()
+                .enumerate()
+                .filter_map(|(i, e)| e.new_since_filter().map(|f| (i, f)))
+                .collect();
+            comps.state.lock().search_picker = Some(picker);
+
+            let components = comps.clone();
+            comps
+                .task_producer
+                .schedule(async move {
+                    let mut api = Api::new();
+                    for (i, filter) in filters {
+                        let count = match api.manga_count(&filter).await {
+                            Ok(c) => c,
+                            Err(e) => {
+                                log::warn!("Couldn't count new mangas ({e})");
+                                continue;
+                            }
+                        };
+                        // the picker might have been closed in the meantime
+                        if let Some(picker) = components.state.lock().search_picker.as_mut() {
+                            if let Some(entry) = picker.entries.get_mut(i) {
+                                entry.new = Some(count);
+                                render::request_redraw();
+                            }
+                        }
+                    }
+                })
+                .ok();
+        }
+        AppEvent::Select => {
+            let entry = comps.state.lock().search_picker.as_ref().and_then(|p| {
+                p.current().map(|e| {
+                    (
+                        p.selected,
+                        e.label.clone(),
+                        e.saved.clone(),
+                        e.filter.clone(),
+                    )
+                })
+            });
+            let (index, label, saved, filter) = match entry {
+                Some(e) => e,
+                None => return,
+            };
+
+            let components = comps.clone();
+            comps
+                .task_producer
+                .schedule(async move {
+                    let mut api = Api::new();
+                    let languages = components.state.lock().languages.clone();
+                    let ids = match api.manga_list(filter.clone(), 0, SEARCH_RESULTS).await {
+                        Ok(ids) => ids,
+                        Err(e) => {
+                            log::error!("Search failed ({e})");
+                            return;
+                        }
+                    };
+                    let results = ids
+                        .iter()
+                        .filter_map(|id| {
+                            api.cache()
+                                .get::<Manga>(id)
+                                .map(|m| (*id, m.title_in(&languages)))
+                        })
+                        .collect();
+
+                    if let Some(picker) = components.state.lock().search_picker.as_mut() {
+                        picker.results = results;
+                        if let Some(entry) = picker.entries.get_mut(index) {
+                            entry.new = Some(0);
+                            entry.last_run = Some(chrono::Utc::now());
+                        }
+                        render::request_redraw();
+                    }
+
+                    let res = Searches::load().and_then(|mut searches| {
+                        match saved {
+                            Some(name) => searches.mark_run(&name),
+                            None => searches.push_history(label, filter),
+                        }
+                        searches.save()
+                    });
+                    if let Err(e) = res {
+                        log::error!("Couldn't save searches ({e})");
+                    }
+                })
+                .ok();
+        }
+        AppEvent::CacheChanged(change) => {
+            // update the views showing the object, and redraw only then
+            let shown =
+                if let (Some(id), Some(manga)) = (change.id::<Manga>(), change.value::<Manga>()) {
+                    manga_changed(&comps, id, manga)
+                } else if let (Some(id), Some(chapter)) =
+                    (change.id::<Chapter>(), change.value::<Chapter>())
+                {
+                    chapter_changed(&comps, id, chapter)
+                } else {
+                    false
+                };
+            if shown {
+                render::request_redraw();
+            }
+        }
         AppEvent::Dummy(s) => {
             comps.state.lock().block_name = s;
         }
@@ -59,7 +374,7 @@ pub fn process_event<B: Backend + Write + Send + 'static>(
         AppEvent::Resize => {
             // necessary because IDK
             std::thread::sleep(*FRAME);
-            let _ = comps.image_manager.lock().set_diry();
+            comps.image_manager.lock().set_diry();
             std::thread::sleep(*FRAME);
             comps.terminal.lock().autoresize().ok();
         }
@@ -82,6 +397,36 @@ impl TryFrom<Event> for AppEvent {
                 modifiers: KeyModifiers::NONE,
             }) => Ok(AppEvent::Resize),
 
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('s'),
+                modifiers: KeyModifiers::NONE,
+            }) => Ok(AppEvent::ToggleSearches),
+
+            Event::Key(KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+            }) => Ok(AppEvent::Select),
+
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('n'),
+                modifiers: KeyModifiers::NONE,
+            }) => Ok(AppEvent::NextChapter),
+
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('p'),
+                modifiers: KeyModifiers::NONE,
+            }) => Ok(AppEvent::PreviousChapter),
+
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::NONE,
+            }) => Ok(AppEvent::ToggleDownloads),
+
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('D'),
+                modifiers: KeyModifiers::SHIFT,
+            }) => Ok(AppEvent::DownloadChapter),
+
             Event::Key(KeyEvent {
                 code: KeyCode::Right,
                 modifiers: KeyModifiers::NONE,
@@ -1,6 +1,13 @@
+use crate::api::{
+    cache::{self, ApiCache, CacheGuard, Subscription},
+    connectivity,
+    structs::{Chapter, Manga},
+    Api,
+};
 use crate::app::render::FRAME;
-use crate::consts::EXECUTOR_THREAD_COUNT;
+use crate::consts::{EXECUTOR_THREAD_COUNT, IDLE_REDRAW_SECS};
 use crate::images::{self, ImageManager};
+use crate::library::queue;
 use anyhow::{Error, Result};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use parking_lot::lock_api::RawMutex;
@@ -26,8 +33,10 @@ use self::render::render;
 use self::state::AppState;
 
 mod events;
+pub mod preferences;
 pub mod reader;
 mod render;
+pub mod searches;
 mod state;
 pub mod time;
 
@@ -130,6 +139,7 @@ where
             spawn_named("Render", move || {
                 let mut time = Vec::with_capacity(6000);
                 let mut last_frame;
+                let mut last_draw = Instant::now();
                 log::info!("Render thread start");
                 loop {
                     if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
@@ -137,6 +147,14 @@ where
                     }
 
                     last_frame = Instant::now();
+                    // only draw when something changed, or once in a while for what changes by itself
+                    if !render::take_redraw()
+                        && last_draw.elapsed() < Duration::from_secs(IDLE_REDRAW_SECS)
+                    {
+                        thread::sleep(*FRAME);
+                        continue;
+                    }
+                    last_draw = last_frame;
                     let ws = images::get_terminal_winsize(fd).unwrap();
                     let _ = render(comps.clone(), &ws);
 
@@ -233,13 +251,106 @@ where
                     }
 
                     if let Ok(event) = event_receciver.recv() {
+                        // cache changes ask for a redraw themselves when they're shown
+                        let redraw = !matches!(event, AppEvent::CacheChanged(_));
                         events::process_event(event, comps.clone(), &should_stop);
+                        if redraw {
+                            render::request_redraw();
+                        }
                     }
                 }
                 log::info!("Event loop thread stop");
             });
         }
 
+        // Cache notifications thread, turns changes of the api caches into events
+        {
+            let event_producer = event_producer.clone();
+            spawn_named("Cache Notifications", move || {
+                let changes = cache::subscribe(vec![
+                    Subscription::of::<Manga>(),
+                    Subscription::of::<Chapter>(),
+                ]);
+                for change in changes {
+                    if event_producer.send(AppEvent::CacheChanged(change)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        // Connectivity thread, pings mangadex while offline and sends the writes that were queued
+        // once it can be reached again
+        {
+            let should_stop = stop.clone();
+            spawn_named("Connectivity", move || {
+                log::info!("Connectivity thread start");
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                let mut was_online = false;
+                let mut last_ping = Instant::now();
+                loop {
+                    if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let online = connectivity::is_online();
+                    if online && !was_online {
+                        rt.block_on(async { Api::new().replay_writes().await });
+                    } else if !online && last_ping.elapsed() > connectivity::RETRY_INTERVAL {
+                        rt.block_on(async { Api::new().ping().await });
+                        last_ping = Instant::now();
+                    }
+                    was_online = online;
+
+                    thread::sleep(Duration::from_secs(1));
+                }
+                log::info!("Connectivity thread stop");
+            });
+        }
+
+        // Caches thread, removes the expired data of the api cache even when nothing uses it, and
+        // saves the api cache and the image cache index once in a while
+        {
+            let should_stop = stop.clone();
+            spawn_named("Caches", move || {
+                log::info!("Caches thread start");
+                let cache = ApiCache::shared();
+                loop {
+                    if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
+                        break;
+                    }
+                    {
+                        let mut cache = CacheGuard::lock(&cache);
+                        cache.sweep_if_due();
+                        cache.save_if_due();
+                    }
+                    images::cache::save_if_due();
+                    thread::sleep(Duration::from_secs(1));
+                }
+                log::info!("Caches thread stop");
+            });
+        }
+
+        // Downloads thread, starts the queued downloads (on the executor) and the ones to retry
+        {
+            let should_stop = stop.clone();
+            let mut producer = self.components.task_producer.clone();
+            spawn_named("Downloads", move || {
+                log::info!("Downloads thread start");
+                loop {
+                    if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
+                        break;
+                    }
+                    queue::schedule(&mut producer);
+                    thread::sleep(Duration::from_secs(1));
+                }
+                log::info!("Downloads thread stop");
+            });
+        }
+
         // Input "thread", has to be on main because ¯\_(ツ)_/¯
         {
             log::info!("Input thread start");
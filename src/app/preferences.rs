@@ -0,0 +1,87 @@
+// User preferences, persisted in the config dir.
+
+use std::{collections::HashMap, fs, io::ErrorKind, path::PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::structs::{chapters::TranslationPreferences, ids::MangaId, lang_codes::LanguageCode},
+    paths,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Languages of titles, descriptions and tag names, in order of preference.
+    pub languages: Vec<LanguageCode>,
+    /// Which uploads to read when a chapter has several.
+    pub translations: TranslationPreferences,
+    /// Per manga overrides of `translations`.
+    pub manga_translations: HashMap<MangaId, TranslationPreferences>,
+    /// Memory budget of the api cache, in MiB.
+    pub cache_memory_mb: usize,
+    /// Size limit of the image cache (pages and covers), in MiB.
+    pub image_cache_mb: u64,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            languages: vec![LanguageCode::English],
+            translations: TranslationPreferences::default(),
+            manga_translations: HashMap::new(),
+            cache_memory_mb: 64,
+            image_cache_mb: 512,
+        }
+    }
+}
+
+fn preferences_path() -> PathBuf {
+    paths::config_dir().join("preferences.json")
+}
+
+impl Preferences {
+    /// Load the preferences from disk, a missing file means the defaults.
+    pub fn load() -> Result<Self> {
+        match fs::read(preferences_path()) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Load the preferences, falling back to the defaults if they can't be read.
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_else(|e| {
+            log::error!("Couldn't load preferences ({e})");
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        paths::write_atomic(&preferences_path(), &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Translation preferences for a manga, an empty list in the override means the global one
+    /// is used.
+    pub fn translations_for(&self, manga: &MangaId) -> TranslationPreferences {
+        let global = &self.translations;
+        match self.manga_translations.get(manga) {
+            Some(o) => TranslationPreferences {
+                languages: if o.languages.is_empty() {
+                    global.languages.clone()
+                } else {
+                    o.languages.clone()
+                },
+                groups: if o.groups.is_empty() {
+                    global.groups.clone()
+                } else {
+                    o.groups.clone()
+                },
+            },
+            None => global.clone(),
+        }
+    }
+}
@@ -1,13 +1,21 @@
 // Here a reader is a struct that is used to read a chapter (input and render)
 
-use std::io::Write;
+use std::{io::Write, path::PathBuf};
 
 use anyhow::Result;
 use tui::{backend::Backend, layout::Rect, Terminal};
 
 use crate::images::{ImageManager, TermWinSize};
 
-use super::AppComponents;
+use super::{render, AppComponents};
+
+/// Where to get a page from.
+#[derive(Clone)]
+pub enum Page {
+    Url(String),
+    /// A downloaded page (see library).
+    File(PathBuf),
+}
 
 pub trait Reader<B: Backend + Write + Send> {
     /// "Advence" the reading (i.e. next page)
@@ -22,8 +30,8 @@ pub trait Reader<B: Backend + Write + Send> {
         term: &mut Terminal<B>,
         image_manager: &mut ImageManager,
     ) -> Result<()>;
-    /// Start reading a chapter (vec of url to pages)
-    fn read(&mut self, chapter: Vec<String>, comps: AppComponents<B>);
+    /// Start reading a chapter (vec of pages)
+    fn read(&mut self, chapter: Vec<Page>, comps: AppComponents<B>);
 }
 
 /// A reader that separates the chapter into distinct pages
@@ -67,17 +75,26 @@ impl<B: Backend + Write + Send + 'static> Reader<B> for PageReader {
         Ok(())
     }
 
-    fn read(&mut self, chapter: Vec<String>, mut comps: AppComponents<B>) {
+    fn read(&mut self, chapter: Vec<Page>, mut comps: AppComponents<B>) {
         log::debug!("Reader read");
         self.pages = chapter.len();
         self.current = 0;
-        for (id, url) in chapter.into_iter().enumerate() {
+        for (id, page) in chapter.into_iter().enumerate() {
             let image_manager = comps.image_manager.clone();
             let _ = comps.task_producer.schedule(async move {
-                let img = ImageManager::image_from_url(url).await.unwrap();
-                image_manager.lock().add_image(id as u32 + 1, img);
+                match page {
+                    Page::Url(url) => match ImageManager::image_from_url(&url).await {
+                        Ok(img) => image_manager.lock().add_image(id as u32 + 1, img),
+                        Err(e) => log::error!("Couldn't get page {url} ({e})"),
+                    },
+                    Page::File(path) => {
+                        if let Err(e) = image_manager.lock().add_from_file(id as u32 + 1, &path) {
+                            log::error!("Couldn't read page {} ({e})", path.display());
+                        }
+                    }
+                }
+                render::request_redraw();
             });
         }
     }
 }
-
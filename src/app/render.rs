@@ -2,10 +2,18 @@ use std::{
     borrow::BorrowMut,
     io::Write,
     lazy::SyncLazy,
-    time::{Duration, SystemTime, UNIX_EPOCH}, sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::{consts::FRAME_RATE, images::TermWinSize};
+use crate::{
+    consts::FRAME_RATE,
+    images::TermWinSize,
+    library::queue::{self, ItemState},
+};
 
 use anyhow::Result;
 use parking_lot::Mutex;
@@ -13,13 +21,25 @@ use tui::{
     backend::Backend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style},
-    widgets::{Block, BorderType, Borders, Paragraph},
+    text::Spans,
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
-use super::{state::AppState, AppComponents, reader::Reader};
+use super::{searches::SearchPicker, state::AppState, time, AppComponents, reader::Reader};
 
 pub static FRAME: SyncLazy<Duration> = SyncLazy::new(|| Duration::from_secs(1) / FRAME_RATE);
+/// Whether something shown changed since the last frame.
+static REDRAW: AtomicBool = AtomicBool::new(true);
+
+/// Ask for the next frame to be drawn, frames are skipped until something changes.
+pub fn request_redraw() {
+    REDRAW.store(true, Ordering::Relaxed);
+}
+
+pub fn take_redraw() -> bool {
+    REDRAW.swap(false, Ordering::Relaxed)
+}
 
 pub fn render_widgets<B: Backend + Write + Send>(f: &mut Frame<B>, state: &AppState, reader: Arc<Mutex<dyn Reader<B>>>) -> Rect {
     let size = f.size();
@@ -31,7 +51,12 @@ pub fn render_widgets<B: Backend + Write + Send>(f: &mut Frame<B>, state: &AppSt
             Constraint::Min(10),
         ])
         .split(size);
-    let title = Paragraph::new("mngdx")
+    let title = if crate::api::connectivity::is_online() {
+        "mngdx"
+    } else {
+        "mngdx\n(offline)"
+    };
+    let title = Paragraph::new(title)
         .style(Style::default().fg(Color::LightCyan))
         .alignment(Alignment::Center)
         .block(
@@ -40,7 +65,16 @@ pub fn render_widgets<B: Backend + Write + Send>(f: &mut Frame<B>, state: &AppSt
                 .style(Style::default().fg(Color::White))
                 .border_type(BorderType::Plain),
         );
-    let t = Paragraph::new(format!("loading...\n{}", reader.lock().current()))
+    // placeholders until the manga and the chapter are known
+    let header = match state.reading.as_ref() {
+        Some(r) => match (&r.title, r.chapters.get(&r.chapter)) {
+            (Some(title), Some(e)) => format!("{title} - {}", e.number().unwrap_or("oneshot")),
+            (Some(title), None) => format!("{title} - loading..."),
+            (None, _) => "loading...".to_owned(),
+        },
+        None => "loading...".to_owned(),
+    };
+    let t = Paragraph::new(format!("{header}\n{}", reader.lock().current()))
         .alignment(Alignment::Center)
         .block(Block::default());
     let d = Block::default()
@@ -54,12 +88,85 @@ pub fn render_widgets<B: Backend + Write + Send>(f: &mut Frame<B>, state: &AppSt
         ))
         .borders(Borders::LEFT);
     f.render_widget(title, layout[0]);
-    f.render_widget(t, layout[1]);
+    match state.search_picker {
+        Some(ref picker) => render_search_picker(f, picker, layout[1]),
+        None if state.show_downloads => render_downloads(f, layout[1]),
+        None => f.render_widget(t, layout[1]),
+    }
     f.render_widget(d, layout[2]);
 
     layout[1]
 }
 
+fn render_search_picker<B: Backend>(f: &mut Frame<B>, picker: &SearchPicker, area: Rect) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    let items: Vec<ListItem> = picker
+        .entries
+        .iter()
+        .map(|e| {
+            let kind = if e.saved.is_some() { "*" } else { " " };
+            let new = match (e.last_run, e.new) {
+                (None, _) => String::new(),
+                (Some(d), None) => format!(" (run {}, ...)", time::relative(d)),
+                (Some(d), Some(n)) => format!(" (run {}, {n} new)", time::relative(d)),
+            };
+            ListItem::new(Spans::from(format!("{kind} {}{new}", e.label)))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().title("searches").borders(Borders::BOTTOM))
+        .highlight_style(Style::default().fg(Color::LightCyan));
+    let mut list_state = ListState::default();
+    list_state.select(Some(picker.selected));
+
+    let titles: Vec<&str> = picker.results.iter().map(|(_, t)| t.as_str()).collect();
+    let results = Paragraph::new(titles.join("\n")).block(Block::default().title("results"));
+
+    f.render_stateful_widget(list, layout[0], &mut list_state);
+    f.render_widget(results, layout[1]);
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MiB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{} KiB", b / 1024),
+        b => format!("{b} B"),
+    }
+}
+
+fn render_downloads<B: Backend>(f: &mut Frame<B>, area: Rect) {
+    let items: Vec<ListItem> = queue::items()
+        .iter()
+        .map(|i| {
+            let status = match i.state {
+                ItemState::Queued => match i.retry_at {
+                    Some(t) => format!(
+                        "retry {} ({})",
+                        time::relative(t),
+                        i.error.as_deref().unwrap_or("?")
+                    ),
+                    None => "queued".to_owned(),
+                },
+                ItemState::Running => format!(
+                    "{}/{} pages, {}",
+                    i.progress.pages,
+                    i.progress.total_pages,
+                    format_bytes(i.progress.bytes)
+                ),
+                ItemState::Done => "done".to_owned(),
+                ItemState::Failed => format!("failed ({})", i.error.as_deref().unwrap_or("?")),
+            };
+            ListItem::new(Spans::from(format!("{}  {status}", i.label)))
+        })
+        .collect();
+    let list = List::new(items).block(Block::default().title("downloads"));
+    f.render_widget(list, area);
+}
+
 pub fn render<B: Backend + Write + Send + 'static>(
     comps: AppComponents<B>,
     ws: &TermWinSize,
@@ -76,12 +183,21 @@ pub fn render<B: Backend + Write + Send + 'static>(
         .lock()
         .draw(|f| reader_area = render_widgets(f, comps.state.lock().borrow_mut(), comps.reader.clone()))?;
 
-    comps.reader.lock().draw(
-        reader_area,
-        ws,
-        comps.terminal.lock().borrow_mut(),
-        comps.image_manager.lock().borrow_mut(),
-    )?;
+    // the picker and the downloads take the place of the reader
+    let covered = {
+        let state = comps.state.lock();
+        state.search_picker.is_some() || state.show_downloads
+    };
+    if covered {
+        comps.image_manager.lock().hide_all_images();
+    } else {
+        comps.reader.lock().draw(
+            reader_area,
+            ws,
+            comps.terminal.lock().borrow_mut(),
+            comps.image_manager.lock().borrow_mut(),
+        )?;
+    }
     comps
         .image_manager
         .lock()
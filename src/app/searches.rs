@@ -0,0 +1,246 @@
+// Saved searches and search history, persisted in the config dir.
+
+use std::{collections::VecDeque, fs, io::ErrorKind, path::PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::structs::{ids::MangaId, MangaListFilter},
+    consts::SEARCH_HISTORY_LEN,
+    paths,
+};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedSearch {
+    pub name: String,
+    pub filter: MangaListFilter,
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    /// What the user typed (or a description of the search).
+    pub label: String,
+    pub filter: MangaListFilter,
+    pub ran_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Searches {
+    saved: Vec<SavedSearch>,
+    // most recent first
+    history: VecDeque<HistoryEntry>,
+}
+
+fn searches_path() -> PathBuf {
+    paths::config_dir().join("searches.json")
+}
+
+impl Searches {
+    /// Load the searches from disk, a missing file just means no searches.
+    pub fn load() -> Result<Self> {
+        match fs::read(searches_path()) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        paths::write_atomic(&searches_path(), &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn saved(&self) -> &[SavedSearch] {
+        &self.saved
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.history.iter()
+    }
+
+    /// Save a search, replacing any search with the same name.
+    pub fn add_saved(&mut self, name: String, filter: MangaListFilter) {
+        let search = SavedSearch {
+            name,
+            filter,
+            last_run: None,
+        };
+        match self.saved.iter_mut().find(|s| s.name == search.name) {
+            Some(s) => *s = search,
+            None => self.saved.push(search),
+        }
+    }
+
+    pub fn remove_saved(&mut self, name: &str) -> Option<SavedSearch> {
+        let index = self.saved.iter().position(|s| s.name == name)?;
+        Some(self.saved.remove(index))
+    }
+
+    /// Record that a saved search was run now.
+    pub fn mark_run(&mut self, name: &str) {
+        if let Some(s) = self.saved.iter_mut().find(|s| s.name == name) {
+            s.last_run = Some(Utc::now());
+        }
+    }
+
+    /// Add a search to the history, running the same search again moves it back to the top.
+    pub fn push_history(&mut self, label: String, filter: MangaListFilter) {
+        self.history.retain(|e| e.label != label);
+        self.history.push_front(HistoryEntry {
+            label,
+            filter,
+            ran_at: Utc::now(),
+        });
+        self.history.truncate(SEARCH_HISTORY_LEN);
+    }
+}
+
+/// An entry of the search picker, either a saved search or one from the history.
+pub struct PickerEntry {
+    pub label: String,
+    /// Name of the saved search, None for history entries.
+    pub saved: Option<String>,
+    pub filter: MangaListFilter,
+    pub last_run: Option<DateTime<Utc>>,
+    /// How many mangas were updated since the last run, None until fetched.
+    pub new: Option<i32>,
+}
+
+impl PickerEntry {
+    /// The filter, restricted to mangas updated since the last run.
+    pub fn new_since_filter(&self) -> Option<MangaListFilter> {
+        self.last_run
+            .map(|d| self.filter.clone().updated_at_since(d))
+    }
+}
+
+/// State of the search picker (in the TUI).
+pub struct SearchPicker {
+    pub entries: Vec<PickerEntry>,
+    pub selected: usize,
+    /// Results of the last search run from the picker, with their titles.
+    pub results: Vec<(MangaId, String)>,
+}
+
+impl SearchPicker {
+    pub fn new(searches: &Searches) -> Self {
+        let saved = searches.saved().iter().map(|s| PickerEntry {
+            label: s.name.clone(),
+            saved: Some(s.name.clone()),
+            filter: s.filter.clone(),
+            last_run: s.last_run,
+            new: None,
+        });
+        let history = searches.history().map(|e| PickerEntry {
+            label: e.label.clone(),
+            saved: None,
+            filter: e.filter.clone(),
+            last_run: Some(e.ran_at),
+            new: None,
+        });
+
+        Self {
+            entries: saved.chain(history).collect(),
+            selected: 0,
+            results: Vec::new(),
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.selected = (self.selected + 1).min(self.entries.len().saturating_sub(1));
+    }
+
+    pub fn previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn current(&self) -> Option<&PickerEntry> {
+        self.entries.get(self.selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::structs::{
+        filter::{MangaListOrder, MangaListOrderCriteria},
+        lang_codes::LanguageCode,
+    };
+
+    fn labels(searches: &Searches) -> Vec<&str> {
+        searches.history().map(|e| e.label.as_str()).collect()
+    }
+
+    #[test]
+    fn history_is_deduplicated_and_bounded() {
+        let mut searches = Searches::default();
+        for label in ["a", "b", "c"] {
+            searches.push_history(label.to_owned(), MangaListFilter::default().title(label));
+        }
+        assert_eq!(labels(&searches), ["c", "b", "a"]);
+
+        // running it again moves it back to the top, with the new filter
+        searches.push_history("a".to_owned(), MangaListFilter::default().year(2000));
+        assert_eq!(labels(&searches), ["a", "c", "b"]);
+        let top = searches.history().next().unwrap();
+        assert_eq!(top.filter, MangaListFilter::default().year(2000));
+
+        for i in 0..SEARCH_HISTORY_LEN {
+            searches.push_history(i.to_string(), MangaListFilter::default());
+        }
+        assert_eq!(searches.history().count(), SEARCH_HISTORY_LEN);
+        assert_eq!(
+            searches.history().next().unwrap().label,
+            (SEARCH_HISTORY_LEN - 1).to_string()
+        );
+        assert!(!labels(&searches).contains(&"a"));
+    }
+
+    #[test]
+    fn saved_searches() {
+        let mut searches = Searches::default();
+        searches.add_saved("seinen".to_owned(), MangaListFilter::default().year(1990));
+        searches.add_saved("other".to_owned(), MangaListFilter::default());
+        searches.mark_run("seinen");
+        assert!(searches.saved()[0].last_run.is_some());
+        assert!(searches.saved()[1].last_run.is_none());
+        // unknown names are ignored
+        searches.mark_run("nothing");
+
+        // same name, replaced in place
+        searches.add_saved("seinen".to_owned(), MangaListFilter::default().year(1991));
+        let names: Vec<_> = searches.saved().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["seinen", "other"]);
+        assert_eq!(
+            searches.saved()[0].filter,
+            MangaListFilter::default().year(1991)
+        );
+        assert!(searches.saved()[0].last_run.is_none());
+
+        assert_eq!(searches.remove_saved("seinen").unwrap().name, "seinen");
+        assert!(searches.remove_saved("seinen").is_none());
+        assert_eq!(searches.saved().len(), 1);
+    }
+
+    #[test]
+    fn saved_filters_round_trip() {
+        let filter = MangaListFilter::default()
+            .title("berserk")
+            .no_year()
+            .translated_language(LanguageCode::English)
+            .order(MangaListOrderCriteria::Year, MangaListOrder::Asc);
+        let mut searches = Searches::default();
+        searches.add_saved("berserk".to_owned(), filter.clone());
+        searches.mark_run("berserk");
+        searches.push_history("berserk".to_owned(), filter.clone());
+
+        let json = serde_json::to_vec(&searches).unwrap();
+        let back: Searches = serde_json::from_slice(&json).unwrap();
+        assert_eq!(back.saved()[0].filter, filter);
+        assert_eq!(back.saved()[0].last_run, searches.saved()[0].last_run);
+        assert_eq!(back.history().next().unwrap().filter, filter);
+    }
+}
@@ -1,11 +1,41 @@
+use crate::api::structs::{
+    chapters::{ChapterList, TranslationPreferences},
+    ids::{ChapterId, MangaId},
+    lang_codes::LanguageCode,
+};
+
+use super::{preferences::Preferences, searches::SearchPicker};
+
+/// The chapter being read, and what is needed to go to the next one.
+pub struct Reading {
+    pub manga: MangaId,
+    /// Title of the manga, None until it is in the cache.
+    pub title: Option<String>,
+    pub chapter: ChapterId,
+    /// All the uploads of the manga.
+    pub chapters: ChapterList,
+    pub translations: TranslationPreferences,
+}
+
 pub struct AppState {
     pub block_name: String,
+    /// The search picker, when it is opened.
+    pub search_picker: Option<SearchPicker>,
+    pub reading: Option<Reading>,
+    /// Whether the download queue is shown (in place of the reader).
+    pub show_downloads: bool,
+    /// Preferred languages of the titles, read once from the preferences.
+    pub languages: Vec<LanguageCode>,
 }
 
 impl AppState {
     pub fn new() -> Self {
         Self {
             block_name: "?".to_owned(),
+            search_picker: None,
+            reading: None,
+            show_downloads: false,
+            languages: Preferences::load_or_default().languages,
         }
     }
 }
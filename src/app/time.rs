@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 #[cfg(feature = "timers")]
 use std::{
     cell::RefCell,
@@ -77,3 +78,72 @@ pub fn timer_start(_: impl IntoString) {}
 #[cfg(not(feature = "timers"))]
 #[inline]
 pub fn timer_stop(_: impl IntoString) {}
+
+/// Format a date relative to now, i.e. "3 days ago" or "in 2 hours".
+pub fn relative(date: DateTime<Utc>) -> String {
+    relative_to(date, Utc::now())
+}
+
+pub fn relative_to(date: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (7 * 24 * 3600, "week"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+    ];
+
+    let secs = (now - date).num_seconds();
+    let (future, secs) = (secs < 0, secs.abs());
+    let text = match UNITS.iter().find(|(s, _)| secs >= *s) {
+        Some((s, unit)) => {
+            let n = secs / s;
+            format!("{n} {unit}{}", if n > 1 { "s" } else { "" })
+        }
+        None => return "just now".to_owned(),
+    };
+
+    if future {
+        format!("in {text}")
+    } else {
+        format!("{text} ago")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn relative_dates() {
+        let now = Utc::now();
+        let cases = [
+            (Duration::zero(), "just now"),
+            (Duration::seconds(59), "just now"),
+            (Duration::seconds(-59), "just now"),
+            (Duration::minutes(1), "1 minute ago"),
+            (Duration::seconds(119), "1 minute ago"),
+            (Duration::minutes(2), "2 minutes ago"),
+            (Duration::hours(1), "1 hour ago"),
+            (Duration::hours(23), "23 hours ago"),
+            (Duration::days(1), "1 day ago"),
+            (Duration::days(6), "6 days ago"),
+            (Duration::days(7), "1 week ago"),
+            (Duration::days(29), "4 weeks ago"),
+            (Duration::days(30), "1 month ago"),
+            (Duration::days(364), "12 months ago"),
+            (Duration::days(365), "1 year ago"),
+            (Duration::days(3 * 365), "3 years ago"),
+            (Duration::minutes(-1), "in 1 minute"),
+            (Duration::hours(-5), "in 5 hours"),
+            (Duration::days(-1), "in 1 day"),
+            (Duration::days(-800), "in 2 years"),
+        ];
+        for (ago, expected) in cases {
+            assert_eq!(relative_to(now - ago, now), expected, "{ago}");
+        }
+    }
+}
@@ -0,0 +1,337 @@
+// Commands that can be run without starting the TUI.
+
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::{Error, Result};
+
+use crate::{
+    api::{
+        search::SearchQuery,
+        structs::{Manga, MangaListFilter},
+        Api,
+    },
+    app::{preferences::Preferences, searches::Searches, time},
+    consts::SEARCH_RESULTS,
+    export::{self, cbz, epub, ChapterRange, Grouping},
+    library::{
+        self,
+        queue::{self, ItemState, QueueItem},
+        Download,
+    },
+};
+
+const USAGE: &str = "\
+usage: mngdx [command]
+
+Without any command, start the TUI.
+
+commands:
+    search <query>         search mangas, i.e. mngdx search berserk tag:action lang:en sort:follows
+    save <name> <query>    save a search under a name, to run it again from the TUI
+    searches               list saved searches
+    forget <name>          remove a saved search
+    download <what>        download to read offline, what is one of:
+                               chapter <id>, volume <manga id> <volume>, manga <id>
+                           add --data-saver for the smaller pages
+    downloads [retry|clear]
+                           list the download queue, retry the failed downloads or remove
+                           the finished ones
+    library                list downloaded mangas and chapters
+    export <what> [dir]    export to cbz files (with a ComicInfo.xml) in dir, or the current
+                           directory, what is one of the downloads above, options:
+                               --epub              fixed layout epub instead of cbz
+                               --chapters <range>  only these chapters, i.e. 1-20, 5- or 12
+                               --per-chapter, --per-volume, --single
+                                                   a file per chapter, per volume (the
+                                                   default for cbz) or for everything (for
+                                                   epub)
+                               --grayscale         convert the pages to grayscale (epub)
+                               --size <w>x<h>      scale the pages down to fit (epub)
+    help                   show this message";
+
+/// Run the command in args (without the program name), returns None if there is no command and
+/// the TUI should be started.
+pub fn run(args: &[String]) -> Option<Result<()>> {
+    let (command, rest) = args.split_first()?;
+
+    Some(match (command.as_str(), rest) {
+        ("search", _) => search(&join_query(rest)),
+        ("save", [name, query @ ..]) => save(name, &join_query(query)),
+        ("searches", []) => list_searches(),
+        ("forget", [name]) => forget(name),
+        ("download", _) => download(rest),
+        ("downloads", _) => downloads(rest),
+        ("library", []) => list_library(),
+        ("export", _) => export(rest),
+        ("help" | "-h" | "--help", _) => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            Err(Error::msg(format!("Bad command `{}`", args.join(" "))))
+        }
+    })
+}
+
+/// Join the arguments of a search into a query. The shell removed the quotes (`tag:"slice of
+/// life"` is given as `tag:slice of life`), so arguments with spaces are quoted again, or they
+/// would be split into a tag and title words.
+fn join_query(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if !arg.contains(char::is_whitespace) || arg.contains('"') {
+                return arg.clone();
+            }
+            // the key stays outside of the quotes, or it would be part of the value
+            match arg.split_once(':') {
+                Some((key, value)) if !key.contains(char::is_whitespace) => {
+                    format!("{key}:\"{value}\"")
+                }
+                _ => format!("\"{arg}\""),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse a search and resolve its tags, printing where the error is if there is one.
+async fn parse_search(api: &mut Api, input: &str) -> Result<MangaListFilter> {
+    let languages = Preferences::load()?.languages;
+    let res = match SearchQuery::parse(input) {
+        Ok(q) => q.into_filter(api, &languages).await,
+        Err(e) => Err(e),
+    };
+
+    res.map_err(|e| {
+        eprintln!("{input}");
+        eprintln!("{}", e.pointer(input));
+        e.into()
+    })
+}
+
+fn search(input: &str) -> Result<()> {
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        let mut api = Api::new();
+        let filter = parse_search(&mut api, input).await?;
+        let languages = Preferences::load()?.languages;
+
+        for id in api.manga_list(filter.clone(), 0, SEARCH_RESULTS).await? {
+            if let Some(manga) = api.cache().get::<Manga>(&id) {
+                println!("{id}  {}", manga.title_in(&languages));
+            }
+        }
+
+        let mut searches = Searches::load()?;
+        searches.push_history(input.to_owned(), filter);
+        searches.save()
+    })
+}
+
+fn save(name: &str, input: &str) -> Result<()> {
+    let rt = tokio::runtime::Runtime::new()?;
+    let filter = rt.block_on(async {
+        let mut api = Api::new();
+        parse_search(&mut api, input).await
+    })?;
+
+    let mut searches = Searches::load()?;
+    searches.add_saved(name.to_owned(), filter);
+    searches.save()
+}
+
+fn list_searches() -> Result<()> {
+    for s in Searches::load()?.saved() {
+        match s.last_run {
+            Some(d) => println!("{}  (last run {})", s.name, time::relative(d)),
+            None => println!("{}", s.name),
+        }
+    }
+    Ok(())
+}
+
+fn forget(name: &str) -> Result<()> {
+    let mut searches = Searches::load()?;
+    match searches.remove_saved(name) {
+        Some(_) => searches.save(),
+        None => Err(Error::msg(format!("No saved search named `{name}`"))),
+    }
+}
+
+fn parse<T: FromStr>(id: &str) -> Result<T> {
+    id.parse().map_err(|_| Error::msg(format!("Bad id `{id}`")))
+}
+
+fn parse_download(args: &[&str]) -> Result<Download> {
+    Ok(match args {
+        ["chapter", id] => Download::Chapter { id: parse(id)? },
+        ["volume", manga, volume] => Download::Volume {
+            manga: parse(manga)?,
+            volume: volume.to_string(),
+        },
+        ["manga", id] => Download::Manga { id: parse(id)? },
+        _ => return Err(Error::msg(format!("Bad download `{}`", args.join(" ")))),
+    })
+}
+
+fn print_item(item: &QueueItem) {
+    let status = match item.state {
+        ItemState::Queued => match item.retry_at {
+            Some(t) => format!(
+                "retry {} ({})",
+                time::relative(t),
+                item.error.as_deref().unwrap_or("?")
+            ),
+            None => "queued".to_owned(),
+        },
+        ItemState::Running => "running".to_owned(),
+        ItemState::Done => "done".to_owned(),
+        ItemState::Failed => format!("failed ({})", item.error.as_deref().unwrap_or("?")),
+    };
+    println!("{}  {}  {status}", item.chapter, item.label);
+}
+
+fn download(args: &[String]) -> Result<()> {
+    let data_saver = args.iter().any(|a| a == "--data-saver");
+    let args: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|a| *a != "--data-saver")
+        .collect();
+    let what = parse_download(&args)?;
+
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        let mut api = Api::new();
+        let added = queue::enqueue(&mut api, &what, data_saver).await?;
+        println!("Queued {added} chapters");
+        queue::run(print_item).await;
+        Ok(())
+    })
+}
+
+fn downloads(args: &[String]) -> Result<()> {
+    match args {
+        [] => {}
+        [a] if a == "retry" => println!("Queued {} chapters again", queue::retry_failed()),
+        [a] if a == "clear" => queue::clear_done(),
+        _ => return Err(Error::msg(format!("Bad downloads `{}`", args.join(" ")))),
+    }
+    for item in queue::items() {
+        print_item(&item);
+    }
+    Ok(())
+}
+
+fn list_library() -> Result<()> {
+    let languages = Preferences::load()?.languages;
+    for manga in library::mangas()? {
+        println!("{}  {}", manga.id, manga.manga.title_in(&languages));
+        for c in manga.chapters()? {
+            println!(
+                "    {}  vol. {} ch. {}  ({} pages{})",
+                c.id,
+                c.chapter.volume.as_deref().unwrap_or("none"),
+                c.chapter.chapter.as_deref().unwrap_or("?"),
+                c.pages.len(),
+                if c.data_saver { ", data saver" } else { "" }
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A screen size, i.e. 1264x1680.
+fn parse_size(size: &str) -> Result<(u32, u32)> {
+    size.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .ok_or_else(|| Error::msg(format!("Bad size `{size}`")))
+}
+
+fn export(args: &[String]) -> Result<()> {
+    let mut to_epub = false;
+    let mut grouping = None;
+    let mut range = ChapterRange::default();
+    let mut options = epub::Options::default();
+    let mut rest = Vec::new();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        match arg {
+            "--epub" => to_epub = true,
+            "--per-chapter" => grouping = Some(Grouping::Chapter),
+            "--per-volume" => grouping = Some(Grouping::Volume),
+            "--single" => grouping = Some(Grouping::All),
+            "--chapters" => range = args.next().unwrap_or_default().parse()?,
+            "--grayscale" => options.grayscale = true,
+            "--size" => options.max_size = Some(parse_size(args.next().unwrap_or_default())?),
+            _ => rest.push(arg),
+        }
+    }
+    // the directory is whatever is left after the download
+    let dir = match parse_download(&rest) {
+        Ok(_) => PathBuf::from("."),
+        Err(_) => PathBuf::from(rest.pop().unwrap_or_default()),
+    };
+    let what = parse_download(&rest)?;
+    // a range of chapters is read as one book, but comic servers want a file per volume
+    let grouping = grouping.unwrap_or(if to_epub {
+        Grouping::All
+    } else {
+        Grouping::Volume
+    });
+
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        let mut api = Api::new();
+        let (series, books) = export::books(&mut api, &what, &range, grouping).await?;
+        for book in &books {
+            let path = if to_epub {
+                epub::write(&mut api, &series, book, &dir, &options).await?
+            } else {
+                cbz::write(&mut api, &series, book, &dir).await?
+            };
+            println!("{}  ({} pages)", path.display(), book.page_count());
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(args: &[&str]) -> String {
+        let args: Vec<_> = args.iter().map(|a| a.to_string()).collect();
+        join_query(&args)
+    }
+
+    #[test]
+    fn quotes_are_put_back() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["berserk", "tag:action"], "berserk tag:action"),
+            // mngdx search tag:"slice of life" "made in abyss"
+            (
+                &["tag:slice of life", "made in abyss"],
+                r#"tag:"slice of life" "made in abyss""#,
+            ),
+            (&["-tag:boys' love"], r#"-tag:"boys' love""#),
+            (&["Re:Zero kara"], r#"Re:"Zero kara""#),
+            (&["one piece: film"], r#""one piece: film""#),
+            // quoted for the query already
+            (&[r#"tag:"slice of life""#], r#"tag:"slice of life""#),
+        ];
+        for (args, query) in cases {
+            assert_eq!(join(args), *query, "{args:?}");
+            assert!(SearchQuery::parse(query).is_ok(), "{query}");
+        }
+    }
+
+    #[test]
+    fn quoted_values_stay_together() {
+        let query = SearchQuery::parse(&join(&["tag:slice of life", "lang:en"])).unwrap();
+        assert_eq!(query.tag_names(), ["slice of life"]);
+        let query = SearchQuery::parse(&join(&["Re:Zero kara", "tag:drama"])).unwrap();
+        assert_eq!(query.tag_names(), ["drama"]);
+    }
+}
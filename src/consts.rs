@@ -8,5 +8,16 @@ pub const KITTY_PADDING: i32 = 5;
 pub const IMAGE_SLOTS: u32 = 20;
 // How many tasks can be run in parallel on the executor thread, setting this number too high will get you rate limited.
 pub const EXECUTOR_THREAD_COUNT: u32 = 2;
+// How many chapters can be downloaded at a time, each download takes an executor thread so this has to be lower than EXECUTOR_THREAD_COUNT.
+pub const DOWNLOAD_CONCURRENCY: usize = 1;
 // The framerate to aim for
 pub const FRAME_RATE: u32 = 60;
+// How often to redraw when nothing changed (in seconds), to keep the downloads, the connectivity
+// and the relative times up to date.
+pub const IDLE_REDRAW_SECS: u64 = 1;
+// Name of the directories mngdx uses in the config / cache / data dirs.
+pub const APP_NAME: &str = "mngdx";
+// How many searches to keep in the history.
+pub const SEARCH_HISTORY_LEN: usize = 50;
+// How many results to show for a search.
+pub const SEARCH_RESULTS: i32 = 20;
@@ -0,0 +1,158 @@
+// Comic book archives: the pages in a zip, with a ComicInfo.xml (the metadata format of
+// ComicRack, that most comic readers understand).
+
+use std::{fmt::Write, path::Path, path::PathBuf};
+
+use anyhow::Result;
+use chrono::Datelike;
+
+use super::{book_path, escape_xml, page_extension, read_page, write_zip, Book, Series};
+use crate::api::Api;
+
+fn comic_info(series: &Series, book: &Book, page_count: usize) -> String {
+    // in the order of the schema (a sequence), some readers don't take them in another one
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    let single = match book.chapters.as_slice() {
+        [c] => Some(&c.chapter),
+        _ => None,
+    };
+    if let Some(title) = single
+        .and_then(|c| c.title.as_ref())
+        .filter(|t| !t.is_empty())
+    {
+        fields.push(("Title", title.clone()));
+    }
+    fields.push(("Series", series.title.clone()));
+    if let Some(n) = single.and_then(|c| c.chapter.as_ref()) {
+        fields.push(("Number", n.clone()));
+    }
+    // it's a number in the schema, volumes like "1.5" can't be put in it
+    if let Some(v) = book.volume.as_ref().filter(|v| v.parse::<u32>().is_ok()) {
+        fields.push(("Volume", v.clone()));
+    }
+    if let Some(date) = book.chapters.iter().map(|c| c.chapter.publish_at).min() {
+        fields.push(("Year", date.year().to_string()));
+        fields.push(("Month", date.month().to_string()));
+        fields.push(("Day", date.day().to_string()));
+    }
+    if !series.writers.is_empty() {
+        fields.push(("Writer", series.writers.join(", ")));
+    }
+    if !series.pencillers.is_empty() {
+        fields.push(("Penciller", series.pencillers.join(", ")));
+    }
+    if !series.genres.is_empty() {
+        fields.push(("Genre", series.genres.join(", ")));
+    }
+    fields.push(("PageCount", page_count.to_string()));
+    if let Some(c) = book.chapters.first() {
+        fields.push(("LanguageISO", c.chapter.translated_language.to_string()));
+    }
+    let manga = if series.right_to_left() {
+        "YesAndRightToLeft"
+    } else {
+        "Yes"
+    };
+    fields.push(("Manga", manga.to_owned()));
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ComicInfo \
+         xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+    );
+    for (name, value) in fields {
+        let _ = writeln!(xml, "  <{name}>{}</{name}>", escape_xml(&value));
+    }
+    xml.push_str("</ComicInfo>\n");
+    xml
+}
+
+/// Write a book to dir as a cbz, returns the path of the file.
+pub async fn write(api: &mut Api, series: &Series, book: &Book, dir: &Path) -> Result<PathBuf> {
+    let path = book_path(dir, book, "cbz");
+    write_zip(&path, |mut zip| async move {
+        // pages are numbered across chapters, so that they stay in order
+        let mut n = 0;
+        for c in &book.chapters {
+            for page in c.pages(api).await? {
+                n += 1;
+                let name = format!("{n:04}.{}", page_extension(&page));
+                zip.add(&name, &read_page(&page).await?)?;
+            }
+        }
+        zip.add("ComicInfo.xml", comic_info(series, book, n).as_bytes())?;
+        Ok(zip)
+    })
+    .await?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::structs::{test_chapter, Chapter},
+        app::reader::Page,
+        export::{ExportChapter, Series},
+    };
+
+    #[test]
+    fn comic_info_order() {
+        let series = Series {
+            id: uuid::Uuid::new_v4().into(),
+            title: "Series".to_owned(),
+            writers: vec!["Writer".to_owned()],
+            pencillers: vec!["Artist".to_owned()],
+            genres: vec!["Action".to_owned(), "Comedy".to_owned()],
+            original_language: "ja".to_owned(),
+            cover: None,
+        };
+        let chapter = Chapter {
+            title: Some("A & B".to_owned()),
+            pages: 2,
+            ..test_chapter(Some("2"), Some("12"))
+        };
+        let book = Book {
+            name: "Series - Vol. 2 Ch. 12".to_owned(),
+            volume: Some("2".to_owned()),
+            chapters: vec![ExportChapter {
+                id: uuid::Uuid::new_v4().into(),
+                volume: chapter.volume.clone(),
+                chapter,
+                downloaded: Some(vec![
+                    Page::Url("a.png".to_owned()),
+                    Page::Url("b.png".to_owned()),
+                ]),
+            }],
+        };
+
+        let xml = comic_info(&series, &book, book.page_count());
+        let names: Vec<&str> = xml
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix('<')?.split_once('>'))
+            .map(|(name, _)| name)
+            .filter(|n| !n.starts_with('?') && !n.starts_with("ComicInfo") && !n.starts_with('/'))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Title",
+                "Series",
+                "Number",
+                "Volume",
+                "Year",
+                "Month",
+                "Day",
+                "Writer",
+                "Penciller",
+                "Genre",
+                "PageCount",
+                "LanguageISO",
+                "Manga"
+            ]
+        );
+        assert!(xml.contains("<Title>A &amp; B</Title>"));
+        assert!(xml.contains("<PageCount>2</PageCount>"));
+        assert!(xml.contains("<Manga>YesAndRightToLeft</Manga>"));
+    }
+}
@@ -0,0 +1,588 @@
+// Fixed layout EPUB 3, for e-readers: a page of the book per page of the manga, with the cover
+// first and a table of contents with the chapters.
+
+use std::{
+    fmt::Write,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, Result};
+use chrono::Utc;
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageOutputFormat};
+use uuid::Uuid;
+
+use super::{book_path, escape_xml, read_page, write_zip, Book, Series};
+use crate::api::{
+    structs::{ids::Id, Chapter},
+    Api,
+};
+
+/// Quality of the pages that are converted.
+const JPEG_QUALITY: u8 = 90;
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+const STYLE: &str = "html, body { margin: 0; padding: 0; }
+img { display: block; width: 100%; height: 100%; }
+";
+
+/// What is done to the pages, to fit e-ink screens.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    pub grayscale: bool,
+    /// Pages bigger than this (width, height) are scaled down to fit in it.
+    pub max_size: Option<(u32, u32)>,
+}
+
+struct Image {
+    bytes: Vec<u8>,
+    extension: &'static str,
+    media_type: &'static str,
+    width: u32,
+    height: u32,
+}
+
+/// Images readers have to support, the others are converted.
+fn supported(format: ImageFormat) -> Option<(&'static str, &'static str)> {
+    match format {
+        ImageFormat::Jpeg => Some(("jpg", "image/jpeg")),
+        ImageFormat::Png => Some(("png", "image/png")),
+        ImageFormat::Gif => Some(("gif", "image/gif")),
+        ImageFormat::WebP => Some(("webp", "image/webp")),
+        _ => None,
+    }
+}
+
+/// Apply the options to an image, it's only decoded if it has to be changed.
+fn process(bytes: Vec<u8>, options: &Options) -> Result<Image> {
+    let reader = image::io::Reader::new(Cursor::new(&bytes)).with_guessed_format()?;
+    let format = reader.format().and_then(supported);
+    let (width, height) = reader.into_dimensions()?;
+    let too_big = options
+        .max_size
+        .is_some_and(|(w, h)| width > w || height > h);
+    if let (Some((extension, media_type)), false, false) = (format, options.grayscale, too_big) {
+        return Ok(Image {
+            bytes,
+            extension,
+            media_type,
+            width,
+            height,
+        });
+    }
+
+    let mut img = image::load_from_memory(&bytes)?;
+    if let Some((w, h)) = options.max_size.filter(|_| too_big) {
+        // keeps the aspect ratio
+        img = img.resize(w, h, FilterType::Lanczos3);
+    }
+    // jpeg has no alpha
+    img = if options.grayscale {
+        DynamicImage::ImageLuma8(img.to_luma8())
+    } else {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    };
+    let mut out = Cursor::new(Vec::new());
+    img.write_to(&mut out, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+    Ok(Image {
+        bytes: out.into_inner(),
+        extension: "jpg",
+        media_type: "image/jpeg",
+        width: img.width(),
+        height: img.height(),
+    })
+}
+
+/// A page of the book, showing one image.
+fn page_xhtml(title: &str, image: &str, width: u32, height: u32) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <link rel="stylesheet" type="text/css" href="../style.css"/>
+</head>
+<body>
+  <img src="../images/{image}" alt=""/>
+</body>
+</html>
+"#,
+        escape_xml(title)
+    )
+}
+
+/// Shown in the table of contents.
+fn chapter_label(c: &Chapter) -> String {
+    let mut label = match (&c.volume, &c.chapter) {
+        (Some(v), Some(n)) => format!("Vol. {v} Ch. {n}"),
+        (None, Some(n)) => format!("Ch. {n}"),
+        (_, None) => "Oneshot".to_owned(),
+    };
+    if let Some(title) = c.title.as_ref().filter(|t| !t.is_empty()) {
+        let _ = write!(label, " - {title}");
+    }
+    label
+}
+
+fn nav_xhtml(title: &str, toc: &[(String, String)]) -> String {
+    let mut xhtml = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{}</title>
+</head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>Contents</h1>
+    <ol>
+"#,
+        escape_xml(title)
+    );
+    for (label, page) in toc {
+        let _ = writeln!(
+            xhtml,
+            r#"      <li><a href="{page}">{}</a></li>"#,
+            escape_xml(label)
+        );
+    }
+    xhtml.push_str("    </ol>\n  </nav>\n</body>\n</html>\n");
+    xhtml
+}
+
+/// Identifier of a book, the same each time the same chapters are exported so that readers don't
+/// take it for another book.
+fn book_id(series: &Series, book: &Book) -> Uuid {
+    let chapters: Vec<u8> = book
+        .chapters
+        .iter()
+        .flat_map(|c| *c.id.uuid().as_bytes())
+        .collect();
+    Uuid::new_v5(&series.id.uuid(), &chapters)
+}
+
+struct ManifestItem {
+    id: String,
+    href: String,
+    media_type: &'static str,
+    properties: Option<&'static str>,
+}
+
+fn content_opf(
+    series: &Series,
+    book: &Book,
+    title: &str,
+    manifest: &[ManifestItem],
+    spine: &[String],
+) -> String {
+    let language = book
+        .chapters
+        .first()
+        .map(|c| c.chapter.translated_language.to_string())
+        .unwrap_or_else(|| "en".to_owned());
+    let direction = if series.right_to_left() { "rtl" } else { "ltr" };
+
+    let mut metadata = vec![
+        format!(
+            r#"<dc:identifier id="book-id">urn:uuid:{}</dc:identifier>"#,
+            book_id(series, book)
+        ),
+        format!("<dc:title>{}</dc:title>", escape_xml(title)),
+        format!("<dc:language>{}</dc:language>", escape_xml(&language)),
+    ];
+    for name in series.writers.iter().chain(&series.pencillers) {
+        let creator = format!("<dc:creator>{}</dc:creator>", escape_xml(name));
+        if !metadata.contains(&creator) {
+            metadata.push(creator);
+        }
+    }
+    for genre in &series.genres {
+        metadata.push(format!("<dc:subject>{}</dc:subject>", escape_xml(genre)));
+    }
+    if let Some(date) = book.chapters.iter().map(|c| c.chapter.publish_at).min() {
+        metadata.push(format!("<dc:date>{}</dc:date>", date.format("%Y-%m-%d")));
+    }
+    metadata.push(format!(
+        r#"<meta property="dcterms:modified">{}</meta>"#,
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    ));
+    metadata.push(r#"<meta property="rendition:layout">pre-paginated</meta>"#.to_owned());
+    metadata.push(r#"<meta property="rendition:orientation">auto</meta>"#.to_owned());
+    metadata.push(r#"<meta property="rendition:spread">none</meta>"#.to_owned());
+    if manifest.iter().any(|i| i.id == "cover") {
+        // for epub 2 readers
+        metadata.push(r#"<meta name="cover" content="cover"/>"#.to_owned());
+    }
+    if series.right_to_left() {
+        // kindle ignores page-progression-direction
+        metadata.push(r#"<meta name="primary-writing-mode" content="horizontal-rl"/>"#.to_owned());
+    }
+
+    let mut opf = String::from(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+"#,
+    );
+    for m in metadata {
+        let _ = writeln!(opf, "    {m}");
+    }
+    opf.push_str("  </metadata>\n  <manifest>\n");
+    for i in manifest {
+        let properties = i
+            .properties
+            .map(|p| format!(r#" properties="{p}""#))
+            .unwrap_or_default();
+        let _ = writeln!(
+            opf,
+            r#"    <item id="{}" href="{}" media-type="{}"{properties}/>"#,
+            i.id, i.href, i.media_type
+        );
+    }
+    let _ = writeln!(
+        opf,
+        "  </manifest>\n  <spine page-progression-direction=\"{direction}\">"
+    );
+    for id in spine {
+        let _ = writeln!(opf, r#"    <itemref idref="{id}"/>"#);
+    }
+    opf.push_str("  </spine>\n</package>\n");
+    opf
+}
+
+/// Write a book to dir as an epub, returns the path of the file.
+pub async fn write(
+    api: &mut Api,
+    series: &Series,
+    book: &Book,
+    dir: &Path,
+    options: &Options,
+) -> Result<PathBuf> {
+    if book.page_count() == 0 {
+        return Err(Error::msg(format!("{} has no pages", book.name)));
+    }
+    let title = match (&book.volume, book.chapters.as_slice()) {
+        (_, [c]) => format!("{} - {}", series.title, chapter_label(&c.chapter)),
+        (Some(v), _) => format!("{} - Vol. {v}", series.title),
+        (None, _) => book.name.clone(),
+    };
+    let path = book_path(dir, book, "epub");
+
+    write_zip(&path, |mut zip| async move {
+        // has to be the first file, and stored
+        zip.add("mimetype", b"application/epub+zip")?;
+        zip.add("META-INF/container.xml", CONTAINER.as_bytes())?;
+        zip.add("OEBPS/style.css", STYLE.as_bytes())?;
+
+        let mut manifest = vec![
+            ManifestItem {
+                id: "nav".to_owned(),
+                href: "nav.xhtml".to_owned(),
+                media_type: "application/xhtml+xml",
+                properties: Some("nav"),
+            },
+            ManifestItem {
+                id: "style".to_owned(),
+                href: "style.css".to_owned(),
+                media_type: "text/css",
+                properties: None,
+            },
+        ];
+        let mut spine = Vec::new();
+        let mut toc = Vec::new();
+
+        // the cover isn't worth failing the export for
+        let cover = match &series.cover {
+            Some(page) => match read_page(page).await.and_then(|b| process(b, options)) {
+                Ok(image) => Some(image),
+                Err(e) => {
+                    log::warn!("Couldn't add the cover of {} ({e})", series.title);
+                    None
+                }
+            },
+            None => None,
+        };
+        if let Some(image) = cover {
+            let name = format!("cover.{}", image.extension);
+            zip.add(&format!("OEBPS/images/{name}"), &image.bytes)?;
+            let xhtml = page_xhtml(&title, &name, image.width, image.height);
+            zip.add("OEBPS/pages/cover.xhtml", xhtml.as_bytes())?;
+            manifest.push(ManifestItem {
+                id: "cover".to_owned(),
+                href: format!("images/{name}"),
+                media_type: image.media_type,
+                properties: Some("cover-image"),
+            });
+            manifest.push(ManifestItem {
+                id: "cover-page".to_owned(),
+                href: "pages/cover.xhtml".to_owned(),
+                media_type: "application/xhtml+xml",
+                properties: None,
+            });
+            spine.push("cover-page".to_owned());
+        }
+
+        let mut n = 0;
+        for c in &book.chapters {
+            for (i, page) in c.pages(api).await?.iter().enumerate() {
+                n += 1;
+                let image = process(read_page(page).await?, options)?;
+                let name = format!("{n:04}.{}", image.extension);
+                zip.add(&format!("OEBPS/images/{name}"), &image.bytes)?;
+                let xhtml = page_xhtml(&title, &name, image.width, image.height);
+                zip.add(&format!("OEBPS/pages/{n:04}.xhtml"), xhtml.as_bytes())?;
+
+                if i == 0 {
+                    toc.push((chapter_label(&c.chapter), format!("pages/{n:04}.xhtml")));
+                }
+                manifest.push(ManifestItem {
+                    id: format!("image-{n:04}"),
+                    href: format!("images/{name}"),
+                    media_type: image.media_type,
+                    properties: None,
+                });
+                manifest.push(ManifestItem {
+                    id: format!("page-{n:04}"),
+                    href: format!("pages/{n:04}.xhtml"),
+                    media_type: "application/xhtml+xml",
+                    properties: None,
+                });
+                spine.push(format!("page-{n:04}"));
+            }
+        }
+
+        zip.add("OEBPS/nav.xhtml", nav_xhtml(&title, &toc).as_bytes())?;
+        let opf = content_opf(series, book, &title, &manifest, &spine);
+        zip.add("OEBPS/content.opf", opf.as_bytes())?;
+        Ok(zip)
+    })
+    .await?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    use super::*;
+    use crate::{
+        api::structs::test_chapter,
+        app::reader::Page,
+        export::{zip::tests::read_back, ExportChapter, Series},
+    };
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbImage::from_fn(width, height, |x, _| Rgb([(x * 5) as u8, 100, 200]));
+        let mut out = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut out, ImageOutputFormat::Png)
+            .unwrap();
+        out.into_inner()
+    }
+
+    fn series(original_language: &str) -> Series {
+        Series {
+            id: Uuid::new_v4().into(),
+            title: "Series".to_owned(),
+            writers: Vec::new(),
+            pencillers: Vec::new(),
+            genres: Vec::new(),
+            original_language: original_language.to_owned(),
+            cover: None,
+        }
+    }
+
+    fn book(numbers: &[&str]) -> Book {
+        let chapters = numbers
+            .iter()
+            .map(|n| ExportChapter {
+                id: Uuid::new_v4().into(),
+                volume: None,
+                chapter: test_chapter(None, Some(n)),
+                downloaded: Some(Vec::new()),
+            })
+            .collect();
+        Book {
+            name: "Series".to_owned(),
+            volume: None,
+            chapters,
+        }
+    }
+
+    fn item(id: &str, properties: Option<&'static str>) -> ManifestItem {
+        ManifestItem {
+            id: id.to_owned(),
+            href: format!("{id}.xhtml"),
+            media_type: "application/xhtml+xml",
+            properties,
+        }
+    }
+
+    #[test]
+    fn untouched_pages_pass_through() {
+        let bytes = png(40, 20);
+        let options = Options {
+            grayscale: false,
+            max_size: Some((40, 20)),
+        };
+        let image = process(bytes.clone(), &options).unwrap();
+        assert_eq!(image.bytes, bytes);
+        assert_eq!((image.extension, image.media_type), ("png", "image/png"));
+        assert_eq!((image.width, image.height), (40, 20));
+    }
+
+    #[test]
+    fn grayscale_pages_become_jpeg() {
+        let options = Options {
+            grayscale: true,
+            max_size: None,
+        };
+        let image = process(png(40, 20), &options).unwrap();
+        assert_eq!((image.extension, image.media_type), ("jpg", "image/jpeg"));
+        let decoded = image::load_from_memory(&image.bytes).unwrap();
+        assert_eq!(
+            image::guess_format(&image.bytes).unwrap(),
+            ImageFormat::Jpeg
+        );
+        assert!(matches!(decoded, DynamicImage::ImageLuma8(_)));
+        assert_eq!(decoded.dimensions(), (40, 20));
+    }
+
+    #[test]
+    fn big_pages_keep_their_aspect_ratio() {
+        let options = Options {
+            grayscale: false,
+            max_size: Some((10, 10)),
+        };
+        let image = process(png(40, 20), &options).unwrap();
+        assert_eq!((image.width, image.height), (10, 5));
+        let decoded = image::load_from_memory(&image.bytes).unwrap();
+        assert_eq!(decoded.dimensions(), (10, 5));
+    }
+
+    #[test]
+    fn opf_direction_and_cover() {
+        let book = book(&["1"]);
+        let spine = ["page-0001".to_owned()];
+
+        let manifest = [item("nav", Some("nav")), item("page-0001", None)];
+        let opf = content_opf(&series("ja"), &book, "Series", &manifest, &spine);
+        assert!(opf.contains(r#"<spine page-progression-direction="rtl">"#));
+        assert!(opf.contains("horizontal-rl"));
+        assert!(!opf.contains(r#"<meta name="cover""#));
+
+        let manifest = [item("cover", Some("cover-image")), item("page-0001", None)];
+        let opf = content_opf(&series("ko"), &book, "Series", &manifest, &spine);
+        assert!(opf.contains(r#"<spine page-progression-direction="ltr">"#));
+        assert!(!opf.contains("horizontal-rl"));
+        assert!(opf.contains(r#"<meta name="cover" content="cover"/>"#));
+    }
+
+    #[test]
+    fn stable_book_ids() {
+        let series = series("ja");
+        let mut book = book(&["1", "2"]);
+        let id = book_id(&series, &book);
+        assert_eq!(book_id(&series, &book), id);
+        let opf = content_opf(&series, &book, "Series", &[], &[]);
+        assert!(opf.contains(&format!("urn:uuid:{id}")));
+
+        // other chapters, another book
+        book.chapters.pop();
+        assert_ne!(book_id(&series, &book), id);
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("mngdx-test-epub-{}", Uuid::new_v4()))
+    }
+
+    /// A downloaded chapter, its pages are written to dir.
+    fn downloaded(dir: &Path, number: &str, pages: usize) -> ExportChapter {
+        let pages = (0..pages)
+            .map(|i| {
+                let path = dir.join(format!("{number}-{i}.png"));
+                std::fs::write(&path, png(20, 30)).unwrap();
+                Page::File(path)
+            })
+            .collect();
+        ExportChapter {
+            id: Uuid::new_v4().into(),
+            volume: None,
+            chapter: test_chapter(None, Some(number)),
+            downloaded: Some(pages),
+        }
+    }
+
+    #[test]
+    fn written_book() {
+        let dir = temp_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let cover = dir.join("cover.png");
+        std::fs::write(&cover, png(20, 30)).unwrap();
+        let series = Series {
+            cover: Some(Page::File(cover)),
+            ..series("ja")
+        };
+        let book = Book {
+            name: "A & B".to_owned(),
+            volume: None,
+            chapters: vec![downloaded(&dir, "1", 2), downloaded(&dir, "2", 1)],
+        };
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let out = dir.join("out");
+        let path = rt
+            .block_on(write(
+                &mut Api::new(),
+                &series,
+                &book,
+                &out,
+                &Options::default(),
+            ))
+            .unwrap();
+        let files = read_back(&std::fs::read(&path).unwrap());
+        let file = |name: &str| {
+            let (_, data) = files.iter().find(|(n, _)| n == name).unwrap();
+            String::from_utf8(data.clone()).unwrap()
+        };
+        assert_eq!(files[0].0, "mimetype");
+
+        // the first page of each chapter, counted across chapters
+        let nav = file("OEBPS/nav.xhtml");
+        assert!(nav.contains("<title>A &amp; B</title>"));
+        assert_eq!(nav.matches("<li>").count(), 2);
+        assert!(nav.contains(r#"<li><a href="pages/0001.xhtml">Ch. 1</a></li>"#));
+        assert!(nav.contains(r#"<li><a href="pages/0003.xhtml">Ch. 2</a></li>"#));
+
+        let opf = file("OEBPS/content.opf");
+        let spine: Vec<&str> = opf
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix(r#"<itemref idref=""#))
+            .filter_map(|l| l.strip_suffix(r#""/>"#))
+            .collect();
+        assert_eq!(spine, ["cover-page", "page-0001", "page-0002", "page-0003"]);
+        assert!(opf.contains(
+            r#"<item id="cover" href="images/cover.png" media-type="image/png" properties="cover-image"/>"#
+        ));
+        assert!(opf.contains(r#"<meta name="cover" content="cover"/>"#));
+        for name in ["images/cover.png", "pages/cover.xhtml", "images/0003.png"] {
+            assert!(
+                files.iter().any(|(n, _)| *n == format!("OEBPS/{name}")),
+                "{name}"
+            );
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
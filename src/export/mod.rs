@@ -0,0 +1,679 @@
+// Export of chapters to files that other readers can open. Pages are taken from the library when
+// the chapters are downloaded, and from mangadex otherwise.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Error, Result};
+
+pub mod cbz;
+pub mod epub;
+mod zip;
+
+use crate::{
+    api::{
+        structs::{
+            chapters::natural_cmp,
+            ids::{AuthorId, ChapterId, CoverId, MangaId},
+            json::data::RelationshipKind,
+            Chapter, Manga, Tag,
+        },
+        Api,
+    },
+    app::{preferences::Preferences, reader::Page},
+    images::cache,
+    library::{resolve_volumes, Download, LibraryChapter, LibraryManga},
+    paths,
+};
+
+use self::zip::ZipWriter;
+
+/// What is known about the manga of the exported chapters.
+pub struct Series {
+    pub id: MangaId,
+    pub title: String,
+    pub writers: Vec<String>,
+    pub pencillers: Vec<String>,
+    /// Names of the tags.
+    pub genres: Vec<String>,
+    /// Language the manga was first published in, i.e. "ja".
+    pub original_language: String,
+    pub cover: Option<Page>,
+}
+
+impl Series {
+    /// Whether it's read from right to left, like japanese mangas.
+    pub fn right_to_left(&self) -> bool {
+        self.original_language == "ja"
+    }
+}
+
+/// How the chapters are split in files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    Chapter,
+    Volume,
+    /// Everything in one file.
+    All,
+}
+
+/// Chapters with a number between from and to (both included), no bound if None. Numbers are
+/// compared like chapters are sorted (see chapters::natural_cmp), so 10.10 comes after 10.5.
+#[derive(Clone, Debug, Default)]
+pub struct ChapterRange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Whether a chapter number is made of digits, with a decimal part or not.
+fn is_chapter_number(n: &str) -> bool {
+    let (integer, decimal) = n.split_once('.').unwrap_or((n, "0"));
+    [integer, decimal]
+        .iter()
+        .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+impl ChapterRange {
+    /// Oneshots are only in a range without bounds.
+    pub fn contains(&self, chapter: &Chapter) -> bool {
+        if self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+        match chapter.chapter.as_deref().map(str::trim) {
+            Some(n) if is_chapter_number(n) => {
+                self.from
+                    .as_deref()
+                    .is_none_or(|f| natural_cmp(n, f) != Ordering::Less)
+                    && self
+                        .to
+                        .as_deref()
+                        .is_none_or(|t| natural_cmp(n, t) != Ordering::Greater)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for ChapterRange {
+    type Err = Error;
+
+    /// "12", "1-20", "5-" or "-10".
+    fn from_str(s: &str) -> Result<Self> {
+        let bound = |b: &str| -> Result<Option<String>> {
+            match b.trim() {
+                "" => Ok(None),
+                b if is_chapter_number(b) => Ok(Some(b.to_owned())),
+                b => Err(Error::msg(format!("Bad chapter number `{b}`"))),
+            }
+        };
+        match s.split_once('-') {
+            Some((from, to)) => Ok(Self {
+                from: bound(from)?,
+                to: bound(to)?,
+            }),
+            None => {
+                let n = bound(s)?;
+                Ok(Self {
+                    from: n.clone(),
+                    to: n,
+                })
+            }
+        }
+    }
+}
+
+pub struct ExportChapter {
+    pub id: ChapterId,
+    pub chapter: Chapter,
+    /// The volume it's sorted in, an upload without one can take the one of the other uploads of
+    /// its chapter.
+    pub volume: Option<String>,
+    /// Pages of the downloaded chapters. The others are asked for right before being written (see
+    /// pages), at-home servers aren't valid for long.
+    downloaded: Option<Vec<Page>>,
+}
+
+impl ExportChapter {
+    /// Number of pages, without asking for them.
+    pub fn page_count(&self) -> usize {
+        match &self.downloaded {
+            Some(pages) => pages.len(),
+            None => self.chapter.pages.max(0) as usize,
+        }
+    }
+
+    /// The pages, an at-home server is asked for if the chapter isn't downloaded.
+    pub async fn pages(&self, api: &mut Api) -> Result<Vec<Page>> {
+        match &self.downloaded {
+            Some(pages) => Ok(pages.clone()),
+            None => Ok(api
+                .chapter_pages(self.id)
+                .await?
+                .into_iter()
+                .map(Page::Url)
+                .collect()),
+        }
+    }
+}
+
+/// Chapters that go in the same file.
+pub struct Book {
+    /// Name of the file, without the extension.
+    pub name: String,
+    /// The volume of its chapters, None if they don't have one (or not the same one).
+    pub volume: Option<String>,
+    pub chapters: Vec<ExportChapter>,
+}
+
+impl Book {
+    pub fn page_count(&self) -> usize {
+        self.chapters.iter().map(ExportChapter::page_count).sum()
+    }
+}
+
+/// Escape text to put it in xml.
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // not allowed in xml 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Make a name usable as a file name (never empty).
+fn file_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    match name.trim_start_matches('.') {
+        "" => "_".to_owned(),
+        name => name.to_owned(),
+    }
+}
+
+/// Extension of a page, png if it has none.
+pub fn page_extension(page: &Page) -> &str {
+    let name = match page {
+        Page::Url(url) => url.as_str(),
+        Page::File(path) => path.to_str().unwrap_or_default(),
+    };
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("png")
+}
+
+/// Bytes of a page, streamed pages are taken from the image cache if they're in it.
+pub async fn read_page(page: &Page) -> Result<Vec<u8>> {
+    match page {
+        Page::Url(url) => cache::fetch_uncached(url).await,
+        Page::File(path) => Ok(fs::read(path)?),
+    }
+}
+
+fn series_from(
+    id: MangaId,
+    manga: &Manga,
+    authors: &[AuthorId],
+    artists: &[AuthorId],
+    names: &HashMap<AuthorId, String>,
+    tags: &[Tag],
+) -> Series {
+    let languages = Preferences::load_or_default().languages;
+    let names_of = |ids: &[AuthorId]| ids.iter().filter_map(|id| names.get(id).cloned()).collect();
+    Series {
+        id,
+        title: manga.title_in(&languages),
+        writers: names_of(authors),
+        pencillers: names_of(artists),
+        genres: tags
+            .iter()
+            .map(|t| t.name.preferred_or_any(&languages))
+            .collect(),
+        original_language: manga.original_language.clone(),
+        cover: None,
+    }
+}
+
+/// The main cover of a manga, from the api.
+async fn cover(api: &mut Api, id: CoverId) -> Option<Page> {
+    if let Err(e) = api.cover_view(id).await {
+        log::warn!("Couldn't get the cover {id} ({e})");
+        return None;
+    }
+    api.cover_url(id).map(Page::Url)
+}
+
+/// The manga of the chapters, from the library if mangadex can't be reached.
+async fn series(api: &mut Api, id: MangaId) -> Result<Series> {
+    let library = LibraryManga::load(&id)?;
+    let library_cover = library
+        .as_ref()
+        .and_then(|m| m.cover_path())
+        .filter(|p| p.exists())
+        .map(Page::File);
+    let details = match api.manga_details(id).await {
+        Ok(details) => details,
+        Err(e) => {
+            let m = library.ok_or(e)?;
+            // the tags are kept in the api cache
+            let tags: Vec<Tag> = m
+                .manga
+                .tags
+                .iter()
+                .filter_map(|t| api.cache().get::<Tag>(t))
+                .collect();
+            let mut series =
+                series_from(id, &m.manga, &m.authors, &m.artists, &m.author_names, &tags);
+            series.cover = library_cover;
+            return Ok(series);
+        }
+    };
+
+    let mut names = library.map(|m| m.author_names).unwrap_or_default();
+    let people: Vec<AuthorId> = details
+        .authors
+        .iter()
+        .chain(&details.artists)
+        .copied()
+        .collect();
+    match api.authors(&people).await {
+        Ok(authors) => names.extend(authors.into_iter().map(|(id, a)| (id, a.name))),
+        Err(e) => log::warn!("Couldn't get the authors of {id} ({e})"),
+    }
+    let tags: Vec<Tag> = details.tags.into_iter().map(|(_, t)| t).collect();
+    let mut series = series_from(
+        id,
+        &details.manga,
+        &details.authors,
+        &details.artists,
+        &names,
+        &tags,
+    );
+    series.cover = match (library_cover, details.main_cover) {
+        (Some(c), _) => Some(c),
+        (None, Some(id)) => cover(api, id).await,
+        (None, None) => None,
+    };
+    Ok(series)
+}
+
+/// A chapter and the manga it's from, None if it isn't in the range.
+async fn chapter(
+    api: &mut Api,
+    id: ChapterId,
+    volume: Option<String>,
+    range: &ChapterRange,
+) -> Result<Option<(MangaId, ExportChapter)>> {
+    match LibraryChapter::find(&id) {
+        Ok(Some(c)) if !range.contains(&c.chapter) => return Ok(None),
+        Ok(Some(c)) => {
+            let pages = c.page_paths().into_iter().map(Page::File).collect();
+            return Ok(Some((
+                c.manga,
+                ExportChapter {
+                    id,
+                    volume: volume.or_else(|| c.chapter.volume.clone()),
+                    chapter: c.chapter,
+                    downloaded: Some(pages),
+                },
+            )));
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Couldn't look for {id} in the library ({e})"),
+    }
+
+    let chapter = api.chapter_view(id).await?;
+    if !range.contains(&chapter) {
+        return Ok(None);
+    }
+    let manga = api
+        .cache()
+        .get_linked::<MangaId>(&id, RelationshipKind::Manga)
+        .and_then(|m| m.first().copied())
+        .ok_or_else(|| Error::msg(format!("Chapter {id} has no manga")))?;
+    Ok(Some((
+        manga,
+        ExportChapter {
+            id,
+            volume: volume.or_else(|| chapter.volume.clone()),
+            chapter,
+            downloaded: None,
+        },
+    )))
+}
+
+/// The chapters to export with the volume they are sorted in, the downloaded ones if mangadex
+/// can't be reached.
+async fn chapter_ids(api: &mut Api, what: &Download) -> Result<Vec<(ChapterId, Option<String>)>> {
+    let e = match resolve_volumes(api, what).await {
+        Ok(ids) => return Ok(ids),
+        Err(e) => e,
+    };
+    let (manga, volume) = match what {
+        Download::Chapter { .. } => return Err(e),
+        Download::Volume { manga, volume } => (manga, Some(volume)),
+        Download::Manga { id } => (id, None),
+    };
+    let ids: Vec<(ChapterId, Option<String>)> = match LibraryManga::load(manga)? {
+        Some(m) => m
+            .sorted_chapters()?
+            .into_iter()
+            .filter(|(_, v)| volume.is_none_or(|volume| v.as_deref().unwrap_or("none") == volume))
+            .map(|(c, v)| (c.id, v))
+            .collect(),
+        None => Vec::new(),
+    };
+    if ids.is_empty() {
+        return Err(e);
+    }
+    Ok(ids)
+}
+
+fn chapter_name(title: &str, c: &ExportChapter) -> String {
+    let number = match (&c.chapter.chapter, &c.chapter.title) {
+        (Some(n), _) => format!("Ch. {n}"),
+        // extras are told apart by their title
+        (None, Some(t)) if !t.is_empty() => t.clone(),
+        (None, _) => "Oneshot".to_owned(),
+    };
+    match &c.volume {
+        Some(v) => format!("{title} - Vol. {v} {number}"),
+        None => format!("{title} - {number}"),
+    }
+}
+
+/// The chapters of an export in the range, split in books.
+pub async fn books(
+    api: &mut Api,
+    what: &Download,
+    range: &ChapterRange,
+    grouping: Grouping,
+) -> Result<(Series, Vec<Book>)> {
+    let mut chapters = Vec::new();
+    let mut manga = None;
+    for (id, volume) in chapter_ids(api, what).await? {
+        if let Some((m, c)) = chapter(api, id, volume, range).await? {
+            manga = Some(m);
+            chapters.push(c);
+        }
+    }
+    let manga = manga.ok_or_else(|| Error::msg(format!("Nothing to export for {what}")))?;
+    let series = series(api, manga).await?;
+
+    let grouping = match what {
+        Download::Chapter { .. } => Grouping::Chapter,
+        _ => grouping,
+    };
+    let books = split_books(&series.title, chapters, grouping)?;
+    Ok((series, books))
+}
+
+/// Split sorted chapters in books, and name them. Fails if two books would get the same file.
+fn split_books(title: &str, chapters: Vec<ExportChapter>, grouping: Grouping) -> Result<Vec<Book>> {
+    let mut books: Vec<Book> = Vec::new();
+    for c in chapters {
+        if let Some(book) = books.last_mut() {
+            // the chapters are sorted, the ones of a volume follow each other
+            let same_book = match grouping {
+                Grouping::Chapter => false,
+                Grouping::Volume => book.volume == c.volume,
+                Grouping::All => true,
+            };
+            if same_book {
+                if book.volume != c.volume {
+                    book.volume = None;
+                }
+                book.chapters.push(c);
+                continue;
+            }
+        }
+        books.push(Book {
+            name: String::new(),
+            volume: c.volume.clone(),
+            chapters: vec![c],
+        });
+    }
+
+    for book in &mut books {
+        let name = match (grouping, book.chapters.as_slice()) {
+            (_, [c]) if grouping != Grouping::Volume => chapter_name(title, c),
+            (Grouping::Volume, _) => match &book.volume {
+                Some(v) => format!("{title} - Vol. {v}"),
+                None => format!("{title} - No volume"),
+            },
+            (_, chapters) => {
+                let number = |c: &ExportChapter| c.chapter.chapter.clone().unwrap_or_default();
+                format!(
+                    "{title} - Ch. {}-{}",
+                    chapters.first().map(number).unwrap_or_default(),
+                    chapters.last().map(number).unwrap_or_default()
+                )
+            }
+        };
+        book.name = file_name(&name);
+    }
+    unique_names(&mut books)?;
+    Ok(books)
+}
+
+/// Number the books that have the same name, so that their files don't overwrite each other.
+fn unique_names(books: &mut [Book]) -> Result<()> {
+    let mut count: HashMap<String, usize> = HashMap::new();
+    for book in books.iter() {
+        *count.entry(book.name.clone()).or_default() += 1;
+    }
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for book in books.iter_mut().filter(|b| count[&b.name] > 1) {
+        let n = seen.entry(book.name.clone()).or_default();
+        *n += 1;
+        book.name = format!("{} ({n})", book.name);
+    }
+    // file systems of e-readers often ignore the case
+    let mut names = HashSet::new();
+    for book in books.iter() {
+        if !names.insert(book.name.to_lowercase()) {
+            return Err(Error::msg(format!(
+                "Two files would be named `{}`",
+                book.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Write a zip file through a temporary one, so that there is never a half written export.
+async fn write_zip<F, Fut>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(ZipWriter<BufWriter<fs::File>>) -> Fut,
+    Fut: std::future::Future<Output = Result<ZipWriter<BufWriter<fs::File>>>>,
+{
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = paths::temp_path(path);
+    let zip = ZipWriter::new(BufWriter::new(fs::File::create(&tmp)?));
+    let res = async { write(zip).await?.finish() }.await;
+    match res {
+        Ok(_) => Ok(fs::rename(tmp, path)?),
+        Err(e) => {
+            let _ = fs::remove_file(tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Path of the file of a book in dir.
+fn book_path(dir: &Path, book: &Book, extension: &str) -> PathBuf {
+    dir.join(format!("{}.{extension}", book.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::api::structs::test_chapter;
+
+    /// A chapter sorted in volume, with chapter as it is on mangadex.
+    fn export(volume: Option<&str>, chapter: Chapter) -> ExportChapter {
+        ExportChapter {
+            id: Uuid::new_v4().into(),
+            volume: volume.map(ToOwned::to_owned),
+            chapter,
+            downloaded: Some(Vec::new()),
+        }
+    }
+
+    fn names(books: &[Book]) -> Vec<&str> {
+        books.iter().map(|b| b.name.as_str()).collect()
+    }
+
+    #[test]
+    fn chapter_ranges() {
+        let range = |s: &str| s.parse::<ChapterRange>().unwrap();
+        let bounds = |s: &str| {
+            let r = range(s);
+            (r.from, r.to)
+        };
+        let some = |n: &str| Some(n.to_owned());
+        assert_eq!(bounds("12"), (some("12"), some("12")));
+        assert_eq!(bounds("1-20"), (some("1"), some("20")));
+        assert_eq!(bounds("5-"), (some("5"), None));
+        assert_eq!(bounds("-10"), (None, some("10")));
+        assert_eq!(bounds(" 2.5 - 3 "), (some("2.5"), some("3")));
+        for bad in ["abc", "1-x", "1.", ".5", "nan", "inf-"] {
+            assert!(bad.parse::<ChapterRange>().is_err(), "{bad}");
+        }
+
+        let c = |n| test_chapter(None, Some(n));
+        assert!(range("12").contains(&c("12")));
+        assert!(!range("12").contains(&c("12.5")));
+        assert!(range("5-").contains(&c("100")));
+        assert!(!range("5-").contains(&c("4")));
+        assert!(range("-10").contains(&c("10")));
+        assert!(!range("-10").contains(&c("10.1")));
+        // like the chapters are sorted: 10.5 < 10.10
+        assert!(!range("10.2-10.5").contains(&c("10.10")));
+        assert!(range("10.2-10.5").contains(&c("10.3")));
+        assert!(range("10.5-11").contains(&c("10.10")));
+        // too big for a float
+        assert!(range("16777217").contains(&c("16777217")));
+        assert!(!range("16777217").contains(&c("16777216")));
+        assert!(!range("5-").contains(&c("Extra")));
+        // oneshots only in a range without bounds
+        assert!(!range("-10").contains(&test_chapter(None, None)));
+        assert!(ChapterRange::default().contains(&test_chapter(None, None)));
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(file_name("Fate/Zero - Ch. 1"), "Fate_Zero - Ch. 1");
+        assert_eq!(file_name("a\\b\tc"), "a_b_c");
+        assert_eq!(file_name(".hack - Ch. 1"), "hack - Ch. 1");
+        assert_eq!(file_name("..."), "_");
+        assert_eq!(file_name(""), "_");
+    }
+
+    #[test]
+    fn xml_escapes() {
+        assert_eq!(
+            escape_xml(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_xml("a\u{1}b\n\u{7f}c"), "ab\nc");
+        assert_eq!(escape_xml("日本"), "日本");
+    }
+
+    #[test]
+    fn volumes_follow_the_sorted_volume() {
+        // the upload without a volume was sorted in vol. 2 by the chapter list
+        let chapters = vec![
+            export(Some("1"), test_chapter(Some("1"), Some("1"))),
+            export(Some("2"), test_chapter(Some("2"), Some("2"))),
+            export(Some("2"), test_chapter(None, Some("3"))),
+            export(Some("2"), test_chapter(Some("2"), Some("4"))),
+            export(None, test_chapter(None, Some("5"))),
+        ];
+        let books = split_books("X", chapters, Grouping::Volume).unwrap();
+        assert_eq!(names(&books), ["X - Vol. 1", "X - Vol. 2", "X - No volume"]);
+        assert_eq!(books[1].chapters.len(), 3);
+        assert_eq!(books[1].volume.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn chapter_and_range_names() {
+        let chapters = vec![
+            export(Some("1"), test_chapter(Some("1"), Some("1"))),
+            export(Some("2"), test_chapter(None, Some("2"))),
+            export(None, test_chapter(None, Some("3"))),
+        ];
+        let books = split_books("X", chapters, Grouping::Chapter).unwrap();
+        assert_eq!(
+            names(&books),
+            ["X - Vol. 1 Ch. 1", "X - Vol. 2 Ch. 2", "X - Ch. 3"]
+        );
+
+        let chapters = vec![
+            export(Some("1"), test_chapter(Some("1"), Some("1"))),
+            export(Some("2"), test_chapter(Some("2"), Some("2"))),
+        ];
+        let books = split_books("X", chapters, Grouping::All).unwrap();
+        assert_eq!(names(&books), ["X - Ch. 1-2"]);
+        // the chapters aren't all in the same volume
+        assert_eq!(books[0].volume, None);
+    }
+
+    #[test]
+    fn oneshots_get_their_own_files() {
+        let titled = |title: &str| {
+            let mut c = test_chapter(None, None);
+            c.title = Some(title.to_owned());
+            export(None, c)
+        };
+        let chapters = vec![
+            export(None, test_chapter(None, None)),
+            titled("Extra"),
+            export(None, test_chapter(None, None)),
+            titled(""),
+        ];
+        let books = split_books("X", chapters, Grouping::Chapter).unwrap();
+        assert_eq!(
+            names(&books),
+            [
+                "X - Oneshot (1)",
+                "X - Extra",
+                "X - Oneshot (2)",
+                "X - Oneshot (3)"
+            ]
+        );
+
+        // numbering can't give a name that's already taken
+        let chapters = vec![
+            export(None, test_chapter(None, None)),
+            export(None, test_chapter(None, None)),
+            titled("oneshot (1)"),
+        ];
+        assert!(split_books("X", chapters, Grouping::Chapter).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+// Minimal zip writer, the files are only stored (pages are already compressed images, and epub
+// wants its mimetype stored anyway).
+
+use std::io::Write;
+
+use anyhow::{Error, Result};
+use chrono::{Datelike, Local, Timelike};
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+/// 2.0, the lowest version that knows about directories (and what everything reads).
+const VERSION: u16 = 20;
+/// The names are utf-8.
+const FLAG_UTF8: u16 = 1 << 11;
+
+struct CentralEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+pub struct ZipWriter<W: Write> {
+    out: W,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+    time: u16,
+    date: u16,
+}
+
+fn u32_size(n: u64, what: &str) -> Result<u32> {
+    u32::try_from(n).map_err(|_| Error::msg(format!("{what} is too big for a zip file")))
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W) -> Self {
+        // every file gets the time at which the archive was started, in the MS-DOS format
+        let now = Local::now();
+        let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+        let date = (((now.year().clamp(1980, 2107) - 1980) << 9) as u16)
+            | (((now.month() << 5) | now.day()) as u16);
+        Self {
+            out,
+            offset: 0,
+            entries: Vec::new(),
+            time,
+            date,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Add a file, names are paths separated by '/'.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let size = u32_size(data.len() as u64, name)?;
+        let offset = u32_size(self.offset, "The archive")?;
+        let crc = crc32fast::hash(data);
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend(LOCAL_HEADER.to_le_bytes());
+        header.extend(VERSION.to_le_bytes());
+        header.extend(FLAG_UTF8.to_le_bytes());
+        // stored
+        header.extend(0u16.to_le_bytes());
+        header.extend(self.time.to_le_bytes());
+        header.extend(self.date.to_le_bytes());
+        header.extend(crc.to_le_bytes());
+        // compressed and uncompressed sizes
+        header.extend(size.to_le_bytes());
+        header.extend(size.to_le_bytes());
+        header.extend((name.len() as u16).to_le_bytes());
+        // no extra field
+        header.extend(0u16.to_le_bytes());
+        header.extend(name.as_bytes());
+        self.write(&header)?;
+        self.write(data)?;
+
+        self.entries.push(CentralEntry {
+            name: name.to_owned(),
+            crc,
+            size,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Write the central directory, the archive is unreadable without it.
+    pub fn finish(mut self) -> Result<W> {
+        let start = u32_size(self.offset, "The archive")?;
+        let count = u16::try_from(self.entries.len())
+            .map_err(|_| Error::msg("Too many files for a zip file"))?;
+
+        let mut directory = Vec::new();
+        for e in &self.entries {
+            directory.extend(CENTRAL_HEADER.to_le_bytes());
+            // made by and needed to extract
+            directory.extend(VERSION.to_le_bytes());
+            directory.extend(VERSION.to_le_bytes());
+            directory.extend(FLAG_UTF8.to_le_bytes());
+            directory.extend(0u16.to_le_bytes());
+            directory.extend(self.time.to_le_bytes());
+            directory.extend(self.date.to_le_bytes());
+            directory.extend(e.crc.to_le_bytes());
+            directory.extend(e.size.to_le_bytes());
+            directory.extend(e.size.to_le_bytes());
+            directory.extend((e.name.len() as u16).to_le_bytes());
+            // extra field, comment, disk, internal and external attributes
+            directory.extend(0u16.to_le_bytes());
+            directory.extend(0u16.to_le_bytes());
+            directory.extend(0u16.to_le_bytes());
+            directory.extend(0u16.to_le_bytes());
+            directory.extend(0u32.to_le_bytes());
+            directory.extend(e.offset.to_le_bytes());
+            directory.extend(e.name.as_bytes());
+        }
+        let size = u32_size(directory.len() as u64, "The zip directory")?;
+        self.write(&directory)?;
+
+        let mut end = Vec::with_capacity(22);
+        end.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        // this disk, and the one with the directory
+        end.extend(0u16.to_le_bytes());
+        end.extend(0u16.to_le_bytes());
+        end.extend(count.to_le_bytes());
+        end.extend(count.to_le_bytes());
+        end.extend(size.to_le_bytes());
+        end.extend(start.to_le_bytes());
+        // no comment
+        end.extend(0u16.to_le_bytes());
+        self.write(&end)?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    /// Read the files back, going through the central directory like unzip does.
+    pub fn read_back(zip: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(zip, end), END_OF_CENTRAL_DIRECTORY);
+        let count = u16_at(zip, end + 10) as usize;
+        let size = u32_at(zip, end + 12) as usize;
+        let start = u32_at(zip, end + 16) as usize;
+        assert_eq!(start + size, end);
+
+        let mut files = Vec::new();
+        let mut at = start;
+        for _ in 0..count {
+            assert_eq!(u32_at(zip, at), CENTRAL_HEADER);
+            assert_eq!(u16_at(zip, at + 8) & FLAG_UTF8, FLAG_UTF8);
+            let crc = u32_at(zip, at + 16);
+            let size = u32_at(zip, at + 20) as usize;
+            let name_len = u16_at(zip, at + 28) as usize;
+            let offset = u32_at(zip, at + 42) as usize;
+            let name = String::from_utf8(zip[at + 46..at + 46 + name_len].to_vec()).unwrap();
+            at += 46 + name_len;
+
+            // the local header says the same
+            assert_eq!(u32_at(zip, offset), LOCAL_HEADER);
+            assert_eq!(u16_at(zip, offset + 8), 0);
+            assert_eq!(u32_at(zip, offset + 14), crc);
+            assert_eq!(u32_at(zip, offset + 22) as usize, size);
+            assert_eq!(&zip[offset + 30..offset + 30 + name_len], name.as_bytes());
+            let data_start = offset + 30 + name_len;
+            let data = zip[data_start..data_start + size].to_vec();
+            assert_eq!(crc32fast::hash(&data), crc);
+            files.push((name, data));
+        }
+        assert_eq!(at, end);
+        files
+    }
+
+    #[test]
+    fn write_read_back() {
+        let files = [
+            ("mimetype", b"application/epub+zip".to_vec()),
+            ("pages/0001.png", vec![0, 1, 2, 255]),
+            ("empty", vec![]),
+            ("名前.txt", "ünïcödé".as_bytes().to_vec()),
+        ];
+        let mut zip = ZipWriter::new(Vec::new());
+        for (name, data) in &files {
+            zip.add(name, data).unwrap();
+        }
+        let bytes = zip.finish().unwrap();
+
+        let read: Vec<(String, Vec<u8>)> = read_back(&bytes);
+        let expected: Vec<(String, Vec<u8>)> = files
+            .iter()
+            .map(|(n, d)| (n.to_string(), d.clone()))
+            .collect();
+        assert_eq!(read, expected);
+    }
+}
@@ -0,0 +1,601 @@
+// Disk cache of the images (pages and covers). Images are keyed by the end of their url
+// (data/{hash}/{filename}, covers/{manga}/{filename}), so they're still found when the at-home
+// server giving them changes.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::{const_mutex, Mutex};
+use reqwest::{IntoUrl, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{api::structs::ids::ChapterId, paths};
+
+/// Serves every image of mangadex, used when the at-home server of a chapter isn't known.
+pub const UPLOADS_URL: &str = "https://uploads.mangadex.org";
+
+/// Size limit of the cache in bytes, see set_limit.
+static LIMIT: AtomicU64 = AtomicU64::new(512 * 1024 * 1024);
+/// Loaded on first use, shared by everything fetching images.
+static INDEX: Mutex<Option<Index>> = const_mutex(None);
+/// The index is written at most this often while running, and on exit (see flush).
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Set the size limit of the cache, the least recently used images are removed when it goes over.
+pub fn set_limit(bytes: u64) {
+    LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Entry {
+    size: u64,
+    /// Checked when reading the file, to never show a corrupted (or half written) image.
+    crc: u32,
+    accessed: DateTime<Utc>,
+}
+
+/// Pages of a chapter, to read it again without asking for its at-home server.
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedChapter {
+    hash: String,
+    data: Vec<String>,
+    data_saver: Vec<String>,
+}
+
+impl CachedChapter {
+    fn files(&self, data_saver: bool) -> &[String] {
+        if data_saver {
+            &self.data_saver
+        } else {
+            &self.data
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    chapters: HashMap<ChapterId, CachedChapter>,
+    #[serde(skip)]
+    size: u64,
+    /// Images or chapters changed since the last save.
+    #[serde(skip)]
+    dirty: bool,
+    /// Only access dates changed since the last save, they're written on exit.
+    #[serde(skip)]
+    touched: bool,
+    #[serde(skip)]
+    last_save: Option<Instant>,
+    /// Where the images and the index are.
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+fn cache_dir() -> PathBuf {
+    paths::cache_dir().join("images")
+}
+
+impl Index {
+    /// Load the index of the cache in dir. Images that aren't in it (because it was lost, or we
+    /// stopped between writing an image and saving the index) are added back.
+    fn load(dir: PathBuf) -> Self {
+        let path = dir.join("index.json");
+        let mut index: Self = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                log::error!("Couldn't read the image cache index ({e}), starting over");
+                Self::default()
+            }),
+            Err(e) => {
+                if e.kind() != ErrorKind::NotFound {
+                    log::error!("Couldn't load the image cache index ({e})");
+                }
+                Self::default()
+            }
+        };
+        index.dir = dir;
+        index.size = index.entries.values().map(|e| e.size).sum();
+        index.rescan();
+        index.evict_to(LIMIT.load(Ordering::Relaxed));
+        index
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join("index.json")
+    }
+
+    fn image_path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    /// Index the images of the directory that aren't, and remove the temporary files of the writes
+    /// that didn't finish.
+    fn rescan(&mut self) {
+        let mut found = 0;
+        let mut dirs = vec![(self.dir.clone(), 0)];
+        while let Some((dir, depth)) = dirs.pop() {
+            let files = match fs::read_dir(&dir) {
+                Ok(files) => files,
+                Err(e) => {
+                    if e.kind() != ErrorKind::NotFound {
+                        log::warn!("Couldn't look through {} ({e})", dir.display());
+                    }
+                    continue;
+                }
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                if file.file_type().is_ok_and(|t| t.is_dir()) {
+                    // keys have three segments, so images are two directories deep
+                    if depth < 2 {
+                        dirs.push((path, depth + 1));
+                    }
+                    continue;
+                }
+                if path.extension().is_some_and(|e| e == "tmp") {
+                    if let Err(e) = fs::remove_file(&path) {
+                        log::warn!("Couldn't remove {} ({e})", path.display());
+                    }
+                    continue;
+                }
+                let key = match path.strip_prefix(&self.dir).ok().and_then(|p| p.to_str()) {
+                    Some(key) if depth == 2 && !self.entries.contains_key(key) => key.to_owned(),
+                    _ => continue,
+                };
+                // images are written whole (see paths::write_atomic), so they can be trusted
+                let bytes = match fs::read(&path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        log::warn!("Couldn't read {} ({e})", path.display());
+                        continue;
+                    }
+                };
+                let accessed = file
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .map(DateTime::from)
+                    .unwrap_or_else(|_| Utc::now());
+                self.size += bytes.len() as u64;
+                self.entries.insert(
+                    key,
+                    Entry {
+                        size: bytes.len() as u64,
+                        crc: crc32fast::hash(&bytes),
+                        accessed,
+                    },
+                );
+                found += 1;
+            }
+        }
+        if found > 0 {
+            log::info!("Added {found} images missing from the image cache index");
+            self.dirty = true;
+        }
+    }
+
+    fn save(&mut self) {
+        if !self.dirty && !self.touched {
+            return;
+        }
+        let res = serde_json::to_vec(self)
+            .map_err(Error::from)
+            .and_then(|bytes| paths::write_atomic(&self.index_path(), &bytes).map_err(Error::from));
+        if let Err(e) = res {
+            log::error!("Couldn't save the image cache index ({e})");
+        }
+        self.dirty = false;
+        self.touched = false;
+        self.last_save = Some(Instant::now());
+    }
+
+    /// Save if something other than access dates changed, and the last save is old enough.
+    fn save_if_due(&mut self) {
+        if self.dirty && self.last_save.is_none_or(|t| t.elapsed() > SAVE_INTERVAL) {
+            self.save();
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(e) = self.entries.get_mut(key) {
+            e.accessed = Utc::now();
+            self.touched = true;
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.size -= entry.size;
+            self.dirty = true;
+        }
+        if let Err(e) = fs::remove_file(self.image_path(key)) {
+            if e.kind() != ErrorKind::NotFound {
+                log::warn!("Couldn't remove {key} from the image cache ({e})");
+            }
+        }
+    }
+
+    /// Remove the least recently used images until the cache fits in size.
+    fn evict_to(&mut self, size: u64) {
+        if self.size <= size {
+            return;
+        }
+        let mut lru: Vec<(DateTime<Utc>, String)> = self
+            .entries
+            .iter()
+            .map(|(k, e)| (e.accessed, k.clone()))
+            .collect();
+        lru.sort_unstable();
+
+        let mut evicted = 0;
+        for (_, key) in lru {
+            if self.size <= size {
+                break;
+            }
+            self.remove(&key);
+            evicted += 1;
+        }
+        log::debug!(
+            "Evicted {evicted} images, the cache is now {} bytes",
+            self.size
+        );
+
+        // forget the chapters that don't have a single page left
+        let entries = &self.entries;
+        self.chapters.retain(|_, c| {
+            let any_cached = |data_saver| {
+                c.files(data_saver)
+                    .iter()
+                    .any(|f| entries.contains_key(&page_key(&c.hash, f, data_saver)))
+            };
+            any_cached(false) || any_cached(true)
+        });
+    }
+}
+
+/// Save the index if it changed and wasn't saved for a while, the app calls it from a timer.
+pub fn save_if_due() {
+    if let Some(index) = INDEX.lock().as_mut() {
+        index.save_if_due();
+    }
+}
+
+/// Save the index if anything changed, access dates included. Called before exiting.
+pub fn flush() {
+    if let Some(index) = INDEX.lock().as_mut() {
+        index.save();
+    }
+}
+
+fn with_index<R>(cache: &Mutex<Option<Index>>, f: impl FnOnce(&mut Index) -> R) -> R {
+    let mut index = cache.lock();
+    f(index.get_or_insert_with(|| Index::load(cache_dir())))
+}
+
+/// Key of an image from its url, the last three segments of its path.
+pub fn key(url: &Url) -> Option<String> {
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    if segments.len() < 3 || segments.iter().any(|s| *s == "." || *s == "..") {
+        return None;
+    }
+    Some(segments[segments.len() - 3..].join("/"))
+}
+
+fn page_key(hash: &str, file: &str, data_saver: bool) -> String {
+    let quality = if data_saver { "data-saver" } else { "data" };
+    format!("{quality}/{hash}/{file}")
+}
+
+/// Get a cached image, None if it isn't cached or is corrupted (it's removed then).
+pub fn get(key: &str) -> Option<Vec<u8>> {
+    get_in(&INDEX, key)
+}
+fn get_in(cache: &Mutex<Option<Index>>, key: &str) -> Option<Vec<u8>> {
+    let (entry, path) = with_index(cache, |index| {
+        Some((index.entries.get(key).cloned()?, index.image_path(key)))
+    })?;
+    let bytes = match fs::read(path) {
+        Ok(bytes) if bytes.len() as u64 == entry.size && crc32fast::hash(&bytes) == entry.crc => {
+            Some(bytes)
+        }
+        Ok(_) => {
+            log::warn!("{key} is corrupted in the image cache, removing it");
+            None
+        }
+        Err(e) => {
+            log::warn!("Couldn't read {key} from the image cache ({e})");
+            None
+        }
+    };
+
+    with_index(cache, |index| {
+        if bytes.is_some() {
+            index.touch(key);
+        } else {
+            index.remove(key);
+            index.save_if_due();
+        }
+    });
+    bytes
+}
+
+/// Path to a cached image. Only its size is checked, its content is left to whoever reads it.
+pub fn path(key: &str) -> Option<PathBuf> {
+    path_in(&INDEX, key)
+}
+fn path_in(cache: &Mutex<Option<Index>>, key: &str) -> Option<PathBuf> {
+    with_index(cache, |index| {
+        let size = index.entries.get(key)?.size;
+        let path = index.image_path(key);
+        match fs::metadata(&path) {
+            Ok(m) if m.len() == size => {
+                index.touch(key);
+                Some(path)
+            }
+            _ => {
+                log::warn!("{key} is missing or truncated in the image cache, removing it");
+                index.remove(key);
+                index.save_if_due();
+                None
+            }
+        }
+    })
+}
+
+/// Add an image to the cache.
+pub fn put(key: &str, bytes: &[u8]) -> Result<()> {
+    put_in(&INDEX, key, bytes)
+}
+fn put_in(cache: &Mutex<Option<Index>>, key: &str, bytes: &[u8]) -> Result<()> {
+    paths::write_atomic(&with_index(cache, |index| index.image_path(key)), bytes)?;
+    with_index(cache, |index| {
+        if let Some(old) = index.entries.remove(key) {
+            index.size -= old.size;
+        }
+        index.entries.insert(
+            key.to_owned(),
+            Entry {
+                size: bytes.len() as u64,
+                crc: crc32fast::hash(bytes),
+                accessed: Utc::now(),
+            },
+        );
+        index.size += bytes.len() as u64;
+        index.dirty = true;
+        index.evict_to(LIMIT.load(Ordering::Relaxed));
+        index.save_if_due();
+    });
+    Ok(())
+}
+
+async fn download(url: Url) -> Result<Vec<u8>> {
+    let bytes = reqwest::get(url).await?.error_for_status()?.bytes().await?;
+    Ok(bytes.to_vec())
+}
+
+/// Get an image from the cache, or download it (and cache it).
+pub async fn fetch(url: impl IntoUrl) -> Result<Vec<u8>> {
+    let url = url.into_url()?;
+    let key = key(&url);
+    if let Some(bytes) = key.as_deref().and_then(get) {
+        log::trace!("image cache hit {url}");
+        return Ok(bytes);
+    }
+
+    let bytes = download(url.clone()).await?;
+    match key {
+        Some(key) => {
+            if let Err(e) = put(&key, &bytes) {
+                log::error!("Couldn't cache {url} ({e})");
+            }
+        }
+        None => log::warn!("{url} can't be cached"),
+    }
+    Ok(bytes)
+}
+
+/// Like fetch, but what is downloaded isn't cached (downloads of whole mangas would push
+/// everything else out of the cache).
+pub async fn fetch_uncached(url: impl IntoUrl) -> Result<Vec<u8>> {
+    let url = url.into_url()?;
+    match key(&url).as_deref().and_then(get) {
+        Some(bytes) => Ok(bytes),
+        None => download(url).await,
+    }
+}
+
+/// Remember the pages of a chapter, so that it can be read from the cache alone.
+pub fn remember_chapter(id: ChapterId, hash: String, data: Vec<String>, data_saver: Vec<String>) {
+    remember_chapter_in(&INDEX, id, hash, data, data_saver)
+}
+fn remember_chapter_in(
+    cache: &Mutex<Option<Index>>,
+    id: ChapterId,
+    hash: String,
+    data: Vec<String>,
+    data_saver: Vec<String>,
+) {
+    with_index(cache, |index| {
+        index.chapters.insert(
+            id,
+            CachedChapter {
+                hash,
+                data,
+                data_saver,
+            },
+        );
+        index.dirty = true;
+        index.save_if_due();
+    });
+}
+
+/// The hash and files of a chapter, if all of its pages are cached.
+pub fn cached_chapter(id: &ChapterId, data_saver: bool) -> Option<(String, Vec<String>)> {
+    cached_chapter_in(&INDEX, id, data_saver)
+}
+fn cached_chapter_in(
+    cache: &Mutex<Option<Index>>,
+    id: &ChapterId,
+    data_saver: bool,
+) -> Option<(String, Vec<String>)> {
+    with_index(cache, |index| {
+        let chapter = index.chapters.get(id)?;
+        let files = chapter.files(data_saver);
+        let complete = !files.is_empty()
+            && files.iter().all(|f| {
+                index
+                    .entries
+                    .contains_key(&page_key(&chapter.hash, f, data_saver))
+            });
+        complete.then(|| (chapter.hash.clone(), files.to_vec()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn temp_cache() -> (PathBuf, Mutex<Option<Index>>) {
+        let dir = std::env::temp_dir().join(format!("mngdx-test-images-{}", Uuid::new_v4()));
+        let cache = Mutex::new(Some(Index::load(dir.clone())));
+        (dir, cache)
+    }
+
+    fn set_accessed(cache: &Mutex<Option<Index>>, key: &str, secs_ago: i64) {
+        with_index(cache, |index| {
+            index.entries.get_mut(key).unwrap().accessed =
+                Utc::now() - chrono::Duration::seconds(secs_ago);
+        });
+    }
+
+    #[test]
+    fn keys() {
+        let key = |url: &str| key(&Url::parse(url).unwrap());
+        assert_eq!(
+            key("https://uploads.mangadex.org/data/abc/1.png").as_deref(),
+            Some("data/abc/1.png")
+        );
+        assert_eq!(
+            key("https://srv.example/token/data-saver/abc/1.jpg").as_deref(),
+            Some("data-saver/abc/1.jpg")
+        );
+        assert_eq!(key("https://uploads.mangadex.org/covers/abc.jpg"), None);
+        assert_eq!(key("https://uploads.mangadex.org/"), None);
+        // never out of the cache directory
+        assert_eq!(key("https://uploads.mangadex.org/data/../../passwd"), None);
+        assert_eq!(key("https://uploads.mangadex.org/data/%2e%2e/passwd"), None);
+    }
+
+    #[test]
+    fn least_recently_used_are_evicted() {
+        let (dir, cache) = temp_cache();
+        let id: ChapterId = Uuid::new_v4().into();
+        remember_chapter_in(
+            &cache,
+            id,
+            "old".to_owned(),
+            vec!["1.png".to_owned()],
+            vec![],
+        );
+        for (key, secs_ago) in [
+            ("data/old/1.png", 30),
+            ("data/a/1.png", 20),
+            ("data/a/2.png", 10),
+        ] {
+            put_in(&cache, key, &[0; 10]).unwrap();
+            set_accessed(&cache, key, secs_ago);
+        }
+
+        with_index(&cache, |index| index.evict_to(20));
+        assert!(get_in(&cache, "data/old/1.png").is_none());
+        assert!(!dir.join("data/old/1.png").exists());
+        // the chapter has no page left
+        assert!(with_index(&cache, |index| index.chapters.is_empty()));
+
+        // reading an image makes it the most recent
+        assert!(get_in(&cache, "data/a/1.png").is_some());
+        with_index(&cache, |index| index.evict_to(10));
+        assert!(path_in(&cache, "data/a/1.png").is_some());
+        assert!(path_in(&cache, "data/a/2.png").is_none());
+        assert_eq!(with_index(&cache, |index| index.size), 10);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted_images_are_removed() {
+        let (dir, cache) = temp_cache();
+        put_in(&cache, "data/a/1.png", b"image").unwrap();
+        put_in(&cache, "data/a/2.png", b"image").unwrap();
+        assert_eq!(get_in(&cache, "data/a/1.png").unwrap(), b"image");
+
+        // same size, different content
+        fs::write(dir.join("data/a/1.png"), b"imagf").unwrap();
+        assert!(get_in(&cache, "data/a/1.png").is_none());
+        assert!(!dir.join("data/a/1.png").exists());
+        assert!(with_index(&cache, |index| !index
+            .entries
+            .contains_key("data/a/1.png")));
+
+        fs::write(dir.join("data/a/2.png"), b"ima").unwrap();
+        assert!(path_in(&cache, "data/a/2.png").is_none());
+        assert_eq!(with_index(&cache, |index| index.size), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn chapters_are_cached_when_complete() {
+        let (dir, cache) = temp_cache();
+        let id: ChapterId = Uuid::new_v4().into();
+        let files = vec!["1.png".to_owned(), "2.png".to_owned()];
+        remember_chapter_in(&cache, id, "h".to_owned(), files.clone(), vec![]);
+        assert!(cached_chapter_in(&cache, &id, false).is_none());
+
+        put_in(&cache, "data/h/1.png", b"1").unwrap();
+        assert!(cached_chapter_in(&cache, &id, false).is_none());
+        put_in(&cache, "data/h/2.png", b"2").unwrap();
+        assert_eq!(
+            cached_chapter_in(&cache, &id, false),
+            Some(("h".to_owned(), files))
+        );
+        // no data saver pages at all
+        assert!(cached_chapter_in(&cache, &id, true).is_none());
+        assert!(cached_chapter_in(&cache, &Uuid::new_v4().into(), false).is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unindexed_images_are_found_again() {
+        let (dir, cache) = temp_cache();
+        put_in(&cache, "data/a/1.png", b"saved").unwrap();
+        with_index(&cache, |index| index.save());
+        // written, but the index wasn't saved after
+        put_in(&cache, "data/a/2.png", b"lost").unwrap();
+        fs::write(dir.join("data/a/3.png.1234.tmp"), b"half").unwrap();
+
+        let cache = Mutex::new(Some(Index::load(dir.clone())));
+        assert_eq!(get_in(&cache, "data/a/1.png").unwrap(), b"saved");
+        assert_eq!(get_in(&cache, "data/a/2.png").unwrap(), b"lost");
+        assert!(!dir.join("data/a/3.png.1234.tmp").exists());
+        assert_eq!(with_index(&cache, |index| index.size), 9);
+
+        // a broken index is rebuilt from the images
+        fs::write(dir.join("index.json"), b"{").unwrap();
+        let cache = Mutex::new(Some(Index::load(dir.clone())));
+        assert_eq!(with_index(&cache, |index| index.entries.len()), 2);
+        assert_eq!(get_in(&cache, "data/a/2.png").unwrap(), b"lost");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
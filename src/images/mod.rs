@@ -12,6 +12,8 @@ use tui::layout::Rect;
 
 use crate::{consts::IMAGE_SLOTS, app::time::{timer_start, timer_stop}};
 
+pub mod cache;
+
 // this is the same as libc::winsize, but I didn't want libc to be exposed (and this implements
 // debug)
 #[derive(Debug)]
@@ -244,8 +246,9 @@ impl ImageManager {
         Ok(())
     }
 
+    /// Get an image from the disk cache, or download it.
     pub async fn image_from_url(url: impl IntoUrl) -> Result<DynamicImage> {
-        let bytes = reqwest::get(url).await?.bytes().await?;
+        let bytes = cache::fetch(url).await?;
         Ok(image::load_from_memory(&bytes)?)
     }
 
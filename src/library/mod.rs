@@ -0,0 +1,574 @@
+// Chapters downloaded to be read offline. The library is in $XDG_DATA_HOME/mngdx/library, with a
+// directory per manga (manga.json and the cover) and in it a directory per chapter (chapter.json
+// and the pages, named by their position). chapter.json is written last, a chapter without it
+// isn't fully downloaded.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    future::Future,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub mod queue;
+
+use crate::{
+    api::{
+        structs::{
+            chapters::ChapterList,
+            ids::{AuthorId, ChapterId, GroupId, MangaId},
+            json::data::RelationshipKind,
+            Chapter, Manga,
+        },
+        Api,
+    },
+    app::preferences::Preferences,
+    images::cache,
+    paths,
+};
+
+/// What to download.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Download {
+    Chapter {
+        id: ChapterId,
+    },
+    /// A volume by name, "none" for the chapters without a volume.
+    Volume {
+        manga: MangaId,
+        volume: String,
+    },
+    Manga {
+        id: MangaId,
+    },
+}
+
+impl Display for Download {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Download::Chapter { id } => write!(f, "chapter {id}"),
+            Download::Volume { manga, volume } => write!(f, "volume {volume} of {manga}"),
+            Download::Manga { id } => write!(f, "manga {id}"),
+        }
+    }
+}
+
+/// How far the download of a chapter is.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Progress {
+    pub pages: usize,
+    pub total_pages: usize,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LibraryManga {
+    pub id: MangaId,
+    pub manga: Manga,
+    pub authors: Vec<AuthorId>,
+    pub artists: Vec<AuthorId>,
+    /// Names of the authors and artists, to export chapters offline.
+    #[serde(default)]
+    pub author_names: HashMap<AuthorId, String>,
+    /// File name of the cover, in the directory of the manga.
+    pub cover: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LibraryChapter {
+    pub id: ChapterId,
+    pub manga: MangaId,
+    pub chapter: Chapter,
+    pub groups: Vec<GroupId>,
+    pub data_saver: bool,
+    /// File names of the pages, in order.
+    pub pages: Vec<String>,
+    pub downloaded_at: DateTime<Utc>,
+}
+
+pub fn library_dir() -> PathBuf {
+    paths::data_dir().join("library")
+}
+
+fn manga_dir(id: &MangaId) -> PathBuf {
+    library_dir().join(id.to_string())
+}
+
+fn chapter_dir(manga: &MangaId, id: &ChapterId) -> PathBuf {
+    manga_dir(manga).join(id.to_string())
+}
+
+/// Read a json file, None if it doesn't exist.
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Extension of a file name, png if it has none.
+fn extension(file: &str) -> &str {
+    Path::new(file)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("png")
+}
+
+impl LibraryManga {
+    pub fn load(id: &MangaId) -> Result<Option<Self>> {
+        read_json(&manga_dir(id).join("manga.json"))
+    }
+    fn save(&self) -> Result<()> {
+        paths::write_atomic(
+            &manga_dir(&self.id).join("manga.json"),
+            &serde_json::to_vec_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn cover_path(&self) -> Option<PathBuf> {
+        self.cover.as_ref().map(|c| manga_dir(&self.id).join(c))
+    }
+
+    /// The downloaded chapters of this manga, sorted.
+    pub fn chapters(&self) -> Result<Vec<LibraryChapter>> {
+        Ok(self
+            .sorted_chapters()?
+            .into_iter()
+            .map(|(c, _)| c)
+            .collect())
+    }
+
+    /// The downloaded chapters sorted, with the volume each one is sorted in (see ChapterList).
+    pub fn sorted_chapters(&self) -> Result<Vec<(LibraryChapter, Option<String>)>> {
+        Ok(sort_chapters(
+            read_chapters(&manga_dir(&self.id))?,
+            self.manga.chapter_numbers_reset_on_new_volume,
+        ))
+    }
+
+    /// The downloaded chapters as a chapter list, to read them like the ones from the api.
+    pub fn chapter_list(&self) -> Result<ChapterList> {
+        Ok(ChapterList::new(
+            self.chapters()?
+                .into_iter()
+                .map(|c| (c.id, c.chapter, c.groups)),
+            self.manga.chapter_numbers_reset_on_new_volume,
+        ))
+    }
+}
+
+impl LibraryChapter {
+    pub fn load(manga: &MangaId, id: &ChapterId) -> Result<Option<Self>> {
+        read_json(&chapter_dir(manga, id).join("chapter.json"))
+    }
+    fn save(&self) -> Result<()> {
+        paths::write_atomic(
+            &self.dir().join("chapter.json"),
+            &serde_json::to_vec_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Look for a chapter in the whole library.
+    pub fn find(id: &ChapterId) -> Result<Option<Self>> {
+        for manga in mangas()? {
+            if let Some(c) = Self::load(&manga.id, id)? {
+                return Ok(Some(c));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        chapter_dir(&self.manga, &self.id)
+    }
+
+    /// Whether all the pages are there in that quality.
+    fn is_complete(&self, data_saver: bool) -> bool {
+        self.data_saver == data_saver && self.page_paths().iter().all(|p| p.exists())
+    }
+
+    pub fn page_paths(&self) -> Vec<PathBuf> {
+        let dir = self.dir();
+        self.pages.iter().map(|p| dir.join(p)).collect()
+    }
+}
+
+/// The fully downloaded chapters in the directory of a manga.
+fn read_chapters(manga_dir: &Path) -> Result<Vec<LibraryChapter>> {
+    let mut chapters = Vec::new();
+    for entry in fs::read_dir(manga_dir)? {
+        let dir = entry?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        if let Some(c) = read_json::<LibraryChapter>(&dir.join("chapter.json"))? {
+            chapters.push(c);
+        }
+    }
+    Ok(chapters)
+}
+
+/// Sort chapters like a ChapterList, with the volume each one is sorted in.
+fn sort_chapters(
+    mut chapters: Vec<LibraryChapter>,
+    resets_on_volume: bool,
+) -> Vec<(LibraryChapter, Option<String>)> {
+    let list = ChapterList::new(
+        chapters
+            .iter()
+            .map(|c| (c.id, c.chapter.clone(), c.groups.clone())),
+        resets_on_volume,
+    );
+    let mut sorted = Vec::with_capacity(chapters.len());
+    for e in list.entries() {
+        if let Some(i) = chapters.iter().position(|c| c.id == e.id) {
+            sorted.push((chapters.swap_remove(i), e.chapter.volume.clone()));
+        }
+    }
+    sorted
+}
+
+/// All the downloaded mangas.
+pub fn mangas() -> Result<Vec<LibraryManga>> {
+    let entries = match fs::read_dir(library_dir()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut mangas = Vec::new();
+    for entry in entries {
+        let dir = entry?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        if let Some(m) = read_json::<LibraryManga>(&dir.join("manga.json"))? {
+            mangas.push(m);
+        }
+    }
+    Ok(mangas)
+}
+
+/// The chapters to download, only one upload per chapter is picked (from the translation
+/// preferences).
+pub async fn resolve(api: &mut Api, download: &Download) -> Result<Vec<ChapterId>> {
+    Ok(resolve_volumes(api, download)
+        .await?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect())
+}
+
+/// Like resolve, with the volume each chapter is sorted in: uploads without a volume can take the
+/// one of the other uploads of their chapter (see ChapterList). None for a single chapter.
+pub async fn resolve_volumes(
+    api: &mut Api,
+    download: &Download,
+) -> Result<Vec<(ChapterId, Option<String>)>> {
+    let (manga, volume) = match download {
+        Download::Chapter { id } => return Ok(vec![(*id, None)]),
+        Download::Volume { manga, volume } => (manga, Some(volume)),
+        Download::Manga { id } => (id, None),
+    };
+    let translations = Preferences::load_or_default().translations_for(manga);
+    let chapters = api
+        .manga_chapter_list(*manga)
+        .await?
+        .deduplicated(&translations);
+
+    let ids: Vec<(ChapterId, Option<String>)> = chapters
+        .entries()
+        .iter()
+        .filter(|e| volume.is_none_or(|v| e.volume().unwrap_or("none") == v))
+        .map(|e| (e.id, e.chapter.volume.clone()))
+        .collect();
+    if ids.is_empty() {
+        return Err(Error::msg(format!("Nothing to download for {download}")));
+    }
+    Ok(ids)
+}
+
+/// Save (or update) the metadata and cover of a manga.
+async fn download_manga_info(api: &mut Api, id: MangaId) -> Result<LibraryManga> {
+    let details = api.manga_details(id).await?;
+    let old = LibraryManga::load(&id)?;
+
+    let mut author_names = old
+        .as_ref()
+        .map(|m| m.author_names.clone())
+        .unwrap_or_default();
+    let people: Vec<AuthorId> = details
+        .authors
+        .iter()
+        .chain(&details.artists)
+        .copied()
+        .collect();
+    match api.authors(&people).await {
+        Ok(authors) => author_names.extend(authors.into_iter().map(|(id, a)| (id, a.name))),
+        Err(e) => log::warn!("Couldn't get the authors of {id} ({e})"),
+    }
+
+    let mut cover = old.and_then(|m| m.cover);
+    if let Some(cover_id) = details.main_cover {
+        let res = async {
+            let file_name = api.cover_view(cover_id).await?.file_name;
+            let url = api
+                .cover_url(cover_id)
+                .ok_or_else(|| Error::msg("cover without a manga"))?;
+            let name = format!("cover.{}", extension(&file_name));
+            paths::write_atomic(
+                &manga_dir(&id).join(&name),
+                &cache::fetch_uncached(url).await?,
+            )?;
+            Ok::<_, Error>(name)
+        };
+        match res.await {
+            Ok(name) => cover = Some(name),
+            Err(e) => log::warn!("Couldn't download the cover of {id} ({e})"),
+        }
+    }
+
+    let manga = LibraryManga {
+        id,
+        manga: details.manga,
+        authors: details.authors,
+        artists: details.artists,
+        author_names,
+        cover,
+        updated_at: Utc::now(),
+    };
+    manga.save()?;
+    Ok(manga)
+}
+
+/// Get the directory of a chapter ready for pages in a quality. The quality is written before the
+/// first page, pages in another one (from an interrupted download or a finished one) would have the
+/// same names, so they are all removed.
+fn prepare_chapter_dir(dir: &Path, data_saver: bool) -> Result<()> {
+    let marker = dir.join("quality");
+    let quality = if data_saver { "data-saver" } else { "data" };
+    if dir.exists() && fs::read_to_string(&marker).ok().as_deref() != Some(quality) {
+        fs::remove_dir_all(dir)?;
+    }
+    paths::write_atomic(&marker, quality.as_bytes())?;
+    Ok(())
+}
+
+/// Download the pages to dir with fetch, returns their file names.
+async fn download_pages<F, Fut>(
+    dir: &Path,
+    urls: Vec<String>,
+    mut fetch: F,
+    mut progress: impl FnMut(Progress),
+) -> Result<Vec<String>>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>>>,
+{
+    let mut done = Progress {
+        total_pages: urls.len(),
+        ..Default::default()
+    };
+    let mut pages = Vec::with_capacity(urls.len());
+    for (i, url) in urls.into_iter().enumerate() {
+        let name = format!("{:03}.{}", i + 1, extension(&url));
+        let path = dir.join(&name);
+        // pages from an interrupted download are kept
+        done.bytes += match fs::metadata(&path) {
+            Ok(m) => m.len(),
+            Err(_) => {
+                let bytes = fetch(url).await?;
+                paths::write_atomic(&path, &bytes)?;
+                bytes.len() as u64
+            }
+        };
+        done.pages += 1;
+        progress(done);
+        pages.push(name);
+    }
+    Ok(pages)
+}
+
+/// Download a chapter (pages already downloaded are kept), nothing is done if it already is in
+/// that quality. progress is called after each page.
+pub async fn download_chapter(
+    api: &mut Api,
+    id: ChapterId,
+    data_saver: bool,
+    progress: impl FnMut(Progress),
+) -> Result<LibraryChapter> {
+    // before anything else, so that it works offline
+    if let Some(c) = LibraryChapter::find(&id)? {
+        if c.is_complete(data_saver) {
+            return Ok(c);
+        }
+    }
+
+    let chapter = api.chapter_view(id).await?;
+    let manga = api
+        .cache()
+        .get_linked::<MangaId>(&id, RelationshipKind::Manga)
+        .and_then(|m| m.first().copied())
+        .ok_or_else(|| Error::msg(format!("Chapter {id} has no manga")))?;
+    if LibraryManga::load(&manga)?.is_none() {
+        download_manga_info(api, manga).await?;
+    }
+
+    if data_saver {
+        api.enable_data_saver();
+    } else {
+        api.disable_data_saver();
+    }
+    let urls = api.chapter_pages(id).await?;
+    let dir = chapter_dir(&manga, &id);
+    prepare_chapter_dir(&dir, data_saver)?;
+    let pages = download_pages(&dir, urls, cache::fetch_uncached, progress).await?;
+
+    let groups = api
+        .cache()
+        .get_linked(&id, RelationshipKind::ScanlationGroup)
+        .unwrap_or_default();
+    let chapter = LibraryChapter {
+        id,
+        manga,
+        chapter,
+        groups,
+        data_saver,
+        pages,
+        downloaded_at: Utc::now(),
+    };
+    chapter.save()?;
+    log::info!("Downloaded chapter {id} ({} pages)", chapter.pages.len());
+    Ok(chapter)
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::api::structs::test_chapter;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("mngdx-test-library-{}", Uuid::new_v4()))
+    }
+
+    fn chapter(manga: MangaId, volume: Option<&str>, number: &str) -> LibraryChapter {
+        LibraryChapter {
+            id: Uuid::new_v4().into(),
+            manga,
+            chapter: test_chapter(volume, Some(number)),
+            groups: Vec::new(),
+            data_saver: false,
+            pages: vec!["001.png".to_owned()],
+            downloaded_at: Utc::now(),
+        }
+    }
+
+    /// Downloads the pages of urls to dir, the pages are their url. Returns the names of the pages
+    /// and the urls that were fetched.
+    fn download(dir: &Path, urls: &[&str]) -> (Vec<String>, Vec<String>) {
+        let mut fetched = Vec::new();
+        let mut last = Progress::default();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let pages = rt
+            .block_on(download_pages(
+                dir,
+                urls.iter().map(|u| u.to_string()).collect(),
+                |url| {
+                    fetched.push(url.clone());
+                    async move { Ok(url.into_bytes()) }
+                },
+                |p| last = p,
+            ))
+            .unwrap();
+        assert_eq!((last.pages, last.total_pages), (urls.len(), urls.len()));
+        (pages, fetched)
+    }
+
+    #[test]
+    fn interrupted_downloads_resume() {
+        let dir = temp_dir();
+        prepare_chapter_dir(&dir, false).unwrap();
+        let (pages, fetched) = download(&dir, &["a/1.png", "a/2.jpg"]);
+        assert_eq!(pages, ["001.png", "002.jpg"]);
+        assert_eq!(fetched.len(), 2);
+
+        // stopped before the second page
+        fs::remove_file(dir.join("002.jpg")).unwrap();
+        prepare_chapter_dir(&dir, false).unwrap();
+        let (pages, fetched) = download(&dir, &["a/1.png", "a/2.jpg"]);
+        assert_eq!(pages, ["001.png", "002.jpg"]);
+        assert_eq!(fetched, ["a/2.jpg"]);
+        assert_eq!(fs::read(dir.join("001.png")).unwrap(), b"a/1.png");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn other_qualities_are_wiped() {
+        let dir = temp_dir();
+        prepare_chapter_dir(&dir, false).unwrap();
+        download(&dir, &["data/1.png"]);
+
+        prepare_chapter_dir(&dir, true).unwrap();
+        assert!(!dir.join("001.png").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("quality")).unwrap(),
+            "data-saver"
+        );
+        let (_, fetched) = download(&dir, &["data-saver/1.png"]);
+        assert_eq!(fetched, ["data-saver/1.png"]);
+        assert_eq!(fs::read(dir.join("001.png")).unwrap(), b"data-saver/1.png");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn chapters_are_sorted() {
+        let dir = temp_dir();
+        let manga: MangaId = Uuid::new_v4().into();
+        let chapters = [
+            chapter(manga, Some("2"), "10"),
+            chapter(manga, None, "11"),
+            chapter(manga, Some("1"), "2"),
+            chapter(manga, Some("1"), "1"),
+        ];
+        for c in &chapters {
+            let json = serde_json::to_vec(c).unwrap();
+            paths::write_atomic(&dir.join(c.id.to_string()).join("chapter.json"), &json).unwrap();
+        }
+        // not fully downloaded
+        fs::create_dir_all(dir.join(Uuid::new_v4().to_string())).unwrap();
+        fs::write(dir.join("manga.json"), b"{}").unwrap();
+
+        let sorted = sort_chapters(read_chapters(&dir).unwrap(), false);
+        let numbers: Vec<_> = sorted
+            .iter()
+            .map(|(c, v)| (v.as_deref(), c.chapter.chapter.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            numbers,
+            [
+                (Some("1"), "1"),
+                (Some("1"), "2"),
+                (Some("2"), "10"),
+                (None, "11")
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
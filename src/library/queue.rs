@@ -0,0 +1,551 @@
+// Queue of chapters to download, kept in $XDG_DATA_HOME/mngdx/downloads.json so that it goes on
+// after a restart. Chapters are downloaded as tasks of the executor (a few at a time), the ones
+// that fail are tried again later, waiting longer each time.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, Result};
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::{const_mutex, Mutex};
+use serde::{Deserialize, Serialize};
+
+use super::{download_chapter, download_manga_info, resolve, Download, LibraryChapter, Progress};
+use crate::{
+    api::{
+        connectivity,
+        structs::{ids::ChapterId, json::data::RelationshipKind, lang_codes::LanguageCode, Manga},
+        Api, ApiError,
+    },
+    app::{preferences::Preferences, TaskProducer},
+    consts::DOWNLOAD_CONCURRENCY,
+    paths,
+};
+
+/// Failed downloads are given up after this many tries.
+const MAX_ATTEMPTS: u32 = 5;
+
+static QUEUE: Mutex<Option<DownloadQueue>> = const_mutex(None);
+/// No download is started before this, set when rate limited.
+static PAUSED_UNTIL: Mutex<Option<DateTime<Utc>>> = const_mutex(None);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QueueItem {
+    pub chapter: ChapterId,
+    pub data_saver: bool,
+    /// What is shown for the chapter, i.e. "Berserk - 12".
+    pub label: String,
+    pub state: ItemState,
+    pub attempts: u32,
+    /// Not tried again before this.
+    pub retry_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub progress: Progress,
+}
+
+impl QueueItem {
+    fn can_start(&self, now: DateTime<Utc>) -> bool {
+        self.state == ItemState::Queued && self.retry_at.is_none_or(|t| t <= now)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct DownloadQueue {
+    items: Vec<QueueItem>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+fn queue_path() -> PathBuf {
+    paths::data_dir().join("downloads.json")
+}
+
+impl DownloadQueue {
+    fn load(path: &Path) -> Self {
+        let mut queue: Self = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                log::error!("Couldn't read the download queue ({e})");
+                Self::default()
+            }),
+            Err(e) => {
+                if e.kind() != ErrorKind::NotFound {
+                    log::error!("Couldn't load the download queue ({e})");
+                }
+                Self::default()
+            }
+        };
+        queue.path = path.to_owned();
+        // these were stopped with the app, they are resumed (the pages they got are kept)
+        for item in &mut queue.items {
+            if item.state == ItemState::Running {
+                item.state = ItemState::Queued;
+            }
+        }
+        queue
+    }
+
+    fn save(&self) {
+        let res = (|| {
+            paths::write_atomic(&self.path, &serde_json::to_vec_pretty(self)?)?;
+            Ok::<_, Error>(())
+        })();
+        if let Err(e) = res {
+            log::error!("Couldn't save the download queue ({e})");
+        }
+    }
+
+    fn get_mut(&mut self, chapter: &ChapterId) -> Option<&mut QueueItem> {
+        self.items.iter_mut().find(|i| i.chapter == *chapter)
+    }
+
+    /// Add new items, see enqueue. Returns how many were added.
+    fn add(&mut self, items: Vec<QueueItem>) -> usize {
+        let mut added = 0;
+        for item in items {
+            match self.get_mut(&item.chapter) {
+                Some(old) if old.state == ItemState::Running => {}
+                Some(old)
+                    if old.state == ItemState::Failed || old.data_saver != item.data_saver =>
+                {
+                    *old = item;
+                    added += 1;
+                }
+                Some(_) => {}
+                None => {
+                    self.items.push(item);
+                    added += 1;
+                }
+            }
+        }
+        self.save();
+        added
+    }
+
+    /// Record how the download of a chapter went.
+    fn finish(&mut self, chapter: ChapterId, res: Result<LibraryChapter>) {
+        let item = match self.get_mut(&chapter) {
+            Some(item) => item,
+            // removed while it was downloaded
+            None => return,
+        };
+        match res {
+            Ok(_) => {
+                item.state = ItemState::Done;
+                item.retry_at = None;
+                item.error = None;
+            }
+            Err(e) => {
+                log::warn!("Couldn't download {} ({e})", item.label);
+                // being offline or rate limited isn't the fault of the chapter, it doesn't count
+                let retry_in = match e.downcast_ref::<ApiError>() {
+                    Some(ApiError::RateLimit(d)) => {
+                        let d = Duration::from_std(*d).unwrap_or_else(|_| Duration::seconds(60));
+                        *PAUSED_UNTIL.lock() = Some(Utc::now() + d);
+                        Some(d)
+                    }
+                    _ if !connectivity::is_online() => {
+                        Duration::from_std(connectivity::RETRY_INTERVAL).ok()
+                    }
+                    _ => {
+                        item.attempts += 1;
+                        (item.attempts < MAX_ATTEMPTS).then(|| backoff(item.attempts))
+                    }
+                };
+                item.error = Some(e.to_string());
+                match retry_in {
+                    Some(d) => {
+                        item.state = ItemState::Queued;
+                        item.retry_at = Some(Utc::now() + d);
+                    }
+                    None => item.state = ItemState::Failed,
+                }
+            }
+        }
+        self.save();
+    }
+}
+
+fn with_queue<R>(f: impl FnOnce(&mut DownloadQueue) -> R) -> R {
+    let mut queue = QUEUE.lock();
+    f(queue.get_or_insert_with(|| DownloadQueue::load(&queue_path())))
+}
+
+/// Everything in the queue, in order.
+pub fn items() -> Vec<QueueItem> {
+    with_queue(|q| q.items.clone())
+}
+
+/// What to show for a chapter.
+async fn label(api: &mut Api, id: ChapterId, languages: &[LanguageCode]) -> String {
+    let number = match api.chapter_view(id).await {
+        Ok(c) => c.chapter.unwrap_or_else(|| "oneshot".to_owned()),
+        Err(_) => return id.to_string(),
+    };
+    let manga = api
+        .cache()
+        .get_linked(&id, RelationshipKind::Manga)
+        .and_then(|m| m.first().copied());
+    let title = manga
+        .and_then(|m| api.cache().get::<Manga>(&m))
+        .map(|m| m.title_in(languages));
+    match title {
+        Some(title) => format!("{title} - {number}"),
+        None => format!("{id} - {number}"),
+    }
+}
+
+/// Add the chapters of a download to the queue, returns how many were added. Chapters that are
+/// already in it are only queued again if they failed, or are wanted in another quality.
+pub async fn enqueue(api: &mut Api, download: &Download, data_saver: bool) -> Result<usize> {
+    let ids = resolve(api, download).await?;
+    if let Download::Volume { manga, .. } | Download::Manga { id: manga } = download {
+        // keep the metadata up to date
+        if let Err(e) = download_manga_info(api, *manga).await {
+            log::warn!("Couldn't update the metadata of {manga} ({e})");
+        }
+    }
+    let languages = Preferences::load_or_default().languages;
+    let mut items = Vec::with_capacity(ids.len());
+    for id in ids {
+        items.push(QueueItem {
+            chapter: id,
+            data_saver,
+            label: label(api, id, &languages).await,
+            state: ItemState::Queued,
+            attempts: 0,
+            retry_at: None,
+            error: None,
+            progress: Progress::default(),
+        });
+    }
+
+    Ok(with_queue(|q| q.add(items)))
+}
+
+/// Queue the failed downloads again.
+pub fn retry_failed() -> usize {
+    with_queue(|q| {
+        let mut n = 0;
+        for item in q.items.iter_mut().filter(|i| i.state == ItemState::Failed) {
+            item.state = ItemState::Queued;
+            item.attempts = 0;
+            item.retry_at = None;
+            n += 1;
+        }
+        q.save();
+        n
+    })
+}
+
+/// Remove the finished downloads from the queue.
+pub fn clear_done() {
+    with_queue(|q| {
+        q.items.retain(|i| i.state != ItemState::Done);
+        q.save();
+    })
+}
+
+/// Take the next download that can be started, it's marked as running.
+fn next_item() -> Option<(ChapterId, bool)> {
+    let now = Utc::now();
+    if PAUSED_UNTIL.lock().is_some_and(|t| t > now) {
+        return None;
+    }
+    with_queue(|q| {
+        let running = q
+            .items
+            .iter()
+            .filter(|i| i.state == ItemState::Running)
+            .count();
+        if running >= DOWNLOAD_CONCURRENCY {
+            return None;
+        }
+        let item = q.items.iter_mut().find(|i| i.can_start(now))?;
+        item.state = ItemState::Running;
+        item.progress = Progress::default();
+        let next = (item.chapter, item.data_saver);
+        q.save();
+        Some(next)
+    })
+}
+
+/// How long to wait before trying again after a failure.
+fn backoff(attempts: u32) -> Duration {
+    let secs = 10 * 2i64.pow(attempts.saturating_sub(1).min(6));
+    Duration::seconds(secs).min(Duration::minutes(10))
+}
+
+fn finish(chapter: ChapterId, res: Result<LibraryChapter>) {
+    with_queue(|q| q.finish(chapter, res))
+}
+
+/// Held while a chapter is downloaded. If the download stops without finishing (its task
+/// panicked), it counts as a failed attempt, so that it isn't left running and blocking the queue.
+struct RunningGuard {
+    chapter: ChapterId,
+    finished: bool,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            finish(
+                self.chapter,
+                Err(Error::msg("the download stopped unexpectedly")),
+            );
+        }
+    }
+}
+
+async fn process(chapter: ChapterId, data_saver: bool) {
+    let mut guard = RunningGuard {
+        chapter,
+        finished: false,
+    };
+    let mut api = Api::new();
+    let res = download_chapter(&mut api, chapter, data_saver, |p| {
+        with_queue(|q| {
+            if let Some(item) = q.get_mut(&chapter) {
+                item.progress = p;
+            }
+        })
+    })
+    .await;
+    guard.finished = true;
+    finish(chapter, res);
+}
+
+/// Start the downloads that can be, as tasks of the producer. This has to be called regularly,
+/// for the retries to be started.
+pub fn schedule(producer: &mut TaskProducer) {
+    while let Some((chapter, data_saver)) = next_item() {
+        let mut next = producer.clone();
+        let res = producer.schedule(async move {
+            process(chapter, data_saver).await;
+            // start the next one right away
+            schedule(&mut next);
+        });
+        if let Err(e) = res {
+            log::error!("Couldn't start a download ({e})");
+            with_queue(|q| {
+                if let Some(item) = q.get_mut(&chapter) {
+                    item.state = ItemState::Queued;
+                }
+            });
+            break;
+        }
+    }
+}
+
+/// Download everything in the queue, one chapter at a time, until there is nothing left to try.
+/// report is called after each chapter.
+pub async fn run(mut report: impl FnMut(&QueueItem)) {
+    loop {
+        if let Some((chapter, data_saver)) = next_item() {
+            process(chapter, data_saver).await;
+            if let Some(item) = with_queue(|q| q.get_mut(&chapter).cloned()) {
+                report(&item);
+            }
+            continue;
+        }
+
+        let waiting = with_queue(|q| {
+            q.items
+                .iter()
+                .filter(|i| i.state == ItemState::Queued)
+                .filter_map(|i| i.retry_at)
+                .min()
+        });
+        let paused = *PAUSED_UNTIL.lock();
+        match waiting.max(paused) {
+            Some(t) if t > Utc::now() => {
+                let wait = (t - Utc::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+            }
+            // nothing is queued, or the rest is run by someone else
+            _ => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::api::connectivity::{set_online, TEST_STATUS};
+
+    fn temp_queue(items: Vec<QueueItem>) -> DownloadQueue {
+        DownloadQueue {
+            items,
+            path: std::env::temp_dir().join(format!("mngdx-test-queue-{}.json", Uuid::new_v4())),
+        }
+    }
+
+    fn item(state: ItemState, data_saver: bool) -> QueueItem {
+        QueueItem {
+            chapter: Uuid::new_v4().into(),
+            data_saver,
+            label: "Berserk - 12".to_owned(),
+            state,
+            attempts: 0,
+            retry_at: None,
+            error: None,
+            progress: Progress::default(),
+        }
+    }
+
+    fn failure() -> Result<LibraryChapter> {
+        Err(ApiError::Them.into())
+    }
+
+    #[test]
+    fn backoff_grows_up_to_ten_minutes() {
+        assert_eq!(backoff(0), Duration::seconds(10));
+        assert_eq!(backoff(1), Duration::seconds(10));
+        assert_eq!(backoff(2), Duration::seconds(20));
+        assert_eq!(backoff(3), Duration::seconds(40));
+        assert_eq!(backoff(6), Duration::seconds(320));
+        assert_eq!(backoff(7), Duration::minutes(10));
+        assert_eq!(backoff(u32::MAX), Duration::minutes(10));
+    }
+
+    #[test]
+    fn failures_are_retried_then_given_up() {
+        let _status = TEST_STATUS.lock();
+        set_online(true);
+        let mut q = temp_queue(vec![item(ItemState::Running, false)]);
+        let chapter = q.items[0].chapter;
+
+        for attempt in 1..MAX_ATTEMPTS {
+            q.finish(chapter, failure());
+            let item = &q.items[0];
+            assert_eq!((item.state, item.attempts), (ItemState::Queued, attempt));
+            assert!(item.retry_at.unwrap() > Utc::now());
+            assert!(item.error.is_some());
+        }
+        q.finish(chapter, failure());
+        assert_eq!(q.items[0].state, ItemState::Failed);
+        assert_eq!(q.items[0].attempts, MAX_ATTEMPTS);
+
+        fs::remove_file(&q.path).unwrap();
+    }
+
+    #[test]
+    fn offline_and_rate_limits_dont_count() {
+        let _status = TEST_STATUS.lock();
+        let mut q = temp_queue(vec![item(ItemState::Running, false)]);
+        let chapter = q.items[0].chapter;
+
+        set_online(false);
+        q.finish(chapter, failure());
+        set_online(true);
+        assert_eq!(
+            (q.items[0].state, q.items[0].attempts),
+            (ItemState::Queued, 0)
+        );
+
+        q.finish(
+            chapter,
+            Err(ApiError::RateLimit(std::time::Duration::from_secs(120)).into()),
+        );
+        assert_eq!(
+            (q.items[0].state, q.items[0].attempts),
+            (ItemState::Queued, 0)
+        );
+        let paused = PAUSED_UNTIL.lock().take().unwrap();
+        assert!(paused > Utc::now() + Duration::seconds(100));
+        assert!(q.items[0].retry_at.unwrap() > Utc::now() + Duration::seconds(100));
+
+        fs::remove_file(&q.path).unwrap();
+    }
+
+    #[test]
+    fn only_failed_or_other_qualities_are_queued_again() {
+        let mut q = temp_queue(vec![
+            item(ItemState::Failed, false),
+            item(ItemState::Done, false),
+            item(ItemState::Done, false),
+            item(ItemState::Running, false),
+        ]);
+        q.items[0].attempts = MAX_ATTEMPTS;
+        let again = |i: &QueueItem, data_saver| QueueItem {
+            chapter: i.chapter,
+            ..item(ItemState::Queued, data_saver)
+        };
+
+        let items = vec![
+            again(&q.items[0], false),
+            again(&q.items[1], false),
+            again(&q.items[2], true),
+            again(&q.items[3], true),
+            item(ItemState::Queued, false),
+        ];
+        assert_eq!(q.add(items), 3);
+        let states: Vec<_> = q.items.iter().map(|i| (i.state, i.data_saver)).collect();
+        assert_eq!(
+            states,
+            [
+                (ItemState::Queued, false),
+                (ItemState::Done, false),
+                (ItemState::Queued, true),
+                (ItemState::Running, false),
+                (ItemState::Queued, false),
+            ]
+        );
+        assert_eq!(q.items[0].attempts, 0);
+
+        fs::remove_file(&q.path).unwrap();
+    }
+
+    #[test]
+    fn running_downloads_are_resumed() {
+        let q = temp_queue(vec![
+            item(ItemState::Running, false),
+            item(ItemState::Done, false),
+        ]);
+        q.save();
+        let loaded = DownloadQueue::load(&q.path);
+        let states: Vec<_> = loaded.items.iter().map(|i| i.state).collect();
+        assert_eq!(states, [ItemState::Queued, ItemState::Done]);
+
+        fs::remove_file(&q.path).unwrap();
+    }
+
+    #[test]
+    fn panicked_downloads_dont_stay_running() {
+        let _status = TEST_STATUS.lock();
+        set_online(true);
+        let q = temp_queue(vec![item(ItemState::Running, false)]);
+        let (chapter, path) = (q.items[0].chapter, q.path.clone());
+        *QUEUE.lock() = Some(q);
+
+        let res = std::panic::catch_unwind(|| {
+            let _guard = RunningGuard {
+                chapter,
+                finished: false,
+            };
+            panic!("in the download");
+        });
+        assert!(res.is_err());
+        let item = with_queue(|q| q.get_mut(&chapter).cloned()).unwrap();
+        assert_eq!((item.state, item.attempts), (ItemState::Queued, 1));
+
+        *QUEUE.lock() = None;
+        fs::remove_file(path).unwrap();
+    }
+}
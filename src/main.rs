@@ -5,8 +5,12 @@
 
 mod api;
 mod app;
+mod cli;
 pub mod consts;
+mod export;
 mod images;
+mod library;
+mod paths;
 
 use std::{io, os::unix::prelude::AsRawFd};
 #[cfg(feature = "set_padding")]
@@ -20,10 +24,20 @@ use crossterm::{
 };
 use tui::{backend::CrosstermBackend, Terminal};
 
-use crate::app::App;
+use crate::app::{preferences::Preferences, App};
 
 fn main() -> Result<()> {
     pretty_env_logger::init();
+    let preferences = Preferences::load_or_default();
+    api::cache::set_memory_budget(preferences.cache_memory_mb * 1024 * 1024);
+    images::cache::set_limit(preferences.image_cache_mb * 1024 * 1024);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(res) = cli::run(&args) {
+        images::cache::flush();
+        api::cache::flush();
+        return res;
+    }
 
     let mut stdout = io::stdout();
 
@@ -44,6 +58,8 @@ fn main() -> Result<()> {
     let mut app = App::new(terminal, fd);
 
     app.run();
+    images::cache::flush();
+    api::cache::flush();
 
     // get terminal from app to reset back to normal
     let terminal = &mut app.get_terminal();
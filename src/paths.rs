@@ -0,0 +1,52 @@
+// Where mngdx keeps its files, following the XDG base directories (through the dirs crate), and
+// how they are written.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::consts::APP_NAME;
+
+// If the directory can't be found (no $HOME ?), fallback to the current directory.
+fn or_current(dir: Option<PathBuf>) -> PathBuf {
+    dir.unwrap_or_else(|| PathBuf::from(".")).join(APP_NAME)
+}
+
+/// $XDG_CONFIG_HOME/mngdx
+pub fn config_dir() -> PathBuf {
+    or_current(dirs::config_dir())
+}
+
+/// $XDG_CACHE_HOME/mngdx
+pub fn cache_dir() -> PathBuf {
+    or_current(dirs::cache_dir())
+}
+
+/// $XDG_DATA_HOME/mngdx
+pub fn data_dir() -> PathBuf {
+    or_current(dirs::data_dir())
+}
+
+/// A path to write to before renaming it to path, unique so that two writes of the same file
+/// don't mix.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{}.tmp", Uuid::new_v4()));
+    path.with_file_name(name)
+}
+
+/// Write a file through a temporary one, so that it's never half written.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = temp_path(path);
+    if let Err(e) = fs::write(&tmp, bytes).and_then(|_| fs::rename(&tmp, path)) {
+        let _ = fs::remove_file(tmp);
+        return Err(e);
+    }
+    Ok(())
+}